@@ -1,44 +1,996 @@
-use std::io::stdout;
-use std::process::Command;
-use std::collections::HashMap;
+use std::io::{stdout, Stdout};
+use std::process::{Command, Stdio};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
 use std::path::PathBuf;
 use std::fs;
-use crossterm::event::{self, Event, KeyCode};
-use crossterm::terminal::{enable_raw_mode, disable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, sync_channel, Receiver, TryRecvError};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventState, KeyModifiers, EnableBracketedPaste, DisableBracketedPaste};
+use crossterm::event::{KeyboardEnhancementFlags, PushKeyboardEnhancementFlags, PopKeyboardEnhancementFlags};
+use crossterm::terminal::{enable_raw_mode, disable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen, SetTitle, supports_keyboard_enhancement};
+use crossterm::style::Print;
+use crossterm::cursor::MoveTo;
 use crossterm::execute;
 use ratatui::backend::CrosstermBackend;
 use ratatui::Terminal;
-use ratatui::layout::{Layout, Constraint, Direction};
-use ratatui::widgets::{Block, Borders, List, ListItem, Paragraph, ListState, Clear};
+use ratatui::layout::{Layout, Constraint, Direction, Rect, Alignment};
+use ratatui::widgets::{Block, Borders, List, ListItem, Paragraph, ListState, Clear, Wrap, Tabs, BarChart, Sparkline};
 use ratatui::style::{Style, Color, Modifier};
+use ratatui::text::{Span, Spans};
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 struct Alias {
     name: String,
     command: String,
-    keybind: Option<char>,
+    keybind: Option<KeyBind>,
+    container: Option<String>,
+    kind: AliasKind,
+    run_in: RunIn,
+    // overrides the global `pause`
+    pause: Option<String>,
+    after: Option<After>,
+    // settings for individual `{{name}}` parameters
+    params: HashMap<String, ParamSpec>,
+    // values of secret parameters for this run, kept out of `command` so they never get stored
+    secret_values: HashMap<String, String>,
+    // first word of the command didn't resolve on PATH last time we looked
+    missing: bool,
+    // comes from the repository's .git/tuish.json rather than the user config
+    repo: bool,
+    // name of the subscribed bundle it comes from, those are read-only
+    bundle: Option<String>,
+    // run it in the output pane this often while tuish is open, like "15m"
+    every: Option<String>,
+    // working directory to run it in, instead of wherever tuish was started
+    cwd: Option<String>,
+    // its keybind only works once this group is opened, see ConfigFile::groups
+    group: Option<String>,
 }
 
-#[derive(Serialize, Deserialize)]
+// An alias row as drawn, kept from frame to frame and only made again when something it shows
+// changes. The number in front depends on the scrolling, so that's added each time
+struct AliasRow {
+    name: String,
+    command: String,
+    keybind: Option<KeyBind>,
+    group: Option<String>,
+    container: Option<String>,
+    kind: AliasKind,
+    repo: bool,
+    bundle: Option<String>,
+    missing: bool,
+    // repo/bundle/dangerous/missing marks
+    badges: Vec<(String, Style)>,
+    // name, keybind and container, what the inline editor keeps
+    label: String,
+    line: String,
+}
+
+impl AliasRow {
+    fn new(a: &Alias, danger_patterns: &[String]) -> AliasRow {
+        let mut badges = Vec::new();
+        if a.repo { badges.push(("repo ".to_string(), Style::default().fg(theme().ok))); }
+        if let Some(b) = &a.bundle { badges.push((format!("{} ", b), Style::default().fg(theme().bundle))); }
+        if a.kind == AliasKind::Command && is_dangerous(&a.command, danger_patterns) {
+            badges.push((glyph("!!", "dangerous").to_string(), Style::default().fg(theme().text).bg(theme().error).add_modifier(Modifier::BOLD)));
+            badges.push((" ".to_string(), Style::default()));
+        }
+        if a.missing { badges.push((glyph("! ", "missing ").to_string(), Style::default().fg(theme().error).add_modifier(Modifier::BOLD))); }
+        let kb = match keybind_label(a) { Some(k) => format!(" [{}]", k), None => "".into() };
+        let ctr = match &a.container { Some(c) => format!(" @{}", c), None => "".into() };
+        let cmd = if a.kind == AliasKind::Cd { format!("cd {}", a.command) } else { one_line(&a.command).into_owned() };
+        let label = format!("{}{}{}", a.name, kb, ctr);
+        AliasRow {
+            name: a.name.clone(), command: a.command.clone(), keybind: a.keybind, group: a.group.clone(), container: a.container.clone(),
+            kind: a.kind, repo: a.repo, bundle: a.bundle.clone(), missing: a.missing,
+            badges, line: format!("{} - {}", label, cmd), label,
+        }
+    }
+
+    fn shows(&self, a: &Alias) -> bool {
+        self.name == a.name && self.command == a.command && self.keybind == a.keybind && self.group == a.group && self.container == a.container
+            && self.kind == a.kind && self.repo == a.repo && self.bundle == a.bundle && self.missing == a.missing
+    }
+}
+
+// Rows by alias name, made with these danger patterns
+#[derive(Default)]
+struct RowCache {
+    patterns: Vec<String>,
+    rows: HashMap<String, AliasRow>,
+}
+
+// How an alias's keybind is shown, with its group in front
+fn keybind_label(a: &Alias) -> Option<String> {
+    let k = a.keybind?;
+    Some(match &a.group { Some(g) => format!("{}: {}", g, k), None => k.to_string() })
+}
+
+// Where a command's output goes: the real terminal (so interactive programs work), captured
+// into tuish's output pane while the menu stays up, or a new terminal window of its own
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum RunIn {
+    #[default]
+    Terminal,
+    Pane,
+    Window,
+}
+
+impl RunIn {
+    fn is_terminal(&self) -> bool { *self == RunIn::Terminal }
+}
+
+// Extra settings for one `{{name}}` parameter of an alias, under `params` in its config entry
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct ParamSpec {
+    // typed in masked and never written to the history or anywhere else
+    #[serde(default)]
+    secret: bool,
+    // the only values allowed, picked from a list instead of typed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    choices: Vec<String>,
+    // or a command whose output lines are the choices, run each time the form opens
+    #[serde(rename = "choices-command", default, skip_serializing_if = "Option::is_none")]
+    choices_command: Option<String>,
+    // filled in already when the form opens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    default: Option<String>,
+    // a regex the whole value has to match
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pattern: Option<String>,
+    // may be left empty
+    #[serde(default)]
+    optional: bool,
+}
+
+impl ParamSpec {
+    fn is_choice(&self) -> bool { !self.choices.is_empty() || self.choices_command.is_some() }
+}
+
+// Where to go once an alias finishes. Unset means back to the menu for terminal runs and
+// staying in the output pane for pane runs
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum After {
+    Menu,
+    Output,
+    Rerun,
+    Quit,
+}
+
+impl After {
+    fn for_alias(alias: &Alias) -> After {
+        alias.after.unwrap_or(match alias.run_in {
+            RunIn::Pane => After::Output,
+            // a popup is there to launch one thing and get out of the way
+            _ if popup() => After::Quit,
+            _ => After::Menu,
+        })
+    }
+}
+
+// What happens once a command run in the terminal exits, from the `pause` setting:
+// "always", "never", "on-error" or a number of seconds to wait before going back by itself
+#[derive(Clone, Copy, PartialEq)]
+enum Pause {
+    Always,
+    Never,
+    OnError,
+    Seconds(u64),
+}
+
+impl Pause {
+    // anything unrecognised keeps the old behaviour of waiting for a key
+    fn parse(s: &str) -> Pause {
+        match s.trim() {
+            "never" => Pause::Never,
+            "on-error" => Pause::OnError,
+            other => other.trim_end_matches('s').parse().map(Pause::Seconds).unwrap_or(Pause::Always),
+        }
+    }
+
+    // keeping the output open means not leaving it before a key is pressed
+    fn for_alias(alias: &Alias, global: &str) -> Pause {
+        if alias.after == Some(After::Output) { return Pause::Always; }
+        Pause::parse(alias.pause.as_deref().unwrap_or(global))
+    }
+}
+
+// A bookmark alias keeps its directory in `command`
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum AliasKind {
+    #[default]
+    Command,
+    Cd,
+}
+
+impl AliasKind {
+    fn is_command(&self) -> bool { *self == AliasKind::Command }
+}
+
+// One key with optional ctrl/alt, written as "d", "ctrl+d", "alt+shift+x", "f5" or "kp7" in the config.
+// Shift on a letter is just the uppercase letter, so "shift+x" and "X" are the same key
+#[derive(Clone, Copy, PartialEq)]
+struct KeyPress {
+    code: KeyCode,
+    mods: KeyModifiers,
+    // on the numeric keypad, only reported by terminals with the kitty keyboard protocol
+    keypad: bool,
+}
+
+// Names for keys that aren't a single character
+const NAMED_KEYS: [(&str, KeyCode); 8] = [
+    ("insert", KeyCode::Insert), ("delete", KeyCode::Delete), ("home", KeyCode::Home), ("end", KeyCode::End),
+    ("pageup", KeyCode::PageUp), ("pagedown", KeyCode::PageDown), ("enter", KeyCode::Enter), ("space", KeyCode::Char(' ')),
+];
+
+impl KeyPress {
+    fn new(code: KeyCode, mut mods: KeyModifiers) -> KeyPress {
+        mods &= KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT;
+        match code {
+            KeyCode::Char(c) if mods.contains(KeyModifiers::SHIFT) => {
+                mods.remove(KeyModifiers::SHIFT);
+                KeyPress { code: KeyCode::Char(c.to_ascii_uppercase()), mods, keypad: false }
+            }
+            _ => KeyPress { code, mods, keypad: false },
+        }
+    }
+
+    fn key_name(name: &str) -> Option<(KeyCode, bool)> {
+        let lower = name.to_lowercase();
+        if let Some(rest) = lower.strip_prefix("kp") {
+            // kp0-kp9, kp+, kp. and so on, plus kpenter
+            return match rest {
+                "enter" => Some((KeyCode::Enter, true)),
+                _ if rest.chars().count() == 1 => Some((KeyCode::Char(rest.chars().next()?), true)),
+                _ => None,
+            };
+        }
+        if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+            return if (1..=12).contains(&n) { Some((KeyCode::F(n), false)) } else { None };
+        }
+        let alias = match lower.as_str() { "ins" => "insert", "del" => "delete", "pgup" => "pageup", "pgdn" => "pagedown", other => other };
+        if let Some((_, code)) = NAMED_KEYS.iter().find(|(n, _)| *n == alias) { return Some((*code, false)); }
+        let mut chars = name.chars();
+        let c = chars.next()?;
+        if chars.next().is_some() { return None; }
+        Some((KeyCode::Char(c), false))
+    }
+
+    fn parse(s: &str) -> Option<KeyPress> {
+        // split on the `+`s between parts, a trailing one is the key itself ("ctrl++", "kp+")
+        let mut prefix = Vec::new();
+        let mut key = s;
+        while let Some(i) = key.find('+') {
+            if i + 1 == key.len() { break; }
+            prefix.push(&key[..i]);
+            key = &key[i + 1..];
+        }
+        let mut mods = KeyModifiers::NONE;
+        for m in prefix {
+            match m.to_lowercase().as_str() {
+                "ctrl" | "control" | "c" => mods.insert(KeyModifiers::CONTROL),
+                "alt" | "meta" | "m" => mods.insert(KeyModifiers::ALT),
+                "shift" | "s" => mods.insert(KeyModifiers::SHIFT),
+                _ => return None,
+            }
+        }
+        let (code, keypad) = KeyPress::key_name(key)?;
+        Some(KeyPress { keypad, ..KeyPress::new(code, mods) })
+    }
+
+    fn from_event(key: &KeyEvent) -> KeyPress {
+        KeyPress { keypad: key.state.contains(KeyEventState::KEYPAD), ..KeyPress::new(key.code, key.modifiers) }
+    }
+
+    fn matches(&self, key: &KeyEvent) -> bool {
+        KeyPress::from_event(key) == *self
+    }
+
+    // can't be confused with typing or moving around, so fine to catch anywhere
+    fn is_global(&self) -> bool {
+        self.keypad || self.mods.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) || !matches!(self.code, KeyCode::Char(_) | KeyCode::Enter)
+    }
+}
+
+impl std::fmt::Display for KeyPress {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.mods.contains(KeyModifiers::CONTROL) { write!(f, "ctrl+")?; }
+        if self.mods.contains(KeyModifiers::ALT) { write!(f, "alt+")?; }
+        if self.mods.contains(KeyModifiers::SHIFT) { write!(f, "shift+")?; }
+        if self.keypad { write!(f, "kp")?; }
+        match self.code {
+            KeyCode::F(n) => write!(f, "f{}", n),
+            KeyCode::Char(' ') if !self.keypad => write!(f, "space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            code => write!(f, "{}", NAMED_KEYS.iter().find(|(_, k)| *k == code).map(|(n, _)| *n).unwrap_or("?")),
+        }
+    }
+}
+
+// An alias keybind: a single key, or a leader key and then a second one, written "g p"
+#[derive(Clone, Copy, PartialEq)]
+struct KeyBind {
+    leader: Option<KeyPress>,
+    key: KeyPress,
+}
+
+impl KeyBind {
+    fn parse(s: &str) -> Option<KeyBind> {
+        let keys: Vec<&str> = s.split_whitespace().collect();
+        match keys.as_slice() {
+            [key] => Some(KeyBind { leader: None, key: KeyPress::parse(key)? }),
+            [leader, key] => Some(KeyBind { leader: Some(KeyPress::parse(leader)?), key: KeyPress::parse(key)? }),
+            _ => None,
+        }
+    }
+
+    // single keys only, the second key of a chord is checked against the pending leader
+    fn matches(&self, key: &KeyEvent) -> bool {
+        self.leader.is_none() && self.key.matches(key)
+    }
+
+    fn starts_with(&self, key: &KeyEvent) -> bool {
+        self.leader.is_some_and(|l| l.matches(key))
+    }
+
+    // bare letters only fire from the Actions list, ctrl/alt, F-keys and such work anywhere on the main screen
+    fn is_global(&self) -> bool {
+        self.leader.unwrap_or(self.key).is_global()
+    }
+}
+
+// Keybind steps of the add/edit flows: a key sets it, a second one after a single key makes
+// a chord (a third starts over) and Backspace clears it
+fn next_keybind(current: Option<KeyBind>, key: &KeyEvent) -> Option<KeyBind> {
+    let press = KeyPress::from_event(key);
+    match key.code {
+        KeyCode::Backspace => None,
+        KeyCode::Char(_) | KeyCode::F(_) | KeyCode::Insert | KeyCode::Delete | KeyCode::Home | KeyCode::End | KeyCode::PageUp | KeyCode::PageDown => {
+            match current {
+                Some(KeyBind { leader: None, key: first }) => Some(KeyBind { leader: Some(first), key: press }),
+                _ => Some(KeyBind { leader: None, key: press }),
+            }
+        }
+        _ => current,
+    }
+}
+
+// Alias (other than the one at `skip`) whose keybind clashes with `kb`: the same keys, or a single
+// key that's also the leader of a chord, which would make one of them unreachable. Only aliases
+// in the same `group` count, each group has keys of its own
+fn keybind_conflict<'a>(aliases: &'a [Alias], kb: KeyBind, group: Option<&str>, skip: usize) -> Option<&'a Alias> {
+    aliases.iter().enumerate().filter(|(i, a)| *i != skip && a.group.as_deref() == group).map(|(_, a)| a).find(|a| a.keybind.is_some_and(|o| {
+        o == kb || (o.leader.is_none() && kb.leader == Some(o.key)) || (kb.leader.is_none() && o.leader == Some(kb.key))
+    }))
+}
+
+// Why `kb` can't go on an alias in `group` (skipping aliases[skip], the one being edited): another
+// alias has it, or on the main screen it's a group's key and would never get through
+fn keybind_problem(aliases: &[Alias], groups: &HashMap<String, String>, kb: KeyBind, group: Option<&str>, skip: usize) -> Option<String> {
+    if let Some(other) = keybind_conflict(aliases, kb, group, skip) {
+        return Some(trf("Clashes with '{}' [{}]", &[&other.name, &other.keybind.map(|k| k.to_string()).unwrap_or_default()]));
+    }
+    let g = groups.iter().filter(|_| group.is_none()).find(|(_, k)| KeyPress::parse(k) == Some(kb.leader.unwrap_or(kb.key))).map(|(g, _)| g)?;
+    Some(trf("That key opens the '{}' group", &[g]))
+}
+
+// The alias a keybind runs with `group` open (None for the main screen). Repository aliases win
+// over the user's own and those over bundles, whatever order the list is sorted in
+fn keybind_target<'a>(aliases: &'a [Alias], group: Option<&str>, hit: impl Fn(&KeyBind) -> bool) -> Option<&'a Alias> {
+    aliases.iter().filter(|a| a.group.as_deref() == group && a.keybind.is_some_and(|k| hit(&k)))
+        .min_by_key(|a| if a.repo { 0 } else if a.bundle.is_none() { 1 } else { 2 })
+}
+
+// How the alias list is ordered, `S` in the Aliases section goes through them
+#[derive(Clone, Copy, PartialEq)]
+enum AliasSort {
+    // the order they were loaded in
+    Config,
+    Name,
+    // most recently run first, from state.json
+    LastRun,
+}
+
+impl AliasSort {
+    fn next(self) -> AliasSort {
+        match self { AliasSort::Config => AliasSort::LastRun, AliasSort::LastRun => AliasSort::Name, AliasSort::Name => AliasSort::Config }
+    }
+
+    fn label(self) -> Option<&'static str> {
+        match self { AliasSort::Config => None, AliasSort::Name => Some(tr("by name")), AliasSort::LastRun => Some(tr("by last run")) }
+    }
+}
+
+// `loaded` is the names in load order, for going back to it; aliases added since go last
+fn sort_aliases(aliases: &mut [Alias], sort: AliasSort, usage: &HashMap<String, Usage>, loaded: &[String]) {
+    match sort {
+        AliasSort::Config => aliases.sort_by_key(|a| loaded.iter().position(|n| *n == a.name).unwrap_or(usize::MAX)),
+        AliasSort::Name => aliases.sort_by_key(|a| a.name.to_lowercase()),
+        AliasSort::LastRun => aliases.sort_by_key(|a| std::cmp::Reverse(usage.get(&a.name).map(|u| u.last_run).unwrap_or(0))),
+    }
+}
+
+// The search bar's toggles
+#[derive(Clone, Copy, Default)]
+struct SearchOptions {
+    regex: bool,
+    scope: SearchScope,
+    case_sensitive: bool,
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+enum SearchScope {
+    #[default]
+    Both,
+    Names,
+    Commands,
+}
+
+impl SearchScope {
+    fn next(self) -> SearchScope {
+        match self { SearchScope::Both => SearchScope::Names, SearchScope::Names => SearchScope::Commands, SearchScope::Commands => SearchScope::Both }
+    }
+}
+
+impl SearchOptions {
+    // "fuzzy, names and commands, any case", for the search bar
+    fn describe(&self) -> String {
+        let mode = if self.regex { tr("regex") } else { tr("fuzzy") };
+        let scope = match self.scope { SearchScope::Both => tr("names and commands"), SearchScope::Names => tr("names"), SearchScope::Commands => tr("commands") };
+        let case = if self.case_sensitive { tr("match case") } else { tr("any case") };
+        format!("{}, {}, {}", mode, scope, case)
+    }
+}
+
+// fzf's scoring: every matched char scores, gaps cost (the first skipped char more than the rest),
+// and matches at the start of a word or right after the previous match get a bonus
+const SCORE_MATCH: i64 = 16;
+const SCORE_GAP_START: i64 = -3;
+const SCORE_GAP_EXTENSION: i64 = -1;
+const BONUS_BOUNDARY: i64 = SCORE_MATCH / 2;
+const BONUS_BOUNDARY_WHITE: i64 = BONUS_BOUNDARY + 2;
+const BONUS_BOUNDARY_DELIMITER: i64 = BONUS_BOUNDARY + 1;
+const BONUS_CAMEL: i64 = BONUS_BOUNDARY + SCORE_GAP_EXTENSION;
+const BONUS_CONSECUTIVE: i64 = -(SCORE_GAP_START + SCORE_GAP_EXTENSION);
+const BONUS_FIRST_CHAR_MULTIPLIER: i64 = 2;
+
+#[derive(Clone, Copy, PartialEq)]
+enum CharClass { White, Delimiter, NonWord, Lower, Upper, Number }
+
+fn char_class(c: char) -> CharClass {
+    if c.is_whitespace() { CharClass::White }
+    else if "/,:;|-_.=".contains(c) { CharClass::Delimiter }
+    else if c.is_lowercase() { CharClass::Lower }
+    else if c.is_uppercase() { CharClass::Upper }
+    else if c.is_numeric() { CharClass::Number }
+    else if c.is_alphabetic() { CharClass::Lower }
+    else { CharClass::NonWord }
+}
+
+// What a match at a char is worth on top of SCORE_MATCH, from the char before it
+fn position_bonus(prev: CharClass, class: CharClass) -> i64 {
+    use CharClass::*;
+    match (prev, class) {
+        (_, White | Delimiter | NonWord) => BONUS_BOUNDARY,
+        (White, _) => BONUS_BOUNDARY_WHITE,
+        (Delimiter, _) => BONUS_BOUNDARY_DELIMITER,
+        (NonWord, _) => BONUS_BOUNDARY,
+        (Lower, Upper) | (Lower | Upper, Number) => BONUS_CAMEL,
+        _ => 0,
+    }
+}
+
+// How well `pattern` matches `text` as a subsequence; None when it doesn't. The best placement
+// is found with fzf's dynamic programming: for each pattern char and each position it could
+// match at, the best score so far, carrying the bonus a run of consecutive matches started with
+fn fuzzy_score(pattern: &str, text: &str, case_sensitive: bool) -> Option<i64> {
+    let fold = |c: char| if case_sensitive { c } else { c.to_lowercase().next().unwrap_or(c) };
+    let pattern: Vec<char> = pattern.chars().map(fold).collect();
+    let original: Vec<char> = text.chars().collect();
+    let text: Vec<char> = original.iter().map(|c| fold(*c)).collect();
+    if pattern.is_empty() { return Some(0); }
+    // quick no before the real work
+    let mut rest = text.iter();
+    if !pattern.iter().all(|p| rest.any(|c| c == p)) { return None; }
+
+    let mut prev = CharClass::White;
+    let bonus: Vec<i64> = original.iter().map(|c| {
+        let class = char_class(*c);
+        let b = position_bonus(prev, class);
+        prev = class;
+        b
+    }).collect();
+    let n = text.len();
+    // score with pattern[i] matched exactly at j, and the bonus its run of consecutive matches began with
+    let mut last: Vec<Option<(i64, i64)>> = vec![None; n];
+    for (i, p) in pattern.iter().enumerate() {
+        let mut row: Vec<Option<(i64, i64)>> = vec![None; n];
+        // best (score - gap cost so far) over earlier positions, for jumping here over a gap
+        let mut best_gap: Option<i64> = None;
+        for j in 0..n {
+            if j >= 2 && let Some((score, _)) = last[j - 2] {
+                let from = score - SCORE_GAP_EXTENSION * (j as i64 - 2);
+                best_gap = Some(best_gap.map_or(from, |b| b.max(from)));
+            }
+            if text[j] != *p { continue; }
+            row[j] = if i == 0 {
+                Some((SCORE_MATCH + bonus[j] * BONUS_FIRST_CHAR_MULTIPLIER, bonus[j]))
+            } else {
+                let gapped = best_gap.map(|b| (b + SCORE_GAP_START + SCORE_GAP_EXTENSION * (j as i64 - 2) + SCORE_MATCH + bonus[j], bonus[j]));
+                let consecutive = j.checked_sub(1).and_then(|k| last[k]).map(|(score, run)| {
+                    // a boundary inside the run starts a new one with its own bonus
+                    let run = if bonus[j] >= BONUS_BOUNDARY && bonus[j] > run { bonus[j] } else { run };
+                    (score + SCORE_MATCH + run.max(BONUS_CONSECUTIVE), run)
+                });
+                match (gapped, consecutive) {
+                    (Some(g), Some(c)) => Some(if c.0 >= g.0 { c } else { g }),
+                    (g, c) => g.or(c),
+                }
+            };
+        }
+        last = row;
+    }
+    last.into_iter().flatten().map(|(score, _)| score).max()
+}
+
+// What the search knows about one name and command, made once and kept until they change
+struct IndexEntry {
+    key: u64,
+    name: String,
+    command: String,
+    // which (lowercase) letters appear, see char_mask
+    name_chars: u64,
+    command_chars: u64,
+    trigrams: Vec<u64>,
+}
+
+// Kept next to the alias list so searching thousands of aliases doesn't start from scratch on
+// every key. `sync` brings it up to date with the list, only new or changed aliases get indexed
+#[derive(Default)]
+struct SearchIndex {
+    entries: Vec<Option<IndexEntry>>,
+    // hash of name and command -> entry, the same alias keeps its entry wherever it's sorted to
+    by_key: HashMap<u64, usize>,
+    free: Vec<usize>,
+    // lowercase trigram -> entries with it in the name or the command
+    postings: HashMap<u64, HashSet<usize>>,
+    // entry of each alias in the list as of the last sync
+    of_alias: Vec<usize>,
+}
+
+impl SearchIndex {
+    fn sync(&mut self, aliases: &[Alias]) {
+        let mut used = vec![false; self.entries.len()];
+        self.of_alias.clear();
+        for a in aliases {
+            let mut h = std::collections::hash_map::DefaultHasher::new();
+            (&a.name, &a.command).hash(&mut h);
+            let key = h.finish();
+            let slot = match self.by_key.get(&key) {
+                Some(&slot) if self.entries[slot].as_ref().is_some_and(|e| e.name == a.name && e.command == a.command) => slot,
+                _ => self.insert(key, a),
+            };
+            if slot < used.len() { used[slot] = true; }
+            self.of_alias.push(slot);
+        }
+        // gone from the list (removed, renamed or edited)
+        for (slot, used) in used.into_iter().enumerate() {
+            if used { continue; }
+            let Some(e) = self.entries[slot].take() else { continue };
+            for t in &e.trigrams {
+                if let Some(p) = self.postings.get_mut(t) { p.remove(&slot); }
+            }
+            if self.by_key.get(&e.key) == Some(&slot) { self.by_key.remove(&e.key); }
+            self.free.push(slot);
+        }
+    }
+
+    fn insert(&mut self, key: u64, a: &Alias) -> usize {
+        let slot = self.free.pop().unwrap_or_else(|| { self.entries.push(None); self.entries.len() - 1 });
+        let mut trigrams = [trigrams(&a.name), trigrams(&a.command)].concat();
+        trigrams.sort_unstable();
+        trigrams.dedup();
+        for t in &trigrams { self.postings.entry(*t).or_default().insert(slot); }
+        self.entries[slot] = Some(IndexEntry { key, name: a.name.clone(), command: a.command.clone(), name_chars: char_mask(&a.name), command_chars: char_mask(&a.command), trigrams });
+        self.by_key.insert(key, slot);
+        slot
+    }
+
+    fn entry(&self, alias: usize) -> Option<&IndexEntry> {
+        self.entries.get(*self.of_alias.get(alias)?)?.as_ref()
+    }
+
+    // Which aliases can match `literal` at all going by the trigrams, None when it's too short to tell
+    fn candidates(&self, literal: &str) -> Option<Vec<bool>> {
+        let mut slots: Option<HashSet<usize>> = None;
+        for t in trigrams(literal) {
+            let with = self.postings.get(&t).cloned().unwrap_or_default();
+            slots = Some(match slots { None => with, Some(s) => s.intersection(&with).copied().collect() });
+        }
+        let slots = slots?;
+        Some(self.of_alias.iter().map(|s| slots.contains(s)).collect())
+    }
+}
+
+// A bit for each lowercase letter and digit (everything else shares the rest), so a fuzzy
+// pattern with a letter the text doesn't have is turned down without looking any further
+fn char_mask(text: &str) -> u64 {
+    text.chars().flat_map(char::to_lowercase).fold(0, |mask, c| mask | 1 << match c {
+        'a'..='z' => c as u32 - 'a' as u32,
+        '0'..='9' => 26 + c as u32 - '0' as u32,
+        _ => 36 + c as u32 % 28,
+    })
+}
+
+fn trigrams(text: &str) -> Vec<u64> {
+    let chars: Vec<char> = text.chars().flat_map(char::to_lowercase).collect();
+    chars.windows(3).map(|w| (w[0] as u64) << 42 | (w[1] as u64) << 21 | w[2] as u64).collect()
+}
+
+// The longest run of plain characters every match of the regex has to contain, for looking it
+// up by trigrams. Anything in a group, a class, escaped, optional or alternated is left out, so is
+// whatever is in braces (`{2,3}`, `\p{Greek}`, `\x{41}`), and flags like (?x) could change what the
+// rest means so those get nothing
+fn required_literal(pattern: &str) -> String {
+    if pattern.contains('|') || pattern.contains("(?") { return String::new(); }
+    let chars: Vec<char> = pattern.chars().collect();
+    let (mut best, mut run) = (String::new(), String::new());
+    let mut depth = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let optional = matches!(chars.get(i + 1), Some('?' | '*' | '{'));
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            '[' => i = class_end(&chars, i),
+            '\\' => { i += 1; run.clear(); }
+            '{' => {
+                if let Some(len) = chars[i..].iter().position(|c| *c == '}') { i += len; }
+            }
+            _ if depth == 0 && (c.is_alphanumeric() || " -_/:=@".contains(c)) && !optional => {
+                run.push(c);
+                if run.chars().count() > best.chars().count() { best = run.clone(); }
+                if chars.get(i + 1) == Some(&'+') { run.clear(); }
+                i += 1;
+                continue;
+            }
+            _ => {}
+        }
+        run.clear();
+        i += 1;
+    }
+    best
+}
+
+// Where the class opening at chars[start] closes. A `]` right after the `[` (or `[^`) is a literal
+// one, and classes nest (`[[:alpha:]]`, `[a-z&&[^aeiou]]`)
+fn class_end(chars: &[char], start: usize) -> usize {
+    let mut i = start + 1;
+    if chars.get(i) == Some(&'^') { i += 1; }
+    if chars.get(i) == Some(&']') { i += 1; }
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '[' => i = class_end(chars, i),
+            ']' => return i,
+            _ => {}
+        }
+        i += 1;
+    }
+    i
+}
+
+// Indices of the aliases matching the search, best first (regexes keep the list order)
+fn search_aliases(aliases: &[Alias], index: &mut SearchIndex, query: &str, options: &SearchOptions) -> Result<Vec<usize>, String> {
+    if query.is_empty() { return Ok((0..aliases.len()).collect()); }
+    let names = options.scope != SearchScope::Commands;
+    let commands = options.scope != SearchScope::Names;
+    index.sync(aliases);
+    if options.regex {
+        let re = regex::RegexBuilder::new(query).case_insensitive(!options.case_sensitive).build().map_err(|e| e.to_string())?;
+        let possible = index.candidates(&required_literal(query));
+        return Ok(aliases.iter().enumerate()
+            .filter(|(i, _)| possible.as_ref().is_none_or(|p| p[*i]))
+            .filter(|(_, a)| (names && re.is_match(&a.name)) || (commands && re.is_match(&a.command)))
+            .map(|(i, _)| i).collect());
+    }
+    let wanted = char_mask(query);
+    // a hit in the name is worth one more matched char than the same hit in the command
+    let mut scored: Vec<(i64, usize)> = aliases.iter().enumerate().filter_map(|(i, a)| {
+        let entry = index.entry(i)?;
+        let names = names && entry.name_chars & wanted == wanted;
+        let commands = commands && entry.command_chars & wanted == wanted;
+        let name = names.then(|| fuzzy_score(query, &a.name, options.case_sensitive)).flatten().map(|s| s + SCORE_MATCH);
+        let command = commands.then(|| fuzzy_score(query, &a.command, options.case_sensitive)).flatten();
+        name.max(command).map(|s| (s, i))
+    }).collect();
+    // ties go to the shorter name, like fzf's length tiebreak
+    scored.sort_by_key(|(score, i)| (std::cmp::Reverse(*score), aliases[*i].name.len()));
+    Ok(scored.into_iter().map(|(_, i)| i).collect())
+}
+
+impl std::fmt::Display for KeyBind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if let Some(l) = self.leader { write!(f, "{} ", l)?; }
+        write!(f, "{}", self.key)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 struct AliasEntry {
     command: String,
     keybind: Option<String>,
+    // run inside this docker container instead of locally
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    container: Option<String>,
+    #[serde(rename = "type", default, skip_serializing_if = "AliasKind::is_command")]
+    kind: AliasKind,
+    #[serde(rename = "run-in", default, skip_serializing_if = "RunIn::is_terminal")]
+    run_in: RunIn,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pause: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    after: Option<After>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    params: HashMap<String, ParamSpec>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    every: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cwd: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    group: Option<String>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 struct ConfigFile {
     aliases: HashMap<String, AliasEntry>,
     #[serde(rename = "default-shell")]
     default_shell: String,
+    // run commands as `shell -l -c ...` so .profile is sourced every time
+    #[serde(rename = "login-shell", default)]
+    login_shell: bool,
+    // capture the interactive login environment once at startup and reuse it for every command
+    #[serde(rename = "snapshot-env", default)]
+    snapshot_env: bool,
+    // commands containing any of these (whitespace ignored) always ask before running
+    #[serde(rename = "dangerous-patterns", default = "default_danger_patterns")]
+    danger_patterns: Vec<String>,
+    // extra variables for every spawned command, on top of the inherited environment
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    env: HashMap<String, String>,
+    // optional built-in panels to show in the actions list, e.g. ["kubernetes"]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    modules: Vec<String>,
+    // removed aliases are kept here for `trash-days` so they can be restored
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    trash: Vec<TrashEntry>,
+    #[serde(rename = "trash-days", default = "default_trash_days")]
+    trash_days: u64,
+    // how much output each job in the output pane keeps, the oldest lines go past either limit.
+    // With `output-spill` they go to a file in the temp directory instead of nowhere
+    #[serde(rename = "output-max-lines", default = "default_output_max_lines")]
+    output_max_lines: usize,
+    #[serde(rename = "output-max-bytes", default = "default_output_max_bytes")]
+    output_max_bytes: usize,
+    #[serde(rename = "output-spill", default)]
+    output_spill: bool,
+    // keys for built-in actions, e.g. {"Go to shell": "f12"}
+    #[serde(rename = "action-keys", default, skip_serializing_if = "HashMap::is_empty")]
+    action_keys: HashMap<String, String>,
+    // keys that open a group of aliases, name -> key, e.g. {"docker": "d"}. Inside a group the
+    // keybinds of its aliases apply, so the same key can mean something else in each one
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    groups: HashMap<String, String>,
+    // "auto" (clipboard tools, OSC 52 over ssh or when none work), "osc52" or "system"
+    #[serde(default = "default_clipboard")]
+    clipboard: String,
+    // after a command run in the terminal: "always" wait for a key, "never", "on-error" or
+    // a number of seconds, see Pause
+    #[serde(default = "default_pause")]
+    pause: String,
+    // read-only alias files to subscribe to, name -> URL, cached next to the config
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    bundles: HashMap<String, String>,
+    // git remote `tuish sync` keeps the config directory in step with
+    #[serde(rename = "sync-remote", default, skip_serializing_if = "Option::is_none")]
+    sync_remote: Option<String>,
+    // what `run-in: window` aliases open in, e.g. "alacritty -e", "kitty" or "wezterm start --".
+    // $TERMINAL with -e when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    terminal: Option<String>,
+    // what `tuish popup` opens tuish in outside tmux, e.g. "alacritty --class tuish-popup -e"
+    #[serde(rename = "popup-terminal", default, skip_serializing_if = "Option::is_none")]
+    popup_terminal: Option<String>,
+    // alias to run in the output pane as soon as tuish starts
+    #[serde(rename = "startup-alias", default, skip_serializing_if = "Option::is_none")]
+    startup_alias: Option<String>,
+    // minutes without a key press before the screen locks, see lock_passphrase
+    #[serde(rename = "lock-after", default, skip_serializing_if = "Option::is_none")]
+    lock_after: Option<u64>,
+    // keep the aliases (and trash) in aliases.age, encrypted with a passphrase asked for at startup
+    #[serde(default)]
+    encrypt: bool,
+    // make URLs and paths in the output pane clickable (OSC 8), off for terminals that print the codes
+    #[serde(default = "default_hyperlinks")]
+    hyperlinks: bool,
+    // widgets on the header line, in order: branch, load, memory, battery, and clock on the right
+    #[serde(default = "default_header")]
+    header: Vec<String>,
+    // colors by role, hex ("#ff8700"), 256-color index or name, see Theme
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    theme: HashMap<String, String>,
+    // plain layout for screen readers: no borders or symbols, and a status line saying what's selected
+    #[serde(rename = "screen-reader", default)]
+    screen_reader: bool,
+    // UI language, looked up in locales/<language>.json next to the config; LANG when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    language: Option<String>,
+    // named sets of `{{env:NAME}}` values, e.g. dev/staging/prod, and the one in use
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    profiles: HashMap<String, Profile>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    profile: Option<String>,
+    // .git/tuish.json of the repository we were started in, if it has one
+    #[serde(skip)]
+    repo_file: Option<PathBuf>,
+    // the passphrase aliases.age was opened with, to write it back with
+    #[serde(skip)]
+    passphrase: Option<String>,
+}
+
+// One of the `profiles`: what `{{env:NAME}}` stands for while it's active
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct Profile {
+    #[serde(default)]
+    vars: HashMap<String, String>,
+    // instead of `default-shell` while the profile is active
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    shell: Option<String>,
+    // where tuish moves to when switching to it, like the Change directory action
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cwd: Option<String>,
+    // on top of `env` for every spawned command
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    env: HashMap<String, String>,
+    // how it shows in the header, a color like in `theme` (red if unset and it's called prod/production)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    color: Option<String>,
+}
+
+// Per-repository aliases, overlaid on the user's own while tuish runs inside the repo
+#[derive(Serialize, Deserialize, Default)]
+struct RepoFile {
+    #[serde(default)]
+    aliases: HashMap<String, AliasEntry>,
+}
+
+impl Default for ConfigFile {
+    fn default() -> Self {
+        ConfigFile { aliases: HashMap::new(), default_shell: "/bin/bash".to_string(), login_shell: false, snapshot_env: false, danger_patterns: default_danger_patterns(), env: HashMap::new(), modules: Vec::new(), trash: Vec::new(), trash_days: default_trash_days(), output_max_lines: default_output_max_lines(), output_max_bytes: default_output_max_bytes(), output_spill: false, action_keys: HashMap::new(), groups: HashMap::new(), clipboard: default_clipboard(), pause: default_pause(), bundles: HashMap::new(), sync_remote: None, terminal: None, popup_terminal: None, startup_alias: None, lock_after: None, encrypt: false, hyperlinks: default_hyperlinks(), header: default_header(), theme: HashMap::new(), screen_reader: false, language: None, profiles: HashMap::new(), profile: None, repo_file: None, passphrase: None }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct TrashEntry {
+    name: String,
+    #[serde(flatten)]
+    entry: AliasEntry,
+    // unix seconds
+    deleted: u64,
+}
+
+fn default_trash_days() -> u64 { 30 }
+
+fn default_output_max_lines() -> usize { 10_000 }
+fn default_output_max_bytes() -> usize { 4_000_000 }
+
+fn default_clipboard() -> String { "auto".to_string() }
+fn default_hyperlinks() -> bool { true }
+fn default_header() -> Vec<String> { vec!["branch".to_string(), "clock".to_string()] }
+
+fn default_pause() -> String { "always".to_string() }
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn default_danger_patterns() -> Vec<String> {
+    ["rm -rf", "rm -fr", "dd of=/dev/", "mkfs", ":(){:|:&};:", "> /dev/sd", "chmod -R 777 /", "git push --force", "git reset --hard"]
+        .iter().map(|p| p.to_string()).collect()
+}
+
+// Whitespace is dropped on both sides so `rm  -rf` and `:(){ :|:& };:` still match
+fn is_dangerous(command: &str, patterns: &[String]) -> bool {
+    let squashed: String = command.chars().filter(|c| !c.is_whitespace()).collect();
+    patterns.iter().any(|p| {
+        let p: String = p.chars().filter(|c| !c.is_whitespace()).collect();
+        !p.is_empty() && squashed.contains(&p)
+    })
 }
 
 enum UiMode {
     Main,
-    Adding { step: u8, name: String, command: String, keybind: Option<char> },
+    // `ask_command` is false when saving a command that was already run, which skips step 2
+    // `error` says why the keybind in step 3 can't be used
+    Adding { step: u8, kind: AliasKind, name: String, command: String, keybind: Option<KeyBind>, ask_command: bool, error: Option<String> },
     EditingSelect,
+    RenameSelect,
+    Renaming { index: usize, name: String, error: Option<String> },
     Editing { index: usize, command: String },
+    // `e` on a row: the command is edited right there in the alias list
+    InlineEdit { index: usize, command: String },
+    // second step of editing, `error` says which alias the chosen keys clash with
+    EditKeybind { index: usize, keybind: Option<KeyBind>, error: Option<String> },
     RemovingSelect,
+    Trash { cursor: usize },
+    // earlier versions of the alias at `index`, newest first
+    AliasHistory { index: usize, cursor: usize },
+    // `/`: aliases matching `query`, with the toggles from the search bar
+    Search { query: String, options: SearchOptions, cursor: usize },
+    Tmux { items: Vec<TmuxEntry>, cursor: usize },
+    Tasks { title: &'static str, items: Vec<TaskEntry>, picked: Vec<bool>, cursor: usize },
+    // `Import aliases`: the scanners still going, (source, found) for the ones done and what they found
+    Importing { rx: Receiver<(&'static str, Vec<TaskEntry>)>, done: Vec<(&'static str, usize)>, items: Vec<TaskEntry> },
+    PickContainer { index: usize, items: Vec<String>, cursor: usize },
+    // profiles to switch to, "(none)" first
+    PickProfile { items: Vec<String>, cursor: usize },
+    ImportHistory { items: Vec<(String, usize)>, picked: Vec<bool>, cursor: usize },
+    // `alias` already has its placeholders filled in
+    ConfirmRun { alias: Alias },
+    // idle lock, whatever was open before comes back once the passphrase is right
+    Locked { previous: Box<UiMode>, input: String, wrong: bool },
+    // an alias with `"after": "rerun"` just finished
+    Rerun { alias: Alias, exit: Option<i32> },
+    // filling in an alias's placeholders one at a time before it runs
+    Params { alias: Alias, names: Vec<String>, values: Vec<String>, choices: Vec<Vec<String>>, field: usize, error: Option<String> },
+    // before that, waiting for its `choices-command`s
+    LoadingChoices { alias: Alias, names: Vec<String>, rx: Receiver<Vec<Vec<String>>> },
+    SshHosts { items: Vec<String>, cursor: usize },
+    Services { items: Vec<ServiceEntry>, cursor: usize, user: bool },
+    Containers { items: Vec<ContainerEntry>, cursor: usize },
+    EnvPanel { cursor: usize },
+    Kube { items: Vec<KubeEntry>, cursor: usize },
+    // `original` is the variable being edited, None when adding
+    EnvEdit { original: Option<String>, input: String },
+    TextView { title: String, lines: Vec<String>, scroll: u16 },
+    // the config was changed outside tuish: what's different, applied only once the user says so
+    ConfigChanged { cfg: Box<ConfigFile>, lines: Vec<String>, scroll: u16 },
+    // output pane for jobs[job]
+    Output { job: usize },
+    // where to write that job's output
+    SaveOutput { job: usize, path: String },
+    // links on screen in that job's output, to open one
+    Links { job: usize, items: Vec<String>, cursor: usize },
+    // the previous run of an alias against its latest, '+'/'-'/' ' per line
+    Diff { name: String, lines: Vec<(char, String)>, scroll: u16 },
+    // run counts, failures and durations from the run history
+    Stats,
+    // the run history itself, newest first, `f` narrows it to the runs that failed
+    RunHistory { cursor: usize, failures_only: bool },
+    // picking a directory for aliases[alias]'s cwd, or tuish's own when None. Row 0 picks `dir`
+    // itself, the rest are `entries` (".." first when there is a parent)
+    PickDir { alias: Option<usize>, dir: PathBuf, entries: Vec<String>, cursor: usize, hidden: bool },
+    // command builder, first the program from PATH (narrowed down by typing) ...
+    BuildPick { all: Vec<String>, filter: String, cursor: usize },
+    // ... then its options from --help, `values[i]` set for the picked ones. The row after the
+    // options is for plain arguments; `editing` sends typed keys to the value (or arguments) under the cursor
+    BuildFlags { program: String, options: Vec<HelpOption>, values: Vec<Option<String>>, args: String, cursor: usize, editing: bool },
+    // in between: its --help still being read, which can take a couple of seconds
+    ReadingHelp { program: String, rx: Receiver<Vec<HelpOption>> },
+    // processes of the jobs running in the output pane; `ticks` is CPU time at `sampled`, for the CPU %
+    Processes { items: Vec<ProcEntry>, cursor: usize, ticks: HashMap<u32, u64>, sampled: Instant },
+    // `:` bar for running a one-off command
+    CommandBar { input: String },
+    // pasting in someone's share string
+    ImportShared { input: String },
     Message(String),
 }
 
@@ -47,9 +999,26 @@ enum Focus {
     Actions,
 }
 
+// below this the layout goes compact, see compact()
 const MIN_W: u16 = 40;
 const MIN_H: u16 = 10;
 
+fn compact(size: Rect) -> bool {
+    size.width < MIN_W || size.height < MIN_H
+}
+
+// Keep a popup on screen: in a compact terminal it takes the full width and moves up until it fits
+fn fit(area: Rect, size: Rect) -> Rect {
+    let (x, width) = if compact(size) { (0, size.width) } else { (area.x, area.width.min(size.width)) };
+    let height = area.height.min(size.height);
+    Rect::new(x, area.y.min(size.height - height), width, height)
+}
+
+// "Recent (r: hide, ...)" -> "Recent" when there's no room for the key hints
+fn brief(title: &str, size: Rect) -> &str {
+    if compact(size) { title.split(" (").next().unwrap_or(title) } else { title }
+}
+
 fn config_path() -> PathBuf {
     if let Some(mut d) = dirs::config_dir() {
         d.push("tuish");
@@ -61,192 +1030,5089 @@ fn config_path() -> PathBuf {
     }
 }
 
-fn write_config(path: &PathBuf, aliases: &Vec<Alias>, default_shell: &str) {
-    let mut map = HashMap::new();
-    for a in aliases.iter() {
-        map.insert(a.name.clone(), AliasEntry { command: a.command.clone(), keybind: a.keybind.map(|c| c.to_string()) });
-    }
-    let cfg = ConfigFile { aliases: map, default_shell: default_shell.to_string() };
-    if let Ok(s) = serde_json::to_string_pretty(&cfg) {
-        let _ = fs::write(path, s);
-    }
+// Translations of the UI text, keyed by the English original; empty (so English) when
+// there's no locale file for the language
+static CATALOG: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+fn ui_language(cfg: &ConfigFile) -> Option<String> {
+    let lang = cfg.language.clone().or_else(|| {
+        ["LC_ALL", "LC_MESSAGES", "LANG"].iter().filter_map(|v| std::env::var(v).ok()).find(|v| !v.is_empty())
+    })?;
+    // de_DE.UTF-8 -> de
+    let lang = lang.split(['_', '.', '@']).next().unwrap_or("").to_lowercase();
+    if lang.is_empty() || lang == "en" || lang == "c" || lang == "posix" { None } else { Some(lang) }
 }
 
-fn ensure_config(path: &PathBuf) -> ConfigFile {
-    if !path.exists() {
-        // create empty aliases by default
-        let example: HashMap<String, AliasEntry> = HashMap::new();
-        let cfg = ConfigFile { aliases: example, default_shell: "/bin/bash".to_string() };
-        if let Ok(s) = serde_json::to_string_pretty(&cfg) {
-            let _ = fs::write(path, s);
+fn load_catalog(cfg: &ConfigFile, cfg_path: &std::path::Path) {
+    let catalog = ui_language(cfg)
+        .and_then(|lang| fs::read_to_string(cfg_path.with_file_name("locales").join(format!("{}.json", lang))).ok())
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default();
+    CATALOG.set(catalog).ok();
+}
+
+// The UI text in the chosen language, or as written when it has no translation
+fn tr(s: &'static str) -> &'static str {
+    CATALOG.get().and_then(|c| c.get(s)).map(|t| t.as_str()).unwrap_or(s)
+}
+
+// tr() for text with {} slots, filled in order after translating
+fn trf(s: &'static str, args: &[&dyn std::fmt::Display]) -> String {
+    let mut out = String::new();
+    let mut args = args.iter();
+    let mut parts = tr(s).split("{}").peekable();
+    while let Some(part) = parts.next() {
+        out.push_str(part);
+        if parts.peek().is_some() && let Some(a) = args.next() {
+            out.push_str(&a.to_string());
         }
-        cfg
-    } else {
-        let data = fs::read_to_string(path).unwrap_or_default();
-        serde_json::from_str(&data).unwrap_or(ConfigFile { aliases: HashMap::new(), default_shell: std::env::var("SHELL").unwrap_or_else(|_| "sh".into()) })
     }
+    out
 }
 
-fn run_shell_command_with_shell(cmd: &str, shell: &str) {
-    // Leave TUI and run the command in the shell, then wait for a keypress
-    disable_raw_mode().ok();
-    execute!(std::io::stdout(), LeaveAlternateScreen).ok();
+fn alias_entry(a: &Alias) -> AliasEntry {
+    AliasEntry { command: a.command.clone(), keybind: a.keybind.map(|k| k.to_string()), container: a.container.clone(), kind: a.kind, run_in: a.run_in, pause: a.pause.clone(), after: a.after, params: a.params.clone(), every: a.every.clone(), cwd: a.cwd.clone(), group: a.group.clone() }
+}
 
-    let status = Command::new(shell).arg("-c").arg(cmd).status();
-    match status {
-        Ok(s) => println!("Command exited with: {}", s),
-        Err(e) => println!("Failed to run command: {}", e),
+// Repo aliases go back to the repo's file, everything else to the user config
+fn write_config(path: &std::path::Path, aliases: &Vec<Alias>, settings: &ConfigFile) {
+    let mut map = HashMap::new();
+    for a in aliases.iter().filter(|a| !a.repo && a.bundle.is_none()) {
+        map.insert(a.name.clone(), alias_entry(a));
+    }
+    let mut cfg = ConfigFile { aliases: map, ..settings.clone() };
+    match &settings.passphrase {
+        Some(pass) => {
+            // the plain config keeps only the settings, everything with commands in it goes in the vault.
+            // scrypt takes a while, so it happens off the UI thread, and the plain file (which no longer
+            // has the aliases) is only written once the vault is safely on disk
+            let vault = Vault { aliases: std::mem::take(&mut cfg.aliases), trash: std::mem::take(&mut cfg.trash) };
+            let (vault, plain) = (serde_json::to_string(&vault), serde_json::to_string_pretty(&cfg));
+            let (path, pass) = (path.to_path_buf(), pass.clone());
+            save_in_background("config", move || {
+                let data = encrypt_with(vault.map_err(|e| e.to_string())?.as_bytes(), &pass)?;
+                write_atomic(&vault_path(&path), &data)?;
+                write_atomic(&path, plain.map_err(|e| e.to_string())?.as_bytes())?;
+                *CONFIG_WRITTEN.lock().unwrap() = config_stamp(&path);
+                Ok(())
+            });
+        }
+        None => {
+            let written = serde_json::to_string_pretty(&cfg).map_err(|e| e.to_string()).and_then(|s| write_atomic(path, s.as_bytes()));
+            if let Err(e) = written { *SAVE_ERROR.lock().unwrap() = Some(e); }
+            *CONFIG_WRITTEN.lock().unwrap() = config_stamp(path);
+        }
     }
+    if let Some(repo_file) = &settings.repo_file {
+        let repo = RepoFile { aliases: aliases.iter().filter(|a| a.repo).map(|a| (a.name.clone(), alias_entry(a))).collect() };
+        if let Ok(s) = serde_json::to_string_pretty(&repo) {
+            let _ = fs::write(repo_file, s);
+        }
+    }
+}
+
+// Write to a file next to `path` and rename it over, so a failed write never leaves half a file
+fn write_atomic(path: &std::path::Path, data: &[u8]) -> Result<(), String> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    fs::write(&tmp, data).and_then(|_| fs::rename(&tmp, path)).map_err(|e| trf("Couldn't save {}: {}", &[&path.display(), &e]))
+}
 
-    println!("Press any key to return to the menu...");
+// The save in progress, the ones after it wait for it so they land in order
+static SAVE_THREAD: Mutex<Option<std::thread::JoinHandle<()>>> = Mutex::new(None);
+// latest save asked for of each kind ("config", "state"), an older one still waiting has nothing left to do
+static SAVE_GENERATIONS: Mutex<Option<HashMap<&'static str, usize>>> = Mutex::new(None);
+// a save that failed, shown on the main screen (or printed by the CLI)
+static SAVE_ERROR: Mutex<Option<String>> = Mutex::new(None);
 
-    // Wait for one key press
-    enable_raw_mode().ok();
-    let _ = event::read();
+fn save_in_background(kind: &'static str, save: impl FnOnce() -> Result<(), String> + Send + 'static) {
+    let generation = {
+        let mut generations = SAVE_GENERATIONS.lock().unwrap();
+        let n = generations.get_or_insert_with(HashMap::new).entry(kind).or_insert(0);
+        *n += 1;
+        *n
+    };
+    let mut slot = SAVE_THREAD.lock().unwrap();
+    let previous = slot.take();
+    *slot = Some(std::thread::spawn(move || {
+        if let Some(p) = previous { let _ = p.join(); }
+        let latest = SAVE_GENERATIONS.lock().unwrap().as_ref().and_then(|g| g.get(kind).copied());
+        if latest != Some(generation) { return; }
+        if let Err(e) = save() { *SAVE_ERROR.lock().unwrap() = Some(e); }
+    }));
 }
 
-fn main() {
-    enable_raw_mode().unwrap();
+fn saving() -> bool {
+    SAVE_THREAD.lock().unwrap().as_ref().is_some_and(|h| !h.is_finished())
+}
 
-    let cfg_path = config_path();
-    let cfg = ensure_config(&cfg_path);
+// Before exiting, or the last edit could be lost
+fn finish_saves() {
+    let last = SAVE_THREAD.lock().unwrap().take();
+    if let Some(h) = last { let _ = h.join(); }
+}
 
-    let mut stdout = stdout();
-    execute!(stdout, EnterAlternateScreen).unwrap();
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend).unwrap();
+// How often the config is looked at for changes made outside tuish (an editor, a sync, another tuish)
+const CONFIG_CHECK: Duration = Duration::from_secs(2);
+// The config files' stamp right after we wrote them ourselves, those changes need no review
+static CONFIG_WRITTEN: Mutex<Option<std::time::SystemTime>> = Mutex::new(None);
 
-    // Load aliases from config
-    let mut aliases: Vec<Alias> = cfg.aliases.iter().map(|(name, entry)| Alias {
-        name: name.clone(),
-        command: entry.command.clone(),
-        keybind: entry.keybind.as_ref().and_then(|s| s.chars().next()),
-    }).collect();
+// Last change to the files the config is read from
+fn config_stamp(cfg_path: &std::path::Path) -> Option<std::time::SystemTime> {
+    [cfg_path.to_path_buf(), vault_path(cfg_path)].iter().filter_map(|p| fs::metadata(p).and_then(|m| m.modified()).ok()).max()
+}
 
-    let default_shell = cfg.default_shell.clone();
+// "+ name: command", "- name" and "~ name: old → new" for every alias that differs
+fn alias_changes(old: &HashMap<String, AliasEntry>, new: &HashMap<String, AliasEntry>) -> Vec<String> {
+    let mut names: Vec<&String> = old.keys().chain(new.keys()).collect();
+    names.sort();
+    names.dedup();
+    let mut lines = Vec::new();
+    for name in names {
+        lines.push(match (old.get(name), new.get(name)) {
+            (None, Some(n)) => format!("+ {}: {}", name, n.command),
+            (Some(_), None) => format!("- {}", name),
+            (Some(o), Some(n)) if o.command != n.command => format!("~ {}: {} → {}", name, o.command, n.command),
+            (Some(o), Some(n)) if serde_json::to_value(o).ok() != serde_json::to_value(n).ok() => trf("~ {}: settings changed", &[name]),
+            _ => continue,
+        });
+    }
+    lines
+}
 
-    let options = vec!["Add an alias", "Edit an alias", "Remove an alias", "Go to shell", "Quit shell"];
-    let mut opt_state = ListState::default();
-    opt_state.select(Some(0));
+// What goes into aliases.age when `encrypt` is on
+#[derive(Serialize, Deserialize, Default)]
+struct Vault {
+    aliases: HashMap<String, AliasEntry>,
+    #[serde(default)]
+    trash: Vec<TrashEntry>,
+}
 
-    // selection state for aliases list and focus
-    let mut alias_state = ListState::default();
-    if !aliases.is_empty() { alias_state.select(Some(0)); } else { alias_state.select(None); }
-    let mut focus = Focus::Actions;
+fn vault_path(cfg_path: &std::path::Path) -> PathBuf {
+    cfg_path.with_file_name("aliases.age")
+}
 
-    let mut ui_mode = UiMode::Main;
-    let mut selected_opt: usize = 0;
+// age's passphrase mode (scrypt), so the file can also be opened with `age -d`
+fn encrypt_with(data: &[u8], passphrase: &str) -> Result<Vec<u8>, String> {
+    use std::io::Write;
+    let encryptor = age::Encryptor::with_user_passphrase(age::secrecy::Secret::new(passphrase.to_string()));
+    let mut out = Vec::new();
+    let mut writer = encryptor.wrap_output(&mut out).map_err(|e| e.to_string())?;
+    writer.write_all(data).map_err(|e| e.to_string())?;
+    writer.finish().map_err(|e| e.to_string())?;
+    Ok(out)
+}
 
-    loop {
-        // Draw UI
-        terminal.draw(|f| {
-            let size = f.size();
-
-            // detect too small
-            if size.width < MIN_W || size.height < MIN_H {
-                let area = ratatui::layout::Rect::new(2, 2, (size.width.saturating_sub(4)).max(1), 3);
-                let msg = format!("Terminal too small — need at least {}x{}", MIN_W, MIN_H);
-                let p = Paragraph::new(msg).style(Style::default().fg(Color::Red).add_modifier(Modifier::BOLD));
-                f.render_widget(Clear, area);
-                f.render_widget(p, area);
-                return;
-            }
-
-            let chunks = Layout::default()
-                .direction(Direction::Vertical)
-                .margin(1)
-                .constraints([
-                    Constraint::Length(1), // header
-                    Constraint::Min(3),     // aliases (will be clipped if too large)
-                    Constraint::Length(7),  // actions
-                ].as_ref())
-                .split(size);
-
-            let header = Paragraph::new("tuish").style(Style::default().fg(Color::Magenta).add_modifier(Modifier::BOLD));
-            f.render_widget(header, chunks[0]);
-
-            // Aliases block (clipped if too many) - make it selectable when focused
-            let alias_items: Vec<ListItem> = if aliases.is_empty() {
-                vec![ListItem::new("(no aliases)").style(Style::default().fg(Color::DarkGray))]
+fn decrypt_with(data: &[u8], passphrase: &str) -> Result<Vec<u8>, String> {
+    use std::io::Read;
+    let decryptor = match age::Decryptor::new(data).map_err(|e| e.to_string())? {
+        age::Decryptor::Passphrase(d) => d,
+        _ => return Err("aliases.age isn't encrypted with a passphrase".to_string()),
+    };
+    let mut reader = decryptor.decrypt(&age::secrecy::Secret::new(passphrase.to_string()), None).map_err(|e| e.to_string())?;
+    let mut out = Vec::new();
+    reader.read_to_end(&mut out).map_err(|e| e.to_string())?;
+    Ok(out)
+}
+
+// With `encrypt` on, ask for the passphrase and load the aliases from aliases.age. The first
+// time there's no aliases.age yet, so a new passphrase is picked and the aliases move over into it
+fn unlock_config(cfg: &mut ConfigFile, cfg_path: &std::path::Path) -> Result<(), String> {
+    if !cfg.encrypt { return Ok(()); }
+    let file = vault_path(cfg_path);
+    if !file.exists() {
+        let pass = read_hidden("New passphrase for your aliases: ").ok_or("cancelled")?;
+        if read_hidden("Same passphrase again: ").as_deref() != Some(pass.as_str()) {
+            return Err("the passphrases don't match".to_string());
+        }
+        cfg.passphrase = Some(pass.clone());
+        *STATE_PASSPHRASE.lock().unwrap() = Some(pass);
+        write_config(cfg_path, &load_aliases(cfg), cfg);
+        return Ok(());
+    }
+    let data = fs::read(&file).map_err(|e| format!("can't read {}: {}", file.display(), e))?;
+    for _ in 0..3 {
+        let pass = read_hidden("Passphrase: ").ok_or("cancelled")?;
+        if let Ok(plain) = decrypt_with(&data, &pass) {
+            let vault: Vault = serde_json::from_slice(&plain).map_err(|e| format!("{} is damaged: {}", file.display(), e))?;
+            cfg.aliases = vault.aliases;
+            cfg.trash = vault.trash;
+            cfg.passphrase = Some(pass.clone());
+            *STATE_PASSPHRASE.lock().unwrap() = Some(pass);
+            return Ok(());
+        }
+        eprintln!("Wrong passphrase");
+    }
+    Err("giving up after 3 tries".to_string())
+}
+
+// The config as it is on disk now (after a sync pulled something in), opening aliases.age with
+// the passphrase we already have
+fn reload_config(cfg_path: &PathBuf, current: &ConfigFile) -> Result<ConfigFile, String> {
+    let mut cfg = ensure_config(cfg_path);
+    cfg.repo_file = current.repo_file.clone();
+    if cfg.encrypt {
+        let Some(pass) = &current.passphrase else { return Err(tr("restart tuish to unlock the synced aliases").to_string()) };
+        let file = vault_path(cfg_path);
+        let data = fs::read(&file).map_err(|e| trf("can't read {}: {}", &[&file.display(), &e]))?;
+        let plain = decrypt_with(&data, pass).map_err(|_| tr("the synced aliases.age has a different passphrase, restart tuish").to_string())?;
+        let vault: Vault = serde_json::from_slice(&plain).map_err(|e| trf("{} is damaged: {}", &[&file.display(), &e]))?;
+        cfg.aliases = vault.aliases;
+        cfg.trash = vault.trash;
+        cfg.passphrase = Some(pass.clone());
+    }
+    Ok(cfg)
+}
+
+// Files a backup holds, whichever of them exist
+const BACKUP_FILES: &[&str] = &["cnfg.json", "state.json", "aliases.age", "state.age"];
+// Automatic backups (the ones made before bulk changes) kept around, the oldest go first
+const MAX_AUTO_BACKUPS: usize = 10;
+
+fn backups_dir(cfg_path: &std::path::Path) -> PathBuf {
+    cfg_path.with_file_name("backups")
+}
+
+// tar.gz of the config and state in backups/, or at `to`. Automatic ones are named
+// differently so pruning them never touches a backup the user asked for
+fn backup_config(cfg_path: &std::path::Path, to: Option<PathBuf>, auto: bool) -> Result<PathBuf, String> {
+    let dir = cfg_path.parent().unwrap_or(std::path::Path::new("."));
+    let kind = if auto { "auto" } else { "backup" };
+    let file = match to {
+        Some(f) => f,
+        None => {
+            let backups = backups_dir(cfg_path);
+            fs::create_dir_all(&backups).map_err(|e| trf("can't create {}: {}", &[&backups.display(), &e]))?;
+            backups.join(format!("tuish-{}-{}.tar.gz", kind, unix_now()))
+        }
+    };
+    let files: Vec<&str> = BACKUP_FILES.iter().copied().filter(|f| dir.join(f).exists()).collect();
+    let out = Command::new("tar").arg("-czf").arg(&file).arg("-C").arg(dir).args(&files).output()
+        .map_err(|_| tr("tar is not installed").to_string())?;
+    if !out.status.success() {
+        return Err(format!("tar: {}", String::from_utf8_lossy(&out.stderr).trim()));
+    }
+    if auto {
+        let mut old: Vec<PathBuf> = fs::read_dir(backups_dir(cfg_path)).map(|rd| rd.flatten().map(|e| e.path())
+            .filter(|p| p.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.starts_with("tuish-auto-"))).collect()).unwrap_or_default();
+        old.sort();
+        for p in old.iter().take(old.len().saturating_sub(MAX_AUTO_BACKUPS)) { let _ = fs::remove_file(p); }
+    }
+    Ok(file)
+}
+
+// Put a backup's files back, after backing up what's there now. Anything in the archive
+// besides our own files is refused rather than unpacked
+fn restore_config(cfg_path: &std::path::Path, file: &std::path::Path) -> Result<PathBuf, String> {
+    let dir = cfg_path.parent().unwrap_or(std::path::Path::new("."));
+    let list = Command::new("tar").arg("-tzf").arg(file).output().map_err(|_| tr("tar is not installed").to_string())?;
+    if !list.status.success() {
+        return Err(trf("{} isn't a tuish backup", &[&file.display()]));
+    }
+    let names: Vec<String> = String::from_utf8_lossy(&list.stdout).lines().map(|l| l.trim_start_matches("./").to_string()).collect();
+    if names.is_empty() || names.iter().any(|n| !BACKUP_FILES.contains(&n.as_str())) {
+        return Err(trf("{} isn't a tuish backup", &[&file.display()]));
+    }
+    let before = backup_config(cfg_path, None, true)?;
+    let out = Command::new("tar").arg("-xzf").arg(file).arg("-C").arg(dir).output().map_err(|_| tr("tar is not installed").to_string())?;
+    if !out.status.success() {
+        return Err(format!("tar: {}", String::from_utf8_lossy(&out.stderr).trim()));
+    }
+    Ok(before)
+}
+
+// git in `dir`, its output or what it said went wrong
+// Never asks for anything: a credential or host key prompt would fight the TUI for the terminal, so
+// without an ssh key, agent or credential helper that works on its own it fails instead
+fn git_in(dir: &std::path::Path, args: &[&str]) -> Result<String, String> {
+    let ssh = std::env::var("GIT_SSH_COMMAND").unwrap_or_else(|_| "ssh".to_string());
+    let out = Command::new("git").arg("-C").arg(dir).args(args)
+        .env("GIT_TERMINAL_PROMPT", "0").env("GIT_SSH_COMMAND", format!("{} -o BatchMode=yes", ssh))
+        .stdin(Stdio::null())
+        .output().map_err(|_| tr("git is not installed").to_string())?;
+    if out.status.success() {
+        Ok(String::from_utf8_lossy(&out.stdout).trim().to_string())
+    } else {
+        let err = String::from_utf8_lossy(&out.stderr).trim().to_string();
+        Err(format!("git {}: {}", args.join(" "), err))
+    }
+}
+
+// Commit the config directory, merge in the remote and push. Conflicts are never left behind
+// half-merged: the merge is aborted and the conflicting files listed. Ok says whether anything came in
+fn sync_config(cfg_path: &std::path::Path, remote: &str) -> Result<(Vec<String>, bool), Vec<String>> {
+    let dir = cfg_path.parent().unwrap_or(std::path::Path::new("."));
+    let mut log = Vec::new();
+    let fail = |e: String| vec![e];
+    if !dir.join(".git").exists() {
+        git_in(dir, &["init", "-q"]).map_err(fail)?;
+        log.push(trf("Started a git repository in {}", &[&dir.display()]));
+    }
+    match git_in(dir, &["remote", "get-url", "origin"]) {
+        Ok(url) if url == remote => {}
+        Ok(_) => { git_in(dir, &["remote", "set-url", "origin", remote]).map_err(fail)?; }
+        Err(_) => { git_in(dir, &["remote", "add", "origin", remote]).map_err(fail)?; }
+    }
+    // per-machine data stays out of it, aliases.age is fine to share
+    let ignore = dir.join(".gitignore");
+    if !ignore.exists() { let _ = fs::write(&ignore, "state.json\nstate.age\nbundles/\nbackups/\n"); }
+
+    git_in(dir, &["add", "-A"]).map_err(fail)?;
+    if git_in(dir, &["status", "--porcelain"]).map_err(fail)?.is_empty() {
+        log.push(tr("Nothing new to commit").to_string());
+    } else {
+        let host = command_output("hostname", &[]).unwrap_or_else(|| "unknown host".to_string());
+        git_in(dir, &["commit", "-q", "-m", &format!("tuish sync from {}", host)]).map_err(fail)?;
+        log.push(tr("Committed local changes").to_string());
+    }
+    let branch = git_in(dir, &["rev-parse", "--abbrev-ref", "HEAD"]).map_err(fail)?;
+    git_in(dir, &["fetch", "-q", "origin"]).map_err(fail)?;
+
+    let upstream = format!("origin/{}", branch);
+    let mut pulled = false;
+    if git_in(dir, &["rev-parse", "--verify", "-q", &upstream]).is_ok() {
+        let before = git_in(dir, &["rev-parse", "HEAD"]).map_err(fail)?;
+        if let Err(e) = git_in(dir, &["merge", "--no-edit", "-q", "--allow-unrelated-histories", &upstream]) {
+            let conflicts = git_in(dir, &["diff", "--name-only", "--diff-filter=U"]).unwrap_or_default();
+            let _ = git_in(dir, &["merge", "--abort"]);
+            if conflicts.is_empty() { return Err(vec![e]); }
+            let mut lines = vec![tr("Conflicts with the remote in:").to_string()];
+            lines.extend(conflicts.lines().map(|f| format!("  {}", f)));
+            lines.push(tr("Nothing was merged or pushed, your config is as it was.").to_string());
+            lines.push(trf("Merge by hand with `git pull` in {} and sync again", &[&dir.display()]));
+            return Err(lines);
+        }
+        pulled = git_in(dir, &["rev-parse", "HEAD"]).map_err(fail)? != before;
+        log.push(tr(if pulled { "Pulled changes from the remote" } else { "Already up to date with the remote" }).to_string());
+    }
+    git_in(dir, &["push", "-q", "-u", "origin", &branch]).map_err(fail)?;
+    log.push(trf("Pushed to {}", &[&remote]));
+    Ok((log, pulled))
+}
+
+// What unlocks the idle lock: the encryption passphrase if there is one, otherwise the
+// `lock` secret from the keyring. Without either there's nothing to check against, so no lock
+fn lock_passphrase(cfg: &ConfigFile) -> Option<String> {
+    cfg.passphrase.clone().or_else(|| secret_value("lock"))
+}
+
+fn ensure_config(path: &PathBuf) -> ConfigFile {
+    if !path.exists() {
+        // create empty aliases by default
+        let cfg = ConfigFile::default();
+        if let Ok(s) = serde_json::to_string_pretty(&cfg) {
+            let _ = fs::write(path, s);
+        }
+        cfg
+    } else {
+        let data = fs::read_to_string(path).unwrap_or_default();
+        serde_json::from_str(&data).unwrap_or(ConfigFile { default_shell: std::env::var("SHELL").unwrap_or_else(|_| "sh".into()), ..ConfigFile::default() })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ShellKind {
+    Posix,
+    Fish,
+}
+
+fn shell_kind(shell: &str) -> ShellKind {
+    let base = std::path::Path::new(shell).file_name().and_then(|s| s.to_str()).unwrap_or(shell);
+    match base {
+        "fish" => ShellKind::Fish,
+        _ => ShellKind::Posix,
+    }
+}
+
+// Everything needed to spawn commands the way the user's own shell would
+struct ShellEnv {
+    shell: String,
+    login: bool,
+    // environment captured from an interactive login shell, see `snapshot-env`
+    snapshot: Option<HashMap<String, String>>,
+    // set from the config's `env` and changed for the session in the Environment panel
+    overrides: HashMap<String, String>,
+    // the active profile's values for `{{env:NAME}}`, and its variables on top of `overrides`
+    vars: HashMap<String, String>,
+    profile_env: HashMap<String, String>,
+    // worked out from the above once per change rather than for every run: where the shell lives,
+    // what kind it is, and the environment it gets (the whole of it when there's a snapshot)
+    program: PathBuf,
+    kind: ShellKind,
+    spawn_env: HashMap<String, String>,
+}
+
+impl ShellEnv {
+    fn from_config(cfg: &ConfigFile) -> ShellEnv {
+        let snapshot = if cfg.snapshot_env { snapshot_environment(&cfg.default_shell) } else { None };
+        let mut env = ShellEnv {
+            shell: cfg.default_shell.clone(), login: cfg.login_shell, snapshot, overrides: cfg.env.clone(), vars: HashMap::new(), profile_env: HashMap::new(),
+            program: PathBuf::new(), kind: ShellKind::Posix, spawn_env: HashMap::new(),
+        };
+        env.use_profile(cfg);
+        env
+    }
+
+    // The config changed on disk: a different shell or `snapshot-env` means a new snapshot.
+    // Overrides made in the Environment panel are kept for the session
+    fn reload(&mut self, before: &ConfigFile, cfg: &ConfigFile) {
+        if !cfg.snapshot_env { self.snapshot = None; }
+        else if !before.snapshot_env || cfg.default_shell != before.default_shell { self.snapshot = snapshot_environment(&cfg.default_shell); }
+        self.login = cfg.login_shell;
+        self.use_profile(cfg);
+    }
+
+    fn use_profile(&mut self, cfg: &ConfigFile) {
+        let profile = cfg.profile.as_ref().and_then(|p| cfg.profiles.get(p)).cloned().unwrap_or_default();
+        self.shell = profile.shell.unwrap_or_else(|| cfg.default_shell.clone());
+        self.vars = profile.vars;
+        self.profile_env = profile.env;
+        self.resolve();
+    }
+
+    // Call after changing any of the fields above
+    fn resolve(&mut self) {
+        let mut vars = self.snapshot.clone().unwrap_or_default();
+        vars.extend(self.overrides.iter().map(|(k, v)| (k.clone(), v.clone())));
+        vars.extend(self.profile_env.iter().map(|(k, v)| (k.clone(), v.clone())));
+        self.spawn_env = vars;
+        self.program = find_program(&self.shell, &self.path_var()).unwrap_or_else(|| PathBuf::from(&self.shell));
+        self.kind = shell_kind(&self.shell);
+    }
+
+    // The PATH commands are run with
+    fn path_var(&self) -> String {
+        self.spawn_env.get("PATH").cloned().unwrap_or_else(|| std::env::var("PATH").unwrap_or_default())
+    }
+
+    // What a spawned command will see, sorted by name
+    fn effective(&self) -> Vec<(String, String)> {
+        let mut vars: HashMap<String, String> = match &self.snapshot {
+            Some(_) => HashMap::new(),
+            None => std::env::vars().collect(),
+        };
+        vars.extend(self.spawn_env.iter().map(|(k, v)| (k.clone(), v.clone())));
+        let mut vars: Vec<(String, String)> = vars.into_iter().collect();
+        vars.sort();
+        vars
+    }
+}
+
+const ENV_MARKER: &str = "__tuish_env__";
+
+// Run the shell once as an interactive login shell and capture what `env` sees there.
+// rc files may print things, so everything before the marker is thrown away
+fn snapshot_environment(shell: &str) -> Option<HashMap<String, String>> {
+    let script = format!("printf '{}\\0'; env -0", ENV_MARKER);
+    let out = Command::new(shell).arg("-l").arg("-i").arg("-c").arg(script)
+        .stdin(Stdio::null()).stderr(Stdio::null()).output().ok()?;
+    let text = String::from_utf8_lossy(&out.stdout);
+    let (_, env) = text.split_once(&format!("{}\0", ENV_MARKER))?;
+    let vars: HashMap<String, String> = env.split('\0')
+        .filter_map(|kv| kv.split_once('='))
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    if vars.is_empty() { None } else { Some(vars) }
+}
+
+// Build the invocation for running `cmd` through the shell. fish parses its options differently
+// from sh (no combined `-lc`, no sh-only flags), so everything that spawns a command goes through here
+fn shell_command(env: &ShellEnv, cmd: &str) -> Command {
+    let mut c = interactive_shell(env);
+    match env.kind {
+        ShellKind::Fish => { c.arg("--command").arg(cmd); }
+        ShellKind::Posix => { c.arg("-c").arg(cmd); }
+    }
+    c
+}
+
+// The shell itself with the configured environment, as used by "Go to shell"
+fn interactive_shell(env: &ShellEnv) -> Command {
+    let mut c = Command::new(&env.program);
+    if env.snapshot.is_some() { c.env_clear(); }
+    c.envs(&env.spawn_env);
+    if env.login { c.arg("-l"); }
+    c
+}
+
+// Turn an alias name like "Example Shell" into something usable as a shell identifier
+fn export_name(name: &str) -> String {
+    let mut out = String::new();
+    for ch in name.trim().chars() {
+        if ch.is_ascii_alphanumeric() || ch == '_' || ch == '-' { out.push(ch.to_ascii_lowercase()); }
+        else if ch.is_whitespace() && !out.ends_with('-') { out.push('-'); }
+    }
+    out.trim_matches('-').to_string()
+}
+
+fn sh_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+fn fish_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+// Render the aliases as definitions for the given shell, sorted by name so the output is stable
+fn export_aliases(aliases: &[Alias], kind: ShellKind, abbr: bool) -> String {
+    let mut sorted: Vec<&Alias> = aliases.iter().collect();
+    sorted.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut out = String::from("# generated by tuish export\n");
+    for a in sorted {
+        let name = export_name(&a.name);
+        if name.is_empty() {
+            out.push_str(&format!("# skipped {:?}: name has no usable characters\n", a.name));
+            continue;
+        }
+        let quote = if kind == ShellKind::Fish { fish_quote } else { sh_quote };
+        let command = match (&a.container, a.kind) {
+            (_, AliasKind::Cd) => format!("cd {}", quoted_dir(&a.command, quote)),
+            (Some(c), _) => match &a.cwd {
+                Some(dir) => format!("docker exec -it -w {} {} sh -c {}", sh_quote(dir), c, sh_quote(&a.command)),
+                None => format!("docker exec -it {} sh -c {}", c, sh_quote(&a.command)),
+            },
+            (None, _) => a.command.clone(),
+        };
+        match kind {
+            ShellKind::Fish if abbr => out.push_str(&format!("abbr -a {} {}\n", name, fish_quote(&command))),
+            ShellKind::Fish => out.push_str(&format!("function {} --description {}\n    {} $argv\nend\n", name, fish_quote(&a.name), command)),
+            ShellKind::Posix => out.push_str(&format!("alias {}={}\n", name, sh_quote(&command))),
+        }
+    }
+    out
+}
+
+// A bookmark's directory for `cd`, quoted but with a leading ~ left outside so the shell still
+// takes it for the home directory
+fn quoted_dir(path: &str, quote: fn(&str) -> String) -> String {
+    match path.trim().strip_prefix('~').map(|rest| rest.trim_start_matches('/')) {
+        Some("") => "~".to_string(),
+        Some(rest) if path.trim().starts_with("~/") => format!("~/{}", quote(rest)),
+        _ => quote(path.trim()),
+    }
+}
+
+// Set by the `tuish init` wrapper; a bookmark writes its directory here and tuish exits
+const CD_FILE_VAR: &str = "TUISH_CD_FILE";
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix('~'), dirs::home_dir()) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => home.join(rest.trim_start_matches('/')),
+        _ => PathBuf::from(path),
+    }
+}
+
+fn bookmark_dir(path: &str) -> Result<PathBuf, String> {
+    let dir = expand_home(path.trim());
+    if dir.is_dir() { Ok(dir) } else { Err(trf("Not a directory: {}", &[&dir.display()])) }
+}
+
+// Shell function that wraps tuish so bookmarks can change the calling shell's directory
+fn shell_wrapper(kind: ShellKind) -> String {
+    let exe = std::env::current_exe().map(|p| p.to_string_lossy().to_string()).unwrap_or_else(|_| "tuish".to_string());
+    match kind {
+        ShellKind::Fish => format!(
+            "function tuish\n    set -l cd_file (mktemp)\n    env {var}=$cd_file {exe} $argv\n    if test -s $cd_file\n        cd (cat $cd_file)\n    end\n    rm -f $cd_file\nend\n",
+            var = CD_FILE_VAR, exe = fish_quote(&exe)),
+        ShellKind::Posix => format!(
+            "tuish() {{\n    local cd_file\n    cd_file=\"$(mktemp)\"\n    {var}=\"$cd_file\" {exe} \"$@\"\n    if [ -s \"$cd_file\" ]; then cd \"$(cat \"$cd_file\")\"; fi\n    rm -f \"$cd_file\"\n}}\n",
+            var = CD_FILE_VAR, exe = sh_quote(&exe)),
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(path: &std::path::Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0).unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &std::path::Path) -> bool {
+    path.is_file()
+}
+
+fn path_executables(prefix: &str, path_var: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    for dir in std::env::split_paths(path_var) {
+        let Ok(entries) = fs::read_dir(&dir) else { continue };
+        for e in entries.flatten() {
+            let name = e.file_name().to_string_lossy().to_string();
+            if name.starts_with(prefix) && is_executable(&e.path()) { out.push(name); }
+        }
+    }
+    out
+}
+
+// Paths completing `token`, keeping whatever directory part was typed (including `~/`)
+fn path_candidates(token: &str, dirs_only: bool) -> Vec<String> {
+    let (dir_part, file_prefix) = match token.rfind('/') {
+        Some(i) => (&token[..=i], &token[i+1..]),
+        None => ("", token),
+    };
+    let dir = if dir_part.is_empty() { PathBuf::from(".") } else { expand_home(dir_part) };
+    let Ok(entries) = fs::read_dir(&dir) else { return Vec::new() };
+    let mut out = Vec::new();
+    for e in entries.flatten() {
+        let name = e.file_name().to_string_lossy().to_string();
+        // hidden files only when asked for explicitly
+        if !name.starts_with(file_prefix) || (name.starts_with('.') && !file_prefix.starts_with('.')) { continue; }
+        let is_dir = e.path().is_dir();
+        if dirs_only && !is_dir { continue; }
+        out.push(format!("{}{}{}", dir_part, name, if is_dir { "/" } else { "" }));
+    }
+    out
+}
+
+fn common_prefix(items: &[String]) -> String {
+    let mut prefix: Vec<char> = items[0].chars().collect();
+    for item in &items[1..] {
+        let n = prefix.iter().zip(item.chars()).take_while(|(a, b)| **a == *b).count();
+        prefix.truncate(n);
+    }
+    prefix.into_iter().collect()
+}
+
+// Complete the last word of `input`: program names from PATH for the first word, file paths after that.
+// Returns the new input and, when it was ambiguous, the candidates to show
+fn complete_input(input: &str, path_var: &str, dirs_only: bool) -> (String, Vec<String>) {
+    let start = input.char_indices().rev().find(|(_, c)| c.is_whitespace()).map(|(i, c)| i + c.len_utf8()).unwrap_or(0);
+    let (head, token) = input.split_at(start);
+    let mut candidates = if head.trim().is_empty() && !token.contains('/') && !dirs_only {
+        path_executables(token, path_var)
+    } else {
+        path_candidates(token, dirs_only)
+    };
+    candidates.sort();
+    candidates.dedup();
+    match candidates.len() {
+        0 => (input.to_string(), Vec::new()),
+        1 => {
+            let suffix = if candidates[0].ends_with('/') { "" } else { " " };
+            (format!("{}{}{}", head, candidates[0], suffix), Vec::new())
+        }
+        _ => (format!("{}{}", head, common_prefix(&candidates)), candidates),
+    }
+}
+
+const SHELL_BUILTINS: &[&str] = &[
+    ".", ":", "[", "alias", "bg", "builtin", "cd", "command", "echo", "eval", "exec", "exit", "export",
+    "false", "fg", "for", "function", "if", "jobs", "kill", "printf", "pwd", "read", "set", "source",
+    "test", "then", "time", "true", "type", "ulimit", "umask", "unset", "wait", "while", "{", "(",
+];
+
+// The program a command line starts with, skipping `VAR=value` prefixes
+fn command_program(command: &str) -> Option<&str> {
+    command.split_whitespace().find(|w| !w.contains('=') || w.starts_with('='))
+}
+
+// Whether the alias can run as far as we can tell from here. Container aliases resolve
+// inside the container, so they are never flagged
+fn alias_resolves(alias: &Alias, path_var: &str) -> bool {
+    if alias.kind == AliasKind::Cd { return expand_home(alias.command.trim()).is_dir(); }
+    if alias.container.is_some() { return true; }
+    let Some(prog) = command_program(&alias.command) else { return true };
+    if SHELL_BUILTINS.contains(&prog) { return true; }
+    find_program(prog, path_var).is_some()
+}
+
+// Where `prog` would be run from, the way the shell would look it up
+fn find_program(prog: &str, path_var: &str) -> Option<PathBuf> {
+    if prog.contains('/') { return Some(expand_home(prog)).filter(|p| is_executable(p)); }
+    std::env::split_paths(path_var).map(|dir| dir.join(prog)).find(|p| is_executable(p))
+}
+
+// Edits between two names, counting a swap of neighbouring letters as one ("dokcer" is 1 from "docker")
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() { row[0] = i; }
+    for (j, cell) in d[0].iter_mut().enumerate() { *cell = j; }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i-1] != b[j-1]);
+            d[i][j] = (d[i-1][j] + 1).min(d[i][j-1] + 1).min(d[i-1][j-1] + cost);
+            if i > 1 && j > 1 && a[i-1] == b[j-2] && a[i-2] == b[j-1] {
+                d[i][j] = d[i][j].min(d[i-2][j-2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+// The program on PATH closest to a mistyped one, if any is close enough to be a typo
+fn did_you_mean(prog: &str, path_var: &str) -> Option<String> {
+    let limit = (prog.chars().count() / 3).max(1);
+    let mut names = path_executables("", path_var);
+    names.sort();
+    names.dedup();
+    names.into_iter().map(|n| (edit_distance(prog, &n), n)).filter(|(d, _)| *d <= limit).min_by_key(|(d, _)| *d).map(|(_, n)| n)
+}
+
+fn missing_warning(alias: &Alias) -> Option<String> {
+    if !alias.missing { return None; }
+    match alias.kind {
+        AliasKind::Cd => Some(trf("Saved, but {} is not a directory", &[&alias.command])),
+        AliasKind::Command => Some(trf("Saved, but '{}' was not found on PATH", &[&command_program(&alias.command).unwrap_or("")])),
+    }
+}
+
+fn check_aliases(aliases: &mut [Alias], path_var: &str) {
+    for a in aliases.iter_mut() {
+        a.missing = !alias_resolves(a, path_var);
+    }
+}
+
+// The startup check runs in the background, the list shows up right away and gets its missing
+// marks as the batches come in. From this many aliases (machine-generated configs) the title
+// shows how far it got
+const BACKGROUND_CHECK_MIN: usize = 2000;
+const CHECK_BATCH: usize = 500;
+
+// check_aliases in a thread, sending back (name, missing) a batch at a time
+fn check_in_background(aliases: &[Alias], path_var: &str) -> Receiver<Vec<(String, bool)>> {
+    let (tx, rx) = channel();
+    let aliases = aliases.to_vec();
+    let path_var = path_var.to_string();
+    std::thread::spawn(move || {
+        for batch in aliases.chunks(CHECK_BATCH) {
+            let checked = batch.iter().map(|a| (a.name.clone(), !alias_resolves(a, &path_var))).collect();
+            if tx.send(checked).is_err() { return; }
+        }
+    });
+    rx
+}
+
+fn aliases_from(entries: &HashMap<String, AliasEntry>, repo: bool) -> Vec<Alias> {
+    entries.iter().map(|(name, entry)| Alias {
+        name: name.clone(),
+        command: entry.command.clone(),
+        keybind: entry.keybind.as_deref().and_then(KeyBind::parse),
+        container: entry.container.clone(),
+        kind: entry.kind,
+        run_in: entry.run_in,
+        pause: entry.pause.clone(),
+        after: entry.after,
+        params: entry.params.clone(),
+        secret_values: HashMap::new(),
+        missing: false,
+        repo,
+        bundle: None,
+        every: entry.every.clone(),
+        cwd: entry.cwd.clone(),
+        group: entry.group.clone(),
+    }).collect()
+}
+
+// Move tuish to `dir`. Repository aliases belong to wherever we are, so they're swapped for the new ones
+fn enter_dir(dir: &std::path::Path, aliases: &mut Vec<Alias>, cfg: &mut ConfigFile, path_var: &str) -> std::io::Result<()> {
+    std::env::set_current_dir(dir)?;
+    aliases.retain(|a| !a.repo);
+    cfg.repo_file = repo_alias_file();
+    if let Some(file) = &cfg.repo_file {
+        aliases.splice(0..0, load_repo_aliases(file));
+    }
+    check_aliases(aliases, path_var);
+    Ok(())
+}
+
+// Where the active profile wants tuish to be, if it says
+fn profile_cwd(cfg: &ConfigFile) -> Option<PathBuf> {
+    cfg.profile.as_ref().and_then(|p| cfg.profiles.get(p)).and_then(|p| p.cwd.as_deref()).map(expand_home)
+}
+
+// After the active profile changed from one whose `cwd` was `old`: into the new one's, or back to
+// where tuish started when only the old one had a directory. Ok(true) when it moved
+fn enter_profile_dir(old: Option<PathBuf>, start_dir: Option<&std::path::Path>, aliases: &mut Vec<Alias>, cfg: &mut ConfigFile, path_var: &str) -> Result<bool, String> {
+    let dir = match (old, profile_cwd(cfg), start_dir) {
+        (_, Some(new), _) => new,
+        (Some(_), None, Some(start)) => start.to_path_buf(),
+        _ => return Ok(false),
+    };
+    enter_dir(&dir, aliases, cfg, path_var).map_err(|e| trf("Couldn't change to {}: {}", &[&dir.display(), &e]))?;
+    Ok(true)
+}
+
+// Swap the user's own aliases for the ones in `cfg`, repository and bundle aliases stay
+fn replace_user_aliases(aliases: &mut Vec<Alias>, cfg: &ConfigFile, path_var: &str) {
+    aliases.retain(|a| a.repo || a.bundle.is_some());
+    let at = aliases.iter().filter(|a| a.repo).count();
+    aliases.splice(at..at, load_aliases(cfg));
+    check_aliases(aliases, path_var);
+}
+
+fn load_aliases(cfg: &ConfigFile) -> Vec<Alias> {
+    aliases_from(&cfg.aliases, false)
+}
+
+// `.git/tuish.json` of the repository containing the cwd, if there is one
+fn repo_alias_file() -> Option<PathBuf> {
+    let git_dir = command_output("git", &["rev-parse", "--absolute-git-dir"])?;
+    let file = PathBuf::from(git_dir).join("tuish.json");
+    if file.is_file() { Some(file) } else { None }
+}
+
+fn load_repo_aliases(file: &PathBuf) -> Vec<Alias> {
+    let data = fs::read_to_string(file).unwrap_or_default();
+    let repo: RepoFile = serde_json::from_str(&data).unwrap_or_default();
+    aliases_from(&repo.aliases, true)
+}
+
+// Where a bundle's last download is kept, bundles/<name>.json next to the config
+fn bundle_cache(cfg_path: &std::path::Path, name: &str) -> PathBuf {
+    let safe: String = name.chars().map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' }).collect();
+    cfg_path.with_file_name("bundles").join(format!("{}.json", safe))
+}
+
+// Download a bundle with curl and cache it, but only once it's known to parse
+fn fetch_bundle(url: &str, cache: &std::path::Path) -> Result<(), String> {
+    let out = Command::new("curl").args(["-fsSL", "--max-time", "15", url]).output()
+        .map_err(|_| tr("curl is not installed").to_string())?;
+    if !out.status.success() {
+        return Err(format!("{}: {}", url, String::from_utf8_lossy(&out.stderr).trim()));
+    }
+    serde_json::from_slice::<RepoFile>(&out.stdout).map_err(|e| trf("{}: not an alias bundle ({})", &[&url, &e]))?;
+    if let Some(dir) = cache.parent() { fs::create_dir_all(dir).ok(); }
+    fs::write(cache, &out.stdout).map_err(|e| trf("can't write {}: {}", &[&cache.display(), &e]))
+}
+
+// Aliases of every subscribed bundle from the cache. `refresh` downloads them all again,
+// otherwise only the ones that were never downloaded. Failed downloads come back as errors
+fn load_bundles(cfg: &ConfigFile, cfg_path: &std::path::Path, refresh: bool) -> (Vec<Alias>, Vec<String>) {
+    let mut names: Vec<&String> = cfg.bundles.keys().collect();
+    names.sort();
+    let mut aliases = Vec::new();
+    let mut errors = Vec::new();
+    for name in names {
+        let cache = bundle_cache(cfg_path, name);
+        if (refresh || !cache.exists()) && let Err(e) = fetch_bundle(&cfg.bundles[name], &cache) {
+            errors.push(e);
+        }
+        let data = fs::read_to_string(&cache).unwrap_or_default();
+        let Ok(file) = serde_json::from_str::<RepoFile>(&data) else { continue };
+        let mut loaded = aliases_from(&file.aliases, false);
+        loaded.sort_by(|a, b| a.name.cmp(&b.name));
+        aliases.extend(loaded.into_iter().map(|a| Alias { bundle: Some(name.clone()), ..a }));
+    }
+    (aliases, errors)
+}
+
+// One alias as a line of text to paste to someone: "tuish:" and the alias as base64 JSON
+#[derive(Serialize, Deserialize)]
+struct SharedAlias {
+    name: String,
+    #[serde(flatten)]
+    entry: AliasEntry,
+}
+
+const SHARE_PREFIX: &str = "tuish:";
+
+fn share_string(alias: &Alias) -> String {
+    let shared = SharedAlias { name: alias.name.clone(), entry: alias_entry(alias) };
+    let json = serde_json::to_string(&shared).unwrap_or_default();
+    format!("{}{}", SHARE_PREFIX, base64_with(json.as_bytes(), BASE64_URL_CHARS, false))
+}
+
+// Add the alias a share string stands for. The name gets a number if it's taken and the keybind
+// is dropped if it clashes with one of ours. Returns what was added
+fn import_shared(aliases: &mut Vec<Alias>, text: &str) -> Result<String, String> {
+    let code = text.trim().strip_prefix(SHARE_PREFIX).ok_or(tr("That's not a tuish share string"))?;
+    let data = base64_decode(code).ok_or(tr("The share string is damaged"))?;
+    let shared: SharedAlias = serde_json::from_slice(&data).map_err(|_| tr("The share string is damaged").to_string())?;
+    let mut name = shared.name.trim().to_string();
+    let mut n = 2;
+    while aliases.iter().any(|a| a.name == name) {
+        name = format!("{} {}", shared.name.trim(), n);
+        n += 1;
+    }
+    let mut alias = aliases_from(&HashMap::from([(name.clone(), shared.entry)]), false).remove(0);
+    let mut note = String::new();
+    if let Some(kb) = alias.keybind && keybind_conflict(aliases, kb, alias.group.as_deref(), usize::MAX).is_some() {
+        alias.keybind = None;
+        note = trf(" (without its keybind {}, you already use it)", &[&kb]);
+    }
+    aliases.push(alias);
+    Ok(trf("Added '{}'{}", &[&name, &note]))
+}
+
+// Why the alias can't be changed, if it can't
+fn read_only_reason(alias: &Alias) -> Option<String> {
+    alias.bundle.as_ref().map(|b| trf("'{}' comes from the '{}' bundle and is read-only", &[&alias.name, b]))
+}
+
+fn git_branch() -> Option<String> {
+    command_output("git", &["rev-parse", "--abbrev-ref", "HEAD"])
+}
+
+// How often the header widgets other than the branch are read again
+const HEADER_TICK: Duration = Duration::from_secs(5);
+
+// What the header widgets show, None for widgets that are off or can't be read here
+#[derive(Default, PartialEq)]
+struct HeaderStatus {
+    branch: Option<String>,
+    // 1 minute load average and the number of CPUs
+    load: Option<(f64, usize)>,
+    // used and total, in kB
+    memory: Option<(u64, u64)>,
+    // percent and whether it's charging
+    battery: Option<(u8, bool)>,
+}
+
+fn read_header_status(widgets: &[String]) -> HeaderStatus {
+    let on = |w: &str| widgets.iter().any(|x| x == w);
+    HeaderStatus {
+        branch: if on("branch") { git_branch() } else { None },
+        load: if on("load") { read_load() } else { None },
+        memory: if on("memory") { read_memory() } else { None },
+        battery: if on("battery") { read_battery() } else { None },
+    }
+}
+
+fn read_load() -> Option<(f64, usize)> {
+    let loadavg = fs::read_to_string("/proc/loadavg").ok()?;
+    let cpus = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    Some((loadavg.split_whitespace().next()?.parse().ok()?, cpus))
+}
+
+fn read_memory() -> Option<(u64, u64)> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+    let field = |name: &str| meminfo.lines().find(|l| l.starts_with(name))?.split_whitespace().nth(1)?.parse::<u64>().ok();
+    let total = field("MemTotal:")?;
+    Some((total.saturating_sub(field("MemAvailable:")?), total))
+}
+
+fn read_battery() -> Option<(u8, bool)> {
+    let dir = fs::read_dir("/sys/class/power_supply").ok()?
+        .flatten().map(|e| e.path())
+        .find(|p| p.file_name().is_some_and(|n| n.to_string_lossy().starts_with("BAT")))?;
+    let percent = fs::read_to_string(dir.join("capacity")).ok()?.trim().parse().ok()?;
+    let charging = fs::read_to_string(dir.join("status")).map(|s| s.trim() == "Charging").unwrap_or(false);
+    Some((percent, charging))
+}
+
+// Reads the widgets every HEADER_TICK on its own thread, so a slow git never holds up a key press
+fn header_ticker(widgets: Vec<String>) -> Receiver<HeaderStatus> {
+    let (tx, rx) = channel();
+    std::thread::spawn(move || {
+        while tx.send(read_header_status(&widgets)).is_ok() {
+            std::thread::sleep(HEADER_TICK);
+        }
+    });
+    rx
+}
+
+// "90s", "15m", "2h" or "1d"
+fn parse_interval(text: &str) -> Option<Duration> {
+    let text = text.trim();
+    let unit = text.chars().last()?;
+    let n: u64 = text[..text.len() - unit.len_utf8()].trim().parse().ok()?;
+    let secs = match unit {
+        's' => n,
+        'm' => n.checked_mul(60)?,
+        'h' => n.checked_mul(60 * 60)?,
+        'd' => n.checked_mul(24 * 60 * 60)?,
+        _ => return None,
+    };
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+// How often the scheduler runs `a`, if it may run it unattended at all. Nobody is there to confirm
+// a dangerous command, and bundle and repo aliases come from someone else, so they only run when asked
+fn schedule_interval(a: &Alias, danger_patterns: &[String]) -> Option<Duration> {
+    if a.bundle.is_some() || a.repo { return None; }
+    if a.kind != AliasKind::Command || !placeholders(&a.command).is_empty() || is_dangerous(&a.command, danger_patterns) { return None; }
+    a.every.as_deref().and_then(parse_interval)
+}
+
+// Seconds east of UTC, from `date` once at startup since std has no time zones
+fn utc_offset() -> i64 {
+    let Some(z) = command_output("date", &["+%z"]) else { return 0 };
+    let sign = if z.starts_with('-') { -1 } else { 1 };
+    let digits = z.trim_start_matches(['+', '-']);
+    match (digits.get(..2).and_then(|h| h.parse::<i64>().ok()), digits.get(2..4).and_then(|m| m.parse::<i64>().ok())) {
+        (Some(h), Some(m)) => sign * (h * 3600 + m * 60),
+        _ => 0,
+    }
+}
+
+fn clock_text(offset: i64) -> String {
+    let secs = (unix_now() as i64 + offset).rem_euclid(24 * 60 * 60);
+    format!("{:02}:{:02}", secs / 3600, secs / 60 % 60)
+}
+
+// "5m ago", "3h ago" or "2d ago"
+fn age_text(secs: u64) -> String {
+    match secs {
+        s if s < 60 * 60 => trf("{}m ago", &[&(s / 60)]),
+        s if s < 24 * 60 * 60 => trf("{}h ago", &[&(s / (60 * 60))]),
+        s => trf("{}d ago", &[&(s / (24 * 60 * 60))]),
+    }
+}
+
+// "4:05" or "1:04:05"
+fn format_countdown(left: Duration) -> String {
+    let secs = left.as_secs();
+    if secs >= 3600 { format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60) } else { format!("{}:{:02}", secs / 60, secs % 60) }
+}
+
+// Which job a mode is showing, to keep pointing at the same one when the list changes
+fn shown_job(mode: &mut UiMode) -> Option<&mut usize> {
+    match mode {
+        UiMode::Output { job } | UiMode::SaveOutput { job, .. } | UiMode::Links { job, .. } => Some(job),
+        // what comes back after unlocking has to point at the right job too
+        UiMode::Locked { previous, .. } => shown_job(previous),
+        _ => None,
+    }
+}
+
+// Whether keys go into the lock screen's passphrase or a secret parameter right now
+fn typing_secret(mode: &UiMode) -> bool {
+    match mode {
+        UiMode::Locked { .. } => true,
+        UiMode::Params { alias, names, field, .. } => names.get(*field).and_then(|n| alias.params.get(n)).is_some_and(|p| p.secret),
+        _ => false,
+    }
+}
+
+// "  load 0.52  mem 3.1/15.6G  bat 87%+  git:main", red where it needs a look
+fn header_widgets(status: &HeaderStatus, widgets: &[String]) -> Vec<Span<'static>> {
+    let mut spans = Vec::new();
+    for widget in widgets {
+        let (text, warn) = match (widget.as_str(), status) {
+            ("load", HeaderStatus { load: Some((load, cpus)), .. }) => (format!("load {:.2}", load), *load >= *cpus as f64),
+            ("memory", HeaderStatus { memory: Some((used, total)), .. }) => {
+                let gib = |kb: u64| kb as f64 / (1024.0 * 1024.0);
+                (format!("mem {:.1}/{:.1}G", gib(*used), gib(*total)), *used * 10 > *total * 9)
+            }
+            ("battery", HeaderStatus { battery: Some((percent, charging)), .. }) => {
+                (format!("bat {}%{}", percent, if *charging { "+" } else { "" }), *percent < 20 && !charging)
+            }
+            ("branch", HeaderStatus { branch: Some(b), .. }) => (format!("git:{}", b), false),
+            _ => continue,
+        };
+        let color = if warn { theme().error } else if widget == "branch" { theme().ok } else { theme().dim };
+        spans.push(Span::styled(format!("  {}", text), Style::default().fg(color)));
+    }
+    spans
+}
+
+// Non-interactive entry points, e.g. `tuish export fish > ~/.config/fish/conf.d/tuish.fish`
+fn run_cli(args: &[String], cfg_path: &PathBuf) -> i32 {
+    let mut cfg = ensure_config(cfg_path);
+    if let Err(e) = unlock_config(&mut cfg, cfg_path) {
+        eprintln!("tuish: {}", e);
+        return 1;
+    }
+    match args[0].as_str() {
+        "export" => {
+            let abbr = args.iter().any(|a| a == "--abbr");
+            let kind = match args.iter().skip(1).find(|a| !a.starts_with("--")).map(|s| s.as_str()) {
+                Some("fish") => ShellKind::Fish,
+                Some("bash") | Some("zsh") | Some("sh") => ShellKind::Posix,
+                Some(other) => { eprintln!("tuish: unknown shell '{}' (expected bash, zsh or fish)", other); return 2; }
+                None => shell_kind(&cfg.default_shell),
+            };
+            print!("{}", export_aliases(&load_aliases(&cfg), kind, abbr));
+            0
+        }
+        "init" => {
+            let kind = match args.get(1).map(|s| s.as_str()) {
+                Some("fish") => ShellKind::Fish,
+                Some(_) => ShellKind::Posix,
+                None => shell_kind(&cfg.default_shell),
+            };
+            print!("{}", shell_wrapper(kind));
+            0
+        }
+        "secret" => {
+            let (Some(verb), Some(name)) = (args.get(1).map(|s| s.as_str()), args.get(2)) else {
+                eprintln!("usage: tuish secret set|rm <name>");
+                return 2;
+            };
+            let entry = match keyring::Entry::new(KEYRING_SERVICE, name) {
+                Ok(e) => e,
+                Err(e) => { eprintln!("tuish: keyring: {}", e); return 1; }
+            };
+            let result = match verb {
+                "set" => {
+                    let Some(value) = read_hidden(&format!("Value for secret '{}': ", name)) else { return 1 };
+                    entry.set_password(&value)
+                }
+                "rm" => entry.delete_password(),
+                _ => { eprintln!("usage: tuish secret set|rm <name>"); return 2; }
+            };
+            match result {
+                Ok(()) => 0,
+                Err(e) => { eprintln!("tuish: keyring: {}", e); 1 }
+            }
+        }
+        "share" => {
+            let Some(name) = args.get(1) else {
+                eprintln!("usage: tuish share <name>");
+                return 2;
+            };
+            match load_aliases(&cfg).iter().find(|a| a.name == *name) {
+                Some(a) => { println!("{}", share_string(a)); 0 }
+                None => { eprintln!("tuish: no alias named '{}'", name); 1 }
+            }
+        }
+        "import" => {
+            let Some(code) = args.get(1) else {
+                eprintln!("usage: tuish import <tuish:... share string>");
+                return 2;
+            };
+            let mut aliases = load_aliases(&cfg);
+            match import_shared(&mut aliases, code) {
+                Ok(msg) => { write_config(cfg_path, &aliases, &cfg); println!("{}", msg); 0 }
+                Err(e) => { eprintln!("tuish: {}", e); 1 }
+            }
+        }
+        "backup" => {
+            match backup_config(cfg_path, args.get(1).map(PathBuf::from), false) {
+                Ok(file) => { println!("Backed up to {}", file.display()); 0 }
+                Err(e) => { eprintln!("tuish: {}", e); 1 }
+            }
+        }
+        "restore" => {
+            let Some(file) = args.get(1) else {
+                eprintln!("usage: tuish restore <backup file>");
+                return 2;
+            };
+            match restore_config(cfg_path, std::path::Path::new(file)) {
+                Ok(before) => { println!("Restored {} (what was there before is in {})", file, before.display()); 0 }
+                Err(e) => { eprintln!("tuish: {}", e); 1 }
+            }
+        }
+        "sync" => {
+            let Some(remote) = &cfg.sync_remote else {
+                eprintln!("tuish: set \"sync-remote\" in {} to a git remote first", cfg_path.display());
+                return 2;
+            };
+            match sync_config(cfg_path, remote) {
+                Ok((log, _)) => { for l in log { println!("{}", l); } 0 }
+                Err(lines) => { for l in lines { eprintln!("{}", l); } 1 }
+            }
+        }
+        "rename" => {
+            let (Some(old), Some(new)) = (args.get(1), args.get(2)) else {
+                eprintln!("usage: tuish rename <old name> <new name>");
+                return 2;
+            };
+            let mut aliases = load_aliases(&cfg);
+            let Some(index) = aliases.iter().position(|a| a.name == *old) else {
+                eprintln!("tuish: no alias named '{}'", old);
+                return 1;
+            };
+            let before = aliases[index].clone();
+            if let Err(e) = rename_alias(&mut aliases, index, new) {
+                eprintln!("tuish: {}", e);
+                return 1;
+            }
+            write_config(cfg_path, &aliases, &cfg);
+            let state_file = state_path(cfg_path);
+            let mut state = load_state(&state_file);
+            state.remember_version(&before);
+            state.rename(old, new.trim());
+            write_state(&state_file, &state);
+            0
+        }
+        other => {
+            eprintln!("tuish: unknown command '{}'", other);
+            eprintln!("usage: tuish [export [bash|zsh|fish] [--abbr] | init [bash|zsh|fish] | rename <old> <new> | secret set|rm <name> | sync | share <name> | import <string> | backup [file] | restore <file> | popup | --bench | --record <file> | --replay <file>]");
+            2
+        }
+    }
+}
+
+// `tuish popup`, for a window manager or tmux hotkey: a tmux popup over the current pane when in
+// tmux, else a new `popup-terminal` window, else just here. The config isn't unlocked for this,
+// the popup asks for the passphrase itself
+fn open_popup(cfg: &ConfigFile) -> i32 {
+    let exe = std::env::current_exe().map(|p| p.display().to_string()).unwrap_or_else(|_| "tuish".to_string());
+    let opened = if std::env::var_os("TMUX").is_some() {
+        Command::new("tmux").args(["display-popup", "-E", "-w", "90%", "-h", "80%", &exe, "--popup"]).status().map(|_| ())
+    } else if let Some(term) = &cfg.popup_terminal {
+        // the window lives on after we're gone, so don't wait for it
+        Command::new("sh").arg("-c").arg(format!("{} {} --popup", term, sh_quote(&exe)))
+            .stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null()).spawn().map(|_| ())
+    } else {
+        Command::new(&exe).arg("--popup").status().map(|_| ())
+    };
+    match opened {
+        Ok(()) => 0,
+        Err(e) => { eprintln!("tuish: can't open the popup: {}", e); 1 }
+    }
+}
+
+// Aliases are keyed by name in the config, so the new one has to be free. The next write_config
+// stores it under the new key (in whichever file it came from) and drops the old one
+fn rename_alias(aliases: &mut [Alias], index: usize, new_name: &str) -> Result<(), String> {
+    let new_name = new_name.trim();
+    if new_name.is_empty() { return Err(tr("Name can't be empty").to_string()); }
+    if aliases.iter().enumerate().any(|(i, a)| i != index && a.name == new_name) {
+        return Err(trf("There's already an alias named '{}'", &[&new_name]));
+    }
+    aliases[index].name = new_name.to_string();
+    Ok(())
+}
+
+// Commands shorter than this aren't worth an alias
+const MIN_IMPORT_LEN: usize = 12;
+const MAX_IMPORT_ITEMS: usize = 50;
+
+fn history_files() -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    if let Ok(h) = std::env::var("HISTFILE") { files.push(PathBuf::from(h)); }
+    if let Some(home) = dirs::home_dir() {
+        for f in [".bash_history", ".zsh_history"] {
+            let p = home.join(f);
+            if !files.contains(&p) { files.push(p); }
+        }
+    }
+    files
+}
+
+// zsh's extended history prefixes entries with ": <timestamp>:<duration>;"
+fn parse_history_line(line: &str) -> Option<String> {
+    let line = if line.starts_with(": ") {
+        line.split_once(';').map(|(_, cmd)| cmd).unwrap_or("")
+    } else {
+        line
+    };
+    let line = line.trim();
+    // skip bash timestamps and multi-line continuations
+    if line.is_empty() || line.starts_with('#') || line.ends_with('\\') { return None; }
+    Some(line.to_string())
+}
+
+// Most frequent long commands across the known history files, excluding ones that are already aliases
+fn frequent_history_commands(aliases: &[Alias]) -> Vec<(String, usize)> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for f in history_files() {
+        let Ok(bytes) = fs::read(&f) else { continue };
+        for line in String::from_utf8_lossy(&bytes).lines() {
+            if let Some(cmd) = parse_history_line(line) {
+                if cmd.len() < MIN_IMPORT_LEN || aliases.iter().any(|a| a.command == cmd) { continue; }
+                *counts.entry(cmd).or_insert(0) += 1;
+            }
+        }
+    }
+    let mut items: Vec<(String, usize)> = counts.into_iter().collect();
+    items.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    items.truncate(MAX_IMPORT_ITEMS);
+    items
+}
+
+// Suggest a name from the first couple of words, e.g. "git push origin main" -> "git push"
+fn suggest_alias_name(cmd: &str, aliases: &[Alias]) -> String {
+    let words: Vec<&str> = cmd.split_whitespace()
+        .filter(|w| !w.starts_with('-') && !w.contains('/') && !w.contains('='))
+        .take(2)
+        .collect();
+    let base = if words.is_empty() { "imported".to_string() } else { words.join(" ") };
+    let mut name = base.clone();
+    let mut n = 2;
+    while aliases.iter().any(|a| a.name == name) {
+        name = format!("{} {}", base, n);
+        n += 1;
+    }
+    name
+}
+
+// How an alias actually gets spawned: through the local shell, or inside its container
+fn alias_invocation(alias: &Alias, env: &ShellEnv) -> Command {
+    let (command, secrets) = expand_command(&alias.command, env, &alias.secret_values, param_quote(alias, env), template_value);
+    let mut c = match &alias.container {
+        Some(container) => {
+            let mut c = Command::new("docker");
+            // no terminal to attach to when the output is captured
+            let flags = if alias.run_in == RunIn::Pane { "-i" } else { "-it" };
+            c.args(["exec", flags]);
+            // `-e NAME` without a value passes ours along, so secrets stay out of docker's arguments too
+            for (var, _) in &secrets { c.args(["-e", var.as_str()]); }
+            // `cwd` is a directory inside the container, docker changes to it there
+            if let Some(dir) = &alias.cwd { c.args(["-w", dir.as_str()]); }
+            c.args([container.as_str(), "sh", "-c", command.as_str()]);
+            c
+        }
+        None => {
+            let mut c = shell_command(env, &command);
+            if let Some(dir) = &alias.cwd { c.current_dir(expand_home(dir)); }
+            c
+        }
+    };
+    c.envs(secrets);
+    c
+}
+
+// `run-in: window`: the same invocation handed to a terminal emulator to run in a window of its
+// own, environment and directory included, so tuish stays free while it runs
+fn in_new_window(terminal: Option<&str>, command: &Command) -> Result<Command, String> {
+    let terminal = match terminal {
+        Some(t) => t.to_string(),
+        None => std::env::var("TERMINAL").map(|t| format!("{} -e", t))
+            .map_err(|_| tr("Set `terminal` in the config (like \"alacritty -e\") to run aliases in a new window").to_string())?,
+    };
+    let mut words = terminal.split_whitespace();
+    let Some(program) = words.next() else { return Err(tr("`terminal` is empty").to_string()) };
+    let mut c = Command::new(program);
+    c.args(words).arg(command.get_program()).args(command.get_args());
+    for (k, v) in command.get_envs() {
+        match v {
+            Some(v) => { c.env(k, v); }
+            None => { c.env_remove(k); }
+        }
+    }
+    if let Some(dir) = command.get_current_dir() { c.current_dir(dir); }
+    c.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
+    Ok(c)
+}
+
+// The command line an alias stands for, template variables filled in, as you'd type it yourself
+fn resolved_command(alias: &Alias) -> String {
+    // a cd path is quoted as a whole below
+    let command = expand_template(&alias.command, if alias.kind == AliasKind::Cd { str::to_string } else { sh_quote });
+    match (&alias.kind, &alias.container) {
+        (AliasKind::Cd, _) => format!("cd {}", sh_quote(&expand_home(command.trim()).display().to_string())),
+        (_, Some(container)) => match &alias.cwd {
+            Some(dir) => format!("docker exec -it -w {} {} sh -c {}", sh_quote(dir), container, sh_quote(&command)),
+            None => format!("docker exec -it {} sh -c {}", container, sh_quote(&command)),
+        },
+        (_, None) => match &alias.cwd {
+            Some(dir) => format!("(cd {} && {})", sh_quote(&expand_home(dir).display().to_string()), command),
+            None => command,
+        },
+    }
+}
+
+const BASE64_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+// the URL-safe alphabet, what share strings use so they survive chat apps and URLs
+const BASE64_URL_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+fn base64(data: &[u8]) -> String {
+    base64_with(data, BASE64_CHARS, true)
+}
+
+fn base64_with(data: &[u8], chars: &[u8], pad: bool) -> String {
+    let mut out = String::new();
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, b)| n | ((*b as u32) << (16 - 8 * i)));
+        for i in 0..4 {
+            if i <= chunk.len() { out.push(chars[((n >> (18 - 6 * i)) & 63) as usize] as char); } else if pad { out.push('='); }
+        }
+    }
+    out
+}
+
+// Either alphabet, padded or not
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let (mut n, mut bits) = (0u32, 0);
+    for c in text.trim().trim_end_matches('=').bytes() {
+        let v = match c {
+            b'-' => 62,
+            b'_' => 63,
+            _ => BASE64_CHARS.iter().position(|x| *x == c)? as u32,
+        };
+        n = (n << 6) | v;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((n >> bits) as u8);
+            n &= (1 << bits) - 1;
+        }
+    }
+    Some(out)
+}
+
+// Ask the terminal itself to set the clipboard, which works over ssh as long as the
+// terminal supports OSC 52. tmux needs the sequence wrapped to pass it through
+fn osc52_copy(text: &str) -> Result<(), String> {
+    use std::io::Write;
+    let mut seq = format!("\x1b]52;c;{}\x07", base64(text.as_bytes()));
+    if std::env::var_os("TMUX").is_some() {
+        seq = format!("\x1bPtmux;{}\x1b\\", seq.replace('\x1b', "\x1b\x1b"));
+    }
+    let mut out = std::io::stdout();
+    out.write_all(seq.as_bytes()).and_then(|_| out.flush()).map_err(|e| trf("Couldn't copy: {}", &[&e]))
+}
+
+fn copy_to_clipboard(text: &str, mode: &str) -> Result<(), String> {
+    let over_ssh = std::env::var_os("SSH_TTY").is_some() || std::env::var_os("SSH_CONNECTION").is_some();
+    match mode {
+        "osc52" => osc52_copy(text),
+        "system" => system_copy(text),
+        // a clipboard tool on the remote end would copy to the wrong machine
+        _ if over_ssh => osc52_copy(text),
+        _ => system_copy(text).or_else(|_| osc52_copy(text)),
+    }
+}
+
+// Hand `text` to the first clipboard tool that's installed
+fn system_copy(text: &str) -> Result<(), String> {
+    use std::io::Write;
+    let tools: [(&str, &[&str]); 5] = [
+        ("wl-copy", &[]),
+        ("xclip", &["-selection", "clipboard"]),
+        ("xsel", &["--clipboard", "--input"]),
+        ("pbcopy", &[]),
+        ("clip.exe", &[]),
+    ];
+    for (program, args) in tools {
+        let Ok(mut child) = Command::new(program).args(args).stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::null()).spawn() else { continue };
+        if let Some(mut stdin) = child.stdin.take() {
+            let _ = stdin.write_all(text.as_bytes());
+        }
+        // e.g. wl-copy outside of a wayland session, so try the next one
+        if matches!(child.wait(), Ok(s) if s.success()) { return Ok(()); }
+    }
+    Err(tr("Couldn't copy, no working clipboard tool (wl-clipboard, xclip, xsel)").to_string())
+}
+
+// Trimmed stdout of a helper command, None if it failed or printed nothing
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let out = Command::new(program).args(args).stderr(Stdio::null()).output().ok()?;
+    let text = String::from_utf8_lossy(&out.stdout).trim().to_string();
+    if out.status.success() && !text.is_empty() { Some(text) } else { None }
+}
+
+const TEMPLATE_VARS: &[&str] = &["branch", "kube_context", "kube_namespace"];
+
+// Built-in `{{name}}` variables, only looked up when an alias actually uses them
+fn template_value(name: &str) -> Option<String> {
+    match name {
+        "branch" => git_branch(),
+        "kube_context" => command_output("kubectl", &["config", "current-context"]),
+        "kube_namespace" => command_output("kubectl", &["config", "view", "--minify", "-o", "jsonpath={..namespace}"]).or_else(|| Some("default".to_string())),
+        _ => None,
+    }
+}
+
+// Replace the `{{name}}` placeholders `lookup` knows about, leaving the rest untouched
+fn expand_with(command: &str, mut lookup: impl FnMut(&str) -> Option<String>) -> String {
+    let mut out = String::new();
+    let mut rest = command;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start..].find("}}") else { break };
+        let name = rest[start + 2..start + len].trim();
+        out.push_str(&rest[..start]);
+        match lookup(name) {
+            Some(v) => out.push_str(&v),
+            None => out.push_str(&rest[start..start + len + 2]),
+        }
+        rest = &rest[start + len + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+// `{{env:NAME}}` comes from the active profile, so the same alias goes to dev or prod
+// depending on what the header says
+const ENV_PREFIX: &str = "env:";
+
+// `{{env:...}}` names the active profile doesn't set, better not to run with those
+fn missing_profile_vars(command: &str, env: &ShellEnv) -> Vec<String> {
+    braced_names(command).iter()
+        .filter_map(|n| n.strip_prefix(ENV_PREFIX).map(|v| v.trim().to_string()))
+        .filter(|v| !env.vars.contains_key(v))
+        .collect()
+}
+
+// A branch name can hold `$(...)` too, so template values go in quoted like typed parameters
+fn expand_template(command: &str, quote: fn(&str) -> String) -> String {
+    expand_with(command, |name| template_value(name).map(|v| quoted_value(&v, quote)))
+}
+
+// Typed parameter values go in quoted, so they're one argument whatever they contain
+fn fill_placeholders(command: &str, values: &HashMap<String, String>, quote: fn(&str) -> String) -> String {
+    expand_with(command, |name| values.get(name).map(|v| quoted_value(v, quote)))
+}
+
+// Quoted for the shell, with any `{{` split in two ('{''{' is the same argument) so a value
+// like "{{secret:token}}" can't turn into a placeholder when the command is expanded later
+fn quoted_value(value: &str, quote: fn(&str) -> String) -> String {
+    quote(value).replace("{{", "{''{")
+}
+
+// Containers always run `sh -c`, otherwise it's the user's shell
+fn param_quote(alias: &Alias, env: &ShellEnv) -> fn(&str) -> String {
+    if alias.container.is_none() && env.kind == ShellKind::Fish { fish_quote } else { sh_quote }
+}
+
+// Everything filled in right before spawning, profile variables, template variables (from
+// `templates`, quoted with `quote`), secrets and secret parameters, all in one pass so nothing
+// that comes in gets expanded again. Secrets don't
+// go into the command at all, it gets `"$TUISH_SECRET_1"` and so on and the values come back to be
+// set in its environment, so the shell never parses them and they don't show up in `ps`
+const SECRET_VAR: &str = "TUISH_SECRET_";
+
+fn expand_command(command: &str, env: &ShellEnv, secret_values: &HashMap<String, String>, quote: fn(&str) -> String, templates: fn(&str) -> Option<String>) -> (String, Vec<(String, String)>) {
+    let mut secrets = Vec::new();
+    let command = expand_with(command, |name| {
+        if let Some(v) = name.strip_prefix(ENV_PREFIX) { return env.vars.get(v.trim()).cloned(); }
+        if let Some(v) = templates(name) { return Some(quoted_value(&v, quote)); }
+        let value = match name.strip_prefix(SECRET_PREFIX) {
+            Some(s) => secret_value(s.trim()),
+            None => secret_values.get(name).cloned(),
+        }?;
+        let var = format!("{}{}", SECRET_VAR, secrets.len() + 1);
+        let reference = format!("\"${}\"", var);
+        secrets.push((var, value));
+        Some(reference)
+    });
+    (command, secrets)
+}
+
+// Every `{{name}}` in the command, in order of first appearance
+fn braced_names(command: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    let mut rest = command;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start..].find("}}") else { break };
+        let name = rest[start + 2..start + len].trim().to_string();
+        if !name.is_empty() && !names.contains(&name) { names.push(name); }
+        rest = &rest[start + len + 2..];
+    }
+    names
+}
+
+// Placeholders the user has to fill in before running, in order of first appearance
+fn placeholders(command: &str) -> Vec<String> {
+    braced_names(command).into_iter().filter(|n| !TEMPLATE_VARS.contains(&n.as_str()) && !n.starts_with(SECRET_PREFIX) && !n.starts_with(ENV_PREFIX)).collect()
+}
+
+// `{{secret:name}}` comes from the OS keyring (service "tuish", user `name`) and is only filled in
+// on the Command we spawn, so it never ends up in the config, the history or the clipboard
+const SECRET_PREFIX: &str = "secret:";
+const KEYRING_SERVICE: &str = "tuish";
+
+fn secret_value(name: &str) -> Option<String> {
+    keyring::Entry::new(KEYRING_SERVICE, name).ok()?.get_password().ok()
+}
+
+// Secrets the command uses that aren't in the keyring
+fn missing_secrets(command: &str) -> Vec<String> {
+    braced_names(command).iter()
+        .filter_map(|n| n.strip_prefix(SECRET_PREFIX).map(|s| s.trim().to_string()))
+        .filter(|s| secret_value(s).is_none())
+        .collect()
+}
+
+// A line from stdin without echoing it when that's a terminal
+fn read_hidden(prompt: &str) -> Option<String> {
+    use std::io::{IsTerminal, Write};
+    if !std::io::stdin().is_terminal() {
+        let mut line = String::new();
+        std::io::stdin().read_line(&mut line).ok()?;
+        return Some(line.trim_end_matches(['\n', '\r']).to_string());
+    }
+    eprint!("{}", prompt);
+    let _ = std::io::stderr().flush();
+    enable_raw_mode().ok()?;
+    let mut value = String::new();
+    let done = loop {
+        match event::read() {
+            Ok(Event::Key(key)) => match key.code {
+                KeyCode::Enter => break true,
+                KeyCode::Esc => break false,
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => break false,
+                KeyCode::Backspace => { value.pop(); }
+                KeyCode::Char(c) => value.push(c),
+                _ => {}
+            },
+            Ok(Event::Paste(text)) => value.push_str(&text),
+            Ok(_) => {}
+            Err(_) => break false,
+        }
+    };
+    disable_raw_mode().ok();
+    eprintln!();
+    if done { Some(value) } else { None }
+}
+
+// Why a parameter's value can't be run with, shown under the form
+fn param_error(spec: Option<&ParamSpec>, name: &str, value: &str) -> Option<String> {
+    if value.is_empty() {
+        return if spec.is_some_and(|p| p.optional) { None } else { Some(trf("{} is required", &[&name])) };
+    }
+    let pattern = spec.and_then(|p| p.pattern.as_ref())?;
+    match regex::Regex::new(&format!("^(?:{})$", pattern)) {
+        Ok(re) if re.is_match(value) => None,
+        Ok(_) => Some(trf("{} has to match {}", &[&name, pattern])),
+        Err(e) => Some(trf("Bad pattern for {}: {}", &[&name, &e])),
+    }
+}
+
+// What a parameter can be: its declared choices, otherwise suggestions. A choices command comes
+// back unrun, its lines are added by command_choices() off the UI thread
+fn param_choices(alias: &Alias, name: &str, env: &ShellEnv) -> (Vec<String>, Option<Command>) {
+    let Some(spec) = alias.params.get(name).filter(|p| p.is_choice()) else { return (placeholder_choices(name), None) };
+    let command = spec.choices_command.as_ref().map(|cmd| {
+        let mut c = shell_command(env, cmd);
+        if let Some(dir) = &alias.cwd { c.current_dir(expand_home(dir)); }
+        c
+    });
+    (spec.choices.clone(), command)
+}
+
+// How long a choices command gets before it's killed and the form opens with what it printed so far
+const CHOICES_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Runs the choices commands on a thread of their own, so a slow one doesn't freeze the screen
+fn command_choices(mut choices: Vec<Vec<String>>, commands: Vec<Option<Command>>) -> Receiver<Vec<Vec<String>>> {
+    let (tx, rx) = channel();
+    std::thread::spawn(move || {
+        for (c, command) in choices.iter_mut().zip(commands) {
+            let Some(out) = command.and_then(|cmd| output_within(cmd, CHOICES_TIMEOUT)) else { continue };
+            // `git branch` marks the current one with "* "
+            c.extend(String::from_utf8_lossy(&out).lines()
+                .map(|l| l.trim().trim_start_matches("* ").to_string()).filter(|l| !l.is_empty()));
+        }
+        let _ = tx.send(choices);
+    });
+    rx
+}
+
+// What `c` prints on stdout, killed once `limit` is up. The output is read as it comes so a
+// command printing a lot doesn't sit blocked on a full pipe until then. Something it started in
+// the background can keep the pipe open after the kill, that gets a moment and is then given up on
+fn output_within(mut c: Command, limit: Duration) -> Option<Vec<u8>> {
+    use std::io::Read;
+    let mut child = c.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::null()).spawn().ok()?;
+    let mut stdout = child.stdout.take()?;
+    let (tx, rx) = channel();
+    std::thread::spawn(move || {
+        let mut out = Vec::new();
+        let _ = stdout.read_to_end(&mut out);
+        let _ = tx.send(out);
+    });
+    let started = Instant::now();
+    while started.elapsed() < limit && matches!(child.try_wait(), Ok(None)) {
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+    rx.recv_timeout(Duration::from_millis(200)).ok()
+}
+
+// The parameter form, once the choices are in: what was typed last time comes first so Up brings
+// it back, and fields start on their default, choice parameters otherwise on the last one used or the first
+fn params_form(alias: Alias, names: Vec<String>, mut choices: Vec<Vec<String>>, state: &StateFile) -> UiMode {
+    for (n, c) in names.iter().zip(choices.iter_mut()) {
+        if alias.params.get(n).is_some_and(|p| p.secret || p.is_choice()) { continue; }
+        let used = state.param_values(&alias.name, n);
+        c.retain(|v| !used.contains(v));
+        c.splice(0..0, used);
+    }
+    let values = names.iter().zip(choices.iter()).map(|(n, c)| {
+        let spec = alias.params.get(n);
+        let default = spec.and_then(|p| p.default.clone());
+        if spec.is_some_and(|p| p.is_choice()) {
+            default.filter(|d| c.contains(d))
+                .or_else(|| state.param_values(&alias.name, n).into_iter().find(|v| c.contains(v)))
+                .or_else(|| c.first().cloned()).unwrap_or_default()
+        } else {
+            default.unwrap_or_default()
+        }
+    }).collect();
+    UiMode::Params { alias, values, names, choices, field: 0, error: None }
+}
+
+// The rest of the first choice that starts with what's typed so far, offered in grey and taken with Right
+fn param_completion<'a>(value: &str, choices: &'a [String]) -> Option<&'a str> {
+    if value.is_empty() { return None; }
+    choices.iter().find(|c| c.len() > value.len() && c.starts_with(value)).map(|c| &c[value.len()..])
+}
+
+// Whether Up/Down in a parameter field go through its values: always for a choice, for anything
+// else only while it's empty or still holds one of them. Once something is typed they move
+// between the fields like Tab does
+fn param_picks(spec: Option<&ParamSpec>, value: &str, choices: &[String]) -> bool {
+    !choices.is_empty() && (spec.is_some_and(|p| p.is_choice()) || value.is_empty() || choices.iter().any(|c| c == value))
+}
+
+// Values to offer for a placeholder, picked with Up/Down in the prompt
+fn placeholder_choices(name: &str) -> Vec<String> {
+    match name {
+        "host" => ssh_hosts(),
+        _ => Vec::new(),
+    }
+}
+
+// Concrete host aliases from ~/.ssh/config, wildcard patterns left out
+fn ssh_hosts() -> Vec<String> {
+    let Some(home) = dirs::home_dir() else { return Vec::new() };
+    let data = fs::read_to_string(home.join(".ssh").join("config")).unwrap_or_default();
+    let mut hosts: Vec<String> = Vec::new();
+    for line in data.lines() {
+        let line = line.trim();
+        // both "Host a b" and "Host=a" are valid
+        let Some((key, value)) = line.split_once(|c: char| c.is_whitespace() || c == '=') else { continue };
+        if !key.eq_ignore_ascii_case("host") { continue; }
+        for h in value.split_whitespace() {
+            let h = h.trim_matches('=');
+            if h.is_empty() || h.contains(['*', '?', '!']) || hosts.iter().any(|x| x == h) { continue; }
+            hosts.push(h.to_string());
+        }
+    }
+    hosts
+}
+
+// A row in the Kubernetes panel and the kubectl arguments that switch to it
+struct KubeEntry {
+    label: String,
+    switch: Vec<String>,
+    current: bool,
+}
+
+fn kube_entries() -> Vec<KubeEntry> {
+    let mut entries = Vec::new();
+    let current_ctx = template_value("kube_context").unwrap_or_default();
+    let current_ns = template_value("kube_namespace").unwrap_or_default();
+    for ctx in command_output("kubectl", &["config", "get-contexts", "-o", "name"]).unwrap_or_default().lines() {
+        entries.push(KubeEntry {
+            label: format!("context    {}", ctx),
+            switch: vec!["config".into(), "use-context".into(), ctx.to_string()],
+            current: ctx == current_ctx,
+        });
+    }
+    for ns in command_output("kubectl", &["get", "namespaces", "-o", "name"]).unwrap_or_default().lines() {
+        let ns = ns.trim_start_matches("namespace/");
+        entries.push(KubeEntry {
+            label: format!("namespace  {}", ns),
+            switch: vec!["config".into(), "set-context".into(), "--current".into(), format!("--namespace={}", ns)],
+            current: ns == current_ns,
+        });
+    }
+    entries
+}
+
+struct ServiceEntry {
+    unit: String,
+    active: String,
+    sub: String,
+    description: String,
+}
+
+fn systemd_services(user: bool) -> Vec<ServiceEntry> {
+    let mut args = vec!["list-units", "--type=service", "--all", "--no-legend", "--plain", "--no-pager"];
+    if user { args.insert(0, "--user"); }
+    let text = command_output("systemctl", &args).unwrap_or_default();
+    text.lines().filter_map(|line| {
+        let mut parts = line.split_whitespace();
+        let unit = parts.next()?.to_string();
+        let _load = parts.next()?;
+        let active = parts.next()?.to_string();
+        let sub = parts.next()?.to_string();
+        let description = parts.collect::<Vec<_>>().join(" ");
+        Some(ServiceEntry { unit, active, sub, description })
+    }).collect()
+}
+
+fn systemctl_line(user: bool, verb: &str, unit: &str) -> String {
+    if user { format!("systemctl --user {} {}", verb, unit) } else { format!("systemctl {} {}", verb, unit) }
+}
+
+// A runnable entry found in the project around the cwd (Makefile targets and the like), or
+// by `Import aliases` in the shell's rc files and history
+#[derive(Default)]
+struct TaskEntry {
+    source: &'static str,
+    name: String,
+    command: String,
+    // one-line description when the task file has one
+    desc: String,
+}
+
+impl TaskEntry {
+    // rc file aliases keep their own name, the history already comes with a suggested one
+    fn alias_name(&self) -> String {
+        match self.source {
+            "rc" | "history" => self.name.clone(),
+            source => format!("{} {}", source, self.name),
+        }
+    }
+}
+
+// Closest file with one of `names`, looking in the cwd and then its parents
+fn find_upwards(names: &[&str]) -> Option<PathBuf> {
+    let mut dir = std::env::current_dir().ok()?;
+    loop {
+        for n in names {
+            let p = dir.join(n);
+            if p.is_file() { return Some(p); }
+        }
+        if !dir.pop() { return None; }
+    }
+}
+
+fn makefile_tasks() -> Vec<TaskEntry> {
+    let Some(file) = find_upwards(&["GNUmakefile", "makefile", "Makefile"]) else { return Vec::new() };
+    let dir = file.parent().map(|d| d.display().to_string()).unwrap_or_default();
+    let data = fs::read_to_string(&file).unwrap_or_default();
+    let mut tasks: Vec<TaskEntry> = Vec::new();
+    for line in data.lines() {
+        // recipe lines, comments and special targets like .PHONY
+        if line.starts_with('\t') || line.starts_with('#') || line.starts_with('.') { continue; }
+        let Some((targets, rest)) = line.split_once(':') else { continue };
+        // `a := b` and `a ::= b` are assignments, not rules
+        if rest.starts_with('=') || rest.starts_with(":=") || targets.contains('=') { continue; }
+        for t in targets.split_whitespace() {
+            if t.contains(['%', '$']) || tasks.iter().any(|x| x.name == t) { continue; }
+            tasks.push(TaskEntry { source: "make", name: t.to_string(), command: format!("make -C {} {}", sh_quote(&dir), t), ..Default::default() });
+        }
+    }
+    tasks
+}
+
+// Header of a just recipe, `name param='default' +rest` up to the `:` that isn't `:=`
+fn just_recipe_header(line: &str) -> Option<&str> {
+    let mut quote: Option<char> = None;
+    for (i, c) in line.char_indices() {
+        match (c, quote) {
+            ('\'' | '"', None) => quote = Some(c),
+            (q, Some(open)) if q == open => quote = None,
+            (':', None) => return if line[i + 1..].starts_with('=') { None } else { Some(&line[..i]) },
+            _ => {}
+        }
+    }
+    None
+}
+
+// Recipes become `just` invocations with one placeholder per parameter
+fn justfile_tasks() -> Vec<TaskEntry> {
+    let Some(file) = find_upwards(&["justfile", "Justfile", ".justfile"]) else { return Vec::new() };
+    let data = fs::read_to_string(&file).unwrap_or_default();
+    let mut tasks = Vec::new();
+    for line in data.lines() {
+        if line.is_empty() || line.starts_with([' ', '\t', '#', '[', '@']) { continue; }
+        let Some(header) = just_recipe_header(line) else { continue };
+        let mut words = header.split_whitespace();
+        let Some(name) = words.next() else { continue };
+        // settings, aliases and private recipes aren't something to run
+        if ["set", "alias", "export", "import", "mod"].contains(&name) || name.starts_with('_') { continue; }
+        let mut command = format!("just --justfile {} {}", sh_quote(&file.display().to_string()), name);
+        for param in words {
+            let param = param.trim_start_matches(['+', '*', '$']);
+            let pname = param.split('=').next().unwrap_or("");
+            // pieces of a quoted default value with spaces in it
+            if pname.is_empty() || !pname.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') { continue; }
+            command.push_str(&format!(" {{{{{}}}}}", pname));
+        }
+        tasks.push(TaskEntry { source: "just", name: name.to_string(), command, ..Default::default() });
+    }
+    tasks
+}
+
+// `scripts` from package.json in the cwd, run with whichever package manager the lockfile points to
+fn npm_tasks() -> Vec<TaskEntry> {
+    let Ok(data) = fs::read_to_string("package.json") else { return Vec::new() };
+    let Ok(pkg) = serde_json::from_str::<serde_json::Value>(&data) else { return Vec::new() };
+    let Some(scripts) = pkg.get("scripts").and_then(|s| s.as_object()) else { return Vec::new() };
+    let runner = if PathBuf::from("yarn.lock").exists() {
+        "yarn"
+    } else if PathBuf::from("pnpm-lock.yaml").exists() {
+        "pnpm"
+    } else {
+        "npm"
+    };
+    // script names are any string at all, so they go in quoted
+    scripts.keys().map(|name| TaskEntry { source: runner, name: name.clone(), command: format!("{} run {}", runner, quoted_value(name, sh_quote)), ..Default::default() }).collect()
+}
+
+// Common cargo commands when we're inside a crate, plus any `[alias]` entries from
+// .cargo/config.toml. cargo finds the manifest itself, so these run from the cwd
+fn cargo_tasks() -> Vec<TaskEntry> {
+    if find_upwards(&["Cargo.toml"]).is_none() { return Vec::new() }
+    let mut tasks: Vec<TaskEntry> = ["build", "test", "clippy"].iter()
+        .map(|c| TaskEntry { source: "cargo", name: c.to_string(), command: format!("cargo {}", c), ..Default::default() })
+        .collect();
+    tasks.push(TaskEntry { source: "cargo", name: "run".to_string(), command: "cargo run -- {{args}}".to_string(), ..Default::default() });
+    let config = find_upwards(&[".cargo/config.toml", ".cargo/config"]);
+    let data = config.and_then(|p| fs::read_to_string(p).ok()).unwrap_or_default();
+    for name in cargo_aliases(&data) {
+        if tasks.iter().any(|t| t.name == name) { continue; }
+        // a quoted TOML key can be anything, so it goes in quoted too
+        tasks.push(TaskEntry { source: "cargo", command: format!("cargo {}", quoted_value(&name, sh_quote)), name, ..Default::default() });
+    }
+    tasks
+}
+
+// The names under `[alias]` in a .cargo/config.toml
+fn cargo_aliases(config: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut in_alias = false;
+    for line in config.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            in_alias = line == "[alias]";
+            continue;
+        }
+        if !in_alias || line.starts_with('#') { continue; }
+        let Some((name, _)) = line.split_once('=') else { continue };
+        let name = name.trim().trim_matches('"');
+        if !name.is_empty() { names.push(name.to_string()); }
+    }
+    names
+}
+
+// Names used as `{{.NAME}}` in a Taskfile, skipping the ones task itself provides
+fn taskfile_refs(text: &str) -> Vec<String> {
+    const BUILTIN: [&str; 9] = ["CLI_ARGS", "TASK", "ROOT_DIR", "TASKFILE_DIR", "USER_WORKING_DIR", "CHECKSUM", "TIMESTAMP", "TASK_VERSION", "ITEM"];
+    let mut refs: Vec<String> = Vec::new();
+    for part in text.split("{{").skip(1) {
+        let Some(expr) = part.split("}}").next() else { continue };
+        let Some(name) = expr.trim().strip_prefix('.') else { continue };
+        let name: String = name.chars().take_while(|c| c.is_alphanumeric() || *c == '_').collect();
+        if !name.is_empty() && !BUILTIN.contains(&name.as_str()) && !refs.contains(&name) { refs.push(name); }
+    }
+    refs
+}
+
+// Tasks from a Taskfile.yml. Vars a task uses (or `requires`) without defining them become
+// placeholders, so the parameter prompt asks for them and they're passed as `NAME=value`
+fn taskfile_tasks() -> Vec<TaskEntry> {
+    let Some(file) = find_upwards(&["Taskfile.yml", "Taskfile.yaml", "taskfile.yml", "taskfile.yaml"]) else { return Vec::new() };
+    let data = fs::read_to_string(&file).unwrap_or_default();
+    let indent = |l: &str| l.len() - l.trim_start().len();
+    let lines: Vec<&str> = data.lines().filter(|l| !l.trim().is_empty() && !l.trim_start().starts_with('#')).collect();
+    let Some(start) = lines.iter().position(|l| l.trim_end() == "tasks:") else { return Vec::new() };
+    // vars defined at the top level apply to every task
+    let mut global_vars: Vec<String> = Vec::new();
+    if let Some(v) = lines.iter().position(|l| l.trim_end() == "vars:") {
+        for l in lines[v + 1..].iter().take_while(|l| indent(l) > 0) {
+            if let Some((k, _)) = l.trim().split_once(':') { global_vars.push(k.trim().to_string()); }
+        }
+    }
+    let body: Vec<&str> = lines[start + 1..].iter().take_while(|l| indent(l) > 0).copied().collect();
+    let Some(task_indent) = body.first().map(|l| indent(l)) else { return Vec::new() };
+    let mut tasks = Vec::new();
+    let mut i = 0;
+    while i < body.len() {
+        let Some((name, _)) = body[i].trim().split_once(':') else { i += 1; continue };
+        let name = name.trim().trim_matches(['"', '\'']).to_string();
+        let block: Vec<&str> = body[i + 1..].iter().take_while(|l| indent(l) > task_indent).copied().collect();
+        i += block.len() + 1;
+        let Some(key_indent) = block.first().map(|l| indent(l)) else {
+            // `name: cmd` shorthand
+            tasks.push(TaskEntry { source: "task", command: format!("task --taskfile {} {}", sh_quote(&file.display().to_string()), name), name, ..Default::default() });
+            continue;
+        };
+        let mut desc = String::new();
+        let mut defined = global_vars.clone();
+        let mut required: Vec<String> = Vec::new();
+        let mut section = "";
+        let mut section_indent = 0;
+        for l in &block {
+            let t = l.trim();
+            let (key, value) = t.split_once(':').map(|(k, v)| (k.trim(), v.trim())).unwrap_or((t, ""));
+            if indent(l) == key_indent {
+                section = key;
+                section_indent = 0;
+                if (key == "desc" || key == "summary") && desc.is_empty() { desc = value.trim_matches(['"', '\'']).to_string(); }
+                continue;
+            }
+            if section_indent == 0 { section_indent = indent(l); }
+            match section {
+                // only the names directly under `vars:`, not their `sh:` and such
+                "vars" if indent(l) == section_indent && !t.starts_with('-') => defined.push(key.to_string()),
+                // `vars: [A, B]` or a `- A` list below `vars:`
+                "requires" => {
+                    let list = if key == "vars" { value } else { t.trim_start_matches('-') };
+                    for v in list.trim_matches(['[', ']']).split(',') {
+                        let v = v.trim().trim_matches(['"', '\'']);
+                        if !v.is_empty() && !required.iter().any(|r| r == v) { required.push(v.to_string()); }
+                    }
+                }
+                _ => {}
+            }
+        }
+        if name.starts_with('_') || block.iter().any(|l| l.trim() == "internal: true") { continue; }
+        for r in taskfile_refs(&block.join("\n")) {
+            if !defined.contains(&r) && !required.contains(&r) { required.push(r); }
+        }
+        let mut command = format!("task --taskfile {} {}", sh_quote(&file.display().to_string()), name);
+        for r in &required {
+            command.push_str(&format!(" {}={{{{{}}}}}", r, r));
+        }
+        tasks.push(TaskEntry { source: "task", name, command, desc });
+    }
+    tasks
+}
+
+// `alias` lines in the shell's rc files: `alias gs='git status'`, and fish's `alias gs 'git status'`
+fn rc_aliases(aliases: &[Alias]) -> Vec<TaskEntry> {
+    let Some(home) = dirs::home_dir() else { return Vec::new() };
+    let mut found = Vec::new();
+    for f in [".bashrc", ".bash_aliases", ".zshrc", ".config/fish/config.fish"] {
+        let Ok(data) = fs::read_to_string(home.join(f)) else { continue };
+        found.extend(rc_file_aliases(&data, f, aliases));
+    }
+    found
+}
+
+// The `alias` lines of one rc file, `file` is what the list shows it came from
+fn rc_file_aliases(data: &str, file: &str, aliases: &[Alias]) -> Vec<TaskEntry> {
+    let mut found = Vec::new();
+    for line in data.lines() {
+        let Some(rest) = line.trim().strip_prefix("alias ") else { continue };
+        let Some((name, value)) = rest.split_once('=').or_else(|| rest.split_once(' ')) else { continue };
+        let value = value.trim();
+        let command = value.strip_prefix('\'').and_then(|v| v.strip_suffix('\''))
+            .or_else(|| value.strip_prefix('"').and_then(|v| v.strip_suffix('"'))).unwrap_or(value);
+        let name = name.trim();
+        if name.is_empty() || command.is_empty() || aliases.iter().any(|a| a.name == name || a.command == command) { continue; }
+        found.push(TaskEntry { source: "rc", name: name.to_string(), command: command.to_string(), desc: file.to_string() });
+    }
+    found
+}
+
+// Where `Import aliases` looks, each one scanned in a thread of its own, in the order they're listed
+const IMPORT_SOURCES: [&str; 7] = ["rc", "history", "make", "just", "npm", "cargo", "task"];
+
+fn scan_import_sources(aliases: &[Alias]) -> Receiver<(&'static str, Vec<TaskEntry>)> {
+    let (tx, rx) = channel();
+    for source in IMPORT_SOURCES {
+        let tx = tx.clone();
+        let aliases = aliases.to_vec();
+        std::thread::spawn(move || {
+            let found = match source {
+                "rc" => rc_aliases(&aliases),
+                "history" => frequent_history_commands(&aliases).into_iter().map(|(command, count)| TaskEntry {
+                    source, name: suggest_alias_name(&command, &aliases), desc: format!("{}x", count), command,
+                }).collect(),
+                "make" => makefile_tasks(),
+                "just" => justfile_tasks(),
+                "npm" => npm_tasks(),
+                "cargo" => cargo_tasks(),
+                _ => taskfile_tasks(),
+            };
+            let _ = tx.send((source, found));
+        });
+    }
+    rx
+}
+
+// Everything the scanners found as one list, by source, without commands that showed up twice
+fn merge_imports(mut items: Vec<TaskEntry>) -> Vec<TaskEntry> {
+    items.sort_by_key(|t| IMPORT_SOURCES.iter().position(|s| *s == t.source));
+    let mut seen = HashSet::new();
+    items.retain(|t| seen.insert(t.command.clone()));
+    items
+}
+
+// Everything runnable we can find for the current project. Scanned every time the panel
+// opens, so it always matches the directory we're in
+fn project_tasks() -> Vec<TaskEntry> {
+    let mut tasks = makefile_tasks();
+    tasks.extend(justfile_tasks());
+    tasks.extend(npm_tasks());
+    tasks.extend(cargo_tasks());
+    tasks.extend(taskfile_tasks());
+    tasks
+}
+
+// The actions list, with the optional panels enabled in `modules` slotted in before the shell entries
+fn action_list(cfg: &ConfigFile) -> Vec<&'static str> {
+    let mut options = vec!["Add an alias", "Add a bookmark", "Edit an alias", "Rename an alias", "Remove an alias", "Trash", "Change directory", "Import shared alias", "Build a command", "Stats", "Run history", "Processes", "Record session", "Import from history", "Project tasks", "Import aliases", "tmux sessions", "SSH hosts", "Environment"];
+    for (module, action) in [("kubernetes", "Kubernetes"), ("systemd", "Services"), ("docker", "Containers")] {
+        if cfg.modules.iter().any(|m| m == module) { options.push(action); }
+    }
+    if !cfg.bundles.is_empty() { options.push("Refresh bundles"); }
+    if cfg.sync_remote.is_some() { options.push("Sync config"); }
+    if !cfg.profiles.is_empty() { options.push("Switch profile"); }
+    options.extend(["Go to shell", "Quit shell"]);
+    options
+}
+
+// Describe exactly what running the alias would do, read back from the Command we'd spawn
+fn explain_alias(alias: &Alias, env: &ShellEnv, danger_patterns: &[String]) -> Vec<String> {
+    let mut lines = vec![trf("Alias: {}", &[&alias.name])];
+    if alias.kind == AliasKind::Cd {
+        lines.push(trf("Changes directory to: {}", &[&expand_home(alias.command.trim()).display()]));
+        if std::env::var_os(CD_FILE_VAR).is_some() {
+            lines.push(tr("The shell wrapper cds there after tuish exits").to_string());
+        } else {
+            lines.push(trf("No shell wrapper active, opens {} in that directory", &[&env.shell]));
+        }
+        return lines;
+    }
+    let cmd = alias_invocation(alias, env);
+    lines.push(trf("Program: {}", &[&cmd.get_program().to_string_lossy()]));
+    for (i, arg) in cmd.get_args().enumerate() {
+        lines.push(format!("  argv[{}]: {}", i + 1, arg.to_string_lossy()));
+    }
+    match (&alias.container, &alias.cwd) {
+        (Some(c), Some(dir)) => lines.push(trf("Working directory: {} inside container {}", &[dir, c])),
+        (Some(c), None) => lines.push(trf("Working directory: container {}'s default", &[c])),
+        (None, _) => {
+            let cwd = cmd.get_current_dir().map(|d| d.to_path_buf()).or_else(|| std::env::current_dir().ok());
+            lines.push(trf("Working directory: {}", &[&cwd.map(|d| d.display().to_string()).unwrap_or_else(|| "?".to_string())]));
+        }
+    }
+    match (&alias.container, &env.snapshot) {
+        (Some(c), _) => lines.push(trf("Environment: whatever container {} has", &[c])),
+        (None, Some(vars)) => lines.push(trf("Environment: login snapshot ({} variables)", &[&vars.len()])),
+        (None, None) => lines.push(tr("Environment: inherited from tuish").to_string()),
+    }
+    if alias.container.is_none() {
+        // the active profile's variables win over the config's `env`
+        let mut overrides: Vec<(&String, &String)> = env.overrides.iter().filter(|(k, _)| !env.profile_env.contains_key(*k)).collect();
+        overrides.sort();
+        for (k, v) in overrides {
+            lines.push(format!("  {}={}", k, v));
+        }
+        let mut profile: Vec<(&String, &String)> = env.profile_env.iter().collect();
+        profile.sort();
+        for (k, v) in profile {
+            lines.push(format!("  {}={}  {}", k, v, tr("(from the profile)")));
+        }
+    }
+    match alias.run_in {
+        RunIn::Pane => lines.push(tr("Output: captured in the output pane, stdin is /dev/null").to_string()),
+        RunIn::Window => lines.push(tr("Output: a new terminal window, see the `terminal` setting").to_string()),
+        RunIn::Terminal => {}
+    }
+    lines.push(tr("Timeout: none").to_string());
+    if is_dangerous(&alias.command, danger_patterns) {
+        lines.push(tr("Matches a dangerous pattern, asks for confirmation first").to_string());
+    }
+    lines
+}
+
+// Names of the running containers, empty if docker isn't installed or the daemon is down
+fn docker_containers() -> Vec<String> {
+    docker_ps().into_iter().map(|c| c.name).collect()
+}
+
+struct ContainerEntry {
+    name: String,
+    image: String,
+    status: String,
+}
+
+fn docker_ps() -> Vec<ContainerEntry> {
+    let text = command_output("docker", &["ps", "--format", "{{.Names}}\t{{.Image}}\t{{.Status}}"]).unwrap_or_default();
+    text.lines().filter_map(|line| {
+        let mut parts = line.split('\t');
+        let name = parts.next()?.trim().to_string();
+        if name.is_empty() { return None; }
+        Some(ContainerEntry { name, image: parts.next().unwrap_or("").to_string(), status: parts.next().unwrap_or("").to_string() })
+    }).collect()
+}
+
+// Returns the exit code (None when it couldn't be started or was killed by a signal) and how
+// long it ran, not counting the wait for a key afterwards
+fn run_in_terminal(mut command: Command, pause: Pause) -> (Option<i32>, Duration) {
+    // Leave TUI and run the command in the shell, then (depending on `pause`) wait for a keypress
+    disable_raw_mode().ok();
+    leave_screen();
+
+    let started = Instant::now();
+    let status = if recording() { run_recorded(command) } else { command.status() };
+    let took = started.elapsed();
+    let code = match status {
+        Ok(s) => { println!("{}", trf("Command exited with: {}", &[&s])); record(&format!("Command exited with: {}\n", s)); s.code() }
+        Err(e) => { println!("{}", trf("Failed to run command: {}", &[&e])); record(&format!("Failed to run command: {}\n", e)); None }
+    };
+
+    let pause = match pause {
+        Pause::OnError if code == Some(0) => Pause::Never,
+        Pause::OnError => Pause::Always,
+        p => p,
+    };
+    match pause {
+        Pause::Seconds(n) => println!("{}", trf("Returning to the menu in {}s, or press any key...", &[&n])),
+        Pause::Never => {}
+        _ => println!("{}", tr("Press any key to return to the menu...")),
+    }
+
+    // Wait for one key press (or the timeout)
+    enable_raw_mode().ok();
+    match pause {
+        Pause::Never => {}
+        Pause::Seconds(n) => { if event::poll(Duration::from_secs(n)).unwrap_or(false) { let _ = event::read(); } }
+        _ => { let _ = event::read(); }
+    }
+    (code, took)
+}
+
+// Run the command through shellcheck in the dialect of the default shell, returning its findings
+fn shellcheck(command: &str, shell: &str) -> Result<Vec<String>, String> {
+    use std::io::Write;
+    let dialect = match std::path::Path::new(shell).file_name().and_then(|s| s.to_str()).unwrap_or("") {
+        "fish" => return Err(tr("shellcheck can't check fish commands").to_string()),
+        "sh" | "dash" | "ash" => "sh",
+        "ksh" => "ksh",
+        // zsh isn't supported by shellcheck, bash is the closest match
+        _ => "bash",
+    };
+    let child = Command::new("shellcheck").args(["--format=gcc", "--shell", dialect, "-"])
+        .stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn();
+    let mut child = match child {
+        Ok(c) => c,
+        Err(_) => return Err(tr("shellcheck is not installed").to_string()),
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(command.as_bytes());
+    }
+    let out = child.wait_with_output().map_err(|e| trf("shellcheck failed: {}", &[&e]))?;
+    // findings look like "-:1:5: warning: ... [SC2086]", drop the stdin filename
+    let mut lines: Vec<String> = String::from_utf8_lossy(&out.stdout).lines()
+        .map(|l| l.strip_prefix("-:").unwrap_or(l).to_string())
+        .collect();
+    lines.extend(String::from_utf8_lossy(&out.stderr).lines().map(|l| l.to_string()));
+    Ok(lines)
+}
+
+// Suspend the TUI, run the command in the real terminal and come back to a fresh frame
+fn run_and_return(terminal: &mut Terminal<CrosstermBackend<Stdout>>, command: Command, pause: Pause) -> (Option<i32>, Duration) {
+    disable_raw_mode().ok();
+    leave_screen();
+    let result = run_in_terminal(command, pause);
+    enter_screen();
+    enable_raw_mode().ok();
+    *terminal = Terminal::new(CrosstermBackend::new(std::io::stdout())).unwrap();
+    result
+}
+
+// An asciinema (v2 cast) recording of what commands print, started and stopped with `Record session`
+struct Recorder {
+    path: PathBuf,
+    file: fs::File,
+    start: Instant,
+}
+
+impl Recorder {
+    fn start(path: PathBuf) -> std::io::Result<Recorder> {
+        use std::io::Write;
+        let mut file = fs::File::create(&path)?;
+        let (width, height) = crossterm::terminal::size().unwrap_or((80, 24));
+        writeln!(file, r#"{{"version": 2, "width": {}, "height": {}, "timestamp": {}, "title": "tuish session"}}"#, width, height, unix_now())?;
+        Ok(Recorder { path, file, start: Instant::now() })
+    }
+
+    // One output event, with the newlines a terminal would have turned into \r\n
+    fn output(&mut self, data: &str) {
+        use std::io::Write;
+        let data = serde_json::to_string(&data.replace('\n', "\r\n")).unwrap_or_default();
+        let _ = writeln!(self.file, "[{:.6}, \"o\", {}]", self.start.elapsed().as_secs_f64(), data);
+    }
+}
+
+// The recording in progress, global so every way of running something can add to it
+static RECORDING: Mutex<Option<Recorder>> = Mutex::new(None);
+
+fn recording() -> bool {
+    RECORDING.lock().map(|r| r.is_some()).unwrap_or(false)
+}
+
+fn record(data: &str) {
+    if let Ok(mut rec) = RECORDING.lock() && let Some(r) = rec.as_mut() {
+        r.output(data);
+    }
+}
+
+// Run in the terminal with output going through us so it can be recorded too. Programs see a
+// pipe instead of a terminal for this, so some drop their colors, input is still the terminal's
+fn run_recorded(mut command: Command) -> std::io::Result<std::process::ExitStatus> {
+    use std::io::{Read, Write};
+    command.stdout(Stdio::piped()).stderr(Stdio::piped());
+    let mut child = command.spawn()?;
+    let (tx, rx) = channel::<(bool, Vec<u8>)>();
+    let pipes: Vec<(bool, Box<dyn Read + Send>)> = [
+        child.stdout.take().map(|p| (false, Box::new(p) as Box<dyn Read + Send>)),
+        child.stderr.take().map(|p| (true, Box::new(p) as Box<dyn Read + Send>)),
+    ].into_iter().flatten().collect();
+    for (is_err, mut pipe) in pipes {
+        let tx = tx.clone();
+        std::thread::spawn(move || {
+            let mut buf = [0u8; 4096];
+            while let Ok(n) = pipe.read(&mut buf) {
+                if n == 0 || tx.send((is_err, buf[..n].to_vec())).is_err() { break; }
+            }
+        });
+    }
+    drop(tx);
+    for (is_err, data) in rx {
+        if is_err {
+            let _ = std::io::stderr().write_all(&data);
+        } else {
+            let mut out = stdout();
+            let _ = out.write_all(&data);
+            let _ = out.flush();
+        }
+        record(&String::from_utf8_lossy(&data));
+    }
+    child.wait()
+}
+
+// `output-max-lines`, `output-max-bytes` and `output-spill`, for one job
+#[derive(Clone, Copy)]
+struct OutputLimits {
+    lines: usize,
+    bytes: usize,
+    spill: bool,
+}
+
+impl OutputLimits {
+    fn from_config(cfg: &ConfigFile) -> OutputLimits {
+        OutputLimits { lines: cfg.output_max_lines.max(1), bytes: cfg.output_max_bytes, spill: cfg.output_spill }
+    }
+}
+
+// A line of a job's output as shown, stderr in its own color (and called out for screen readers)
+fn output_spans(line: &str, stderr: bool) -> Spans<'_> {
+    if !stderr { return Spans::from(line); }
+    let prefix = if screen_reader() { "stderr: " } else { "" };
+    Spans::from(Span::styled(format!("{}{}", prefix, line), Style::default().fg(theme().stderr)))
+}
+
+// Longer lines are split, so one endless line can't get around the limits either
+const MAX_LINE_BYTES: u64 = 64 * 1024;
+// Lines the readers can get ahead of the UI before they wait, and the command waits with them
+const OUTPUT_BACKLOG: usize = 1024;
+// Taken in per poll at most, so a command that never stops printing can't keep the screen from drawing
+const MAX_POLL_LINES: usize = 10_000;
+
+// A new file in the temp directory only we can read, under a name nobody can guess ahead of time
+// (and put a symlink at)
+fn private_temp_file(prefix: &str, suffix: &str) -> std::io::Result<(PathBuf, fs::File)> {
+    use std::hash::BuildHasher;
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    loop {
+        let random = std::collections::hash_map::RandomState::new().hash_one(Instant::now());
+        let path = std::env::temp_dir().join(format!("{}-{:016x}{}", prefix, random, suffix));
+        match options.open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// A command running in the background with its output captured for the output pane
+struct Job {
+    name: String,
+    command: String,
+    // the newest lines, within `limits`, and for each whether it came from stderr and its links
+    // (looked for once as it comes in, not on every redraw)
+    lines: VecDeque<String>,
+    stderr: VecDeque<bool>,
+    links: VecDeque<Vec<Link>>,
+    bytes: usize,
+    limits: OutputLimits,
+    // lines ever received, and how many of the oldest were let go
+    received: usize,
+    dropped: usize,
+    // where the dropped lines went, with output-spill, removed along with the job
+    spill: Option<(PathBuf, fs::File)>,
+    rx: Receiver<(bool, String, Vec<Link>)>,
+    // None once it has exited (or never started)
+    child: Option<std::process::Child>,
+    // both output pipes have hit EOF
+    eof: bool,
+    exit: Option<i32>,
+    started: Instant,
+    after: After,
+    // first line on screen, unless `follow` keeps us at the bottom as lines come in
+    scroll: usize,
+    follow: bool,
+    // `e` in the output pane, just what went to stderr
+    stderr_only: bool,
+}
+
+impl Job {
+    fn start(alias: &Alias, mut command: Command, limits: OutputLimits) -> Job {
+        command.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped());
+        let (tx, rx) = sync_channel(OUTPUT_BACKLOG);
+        let child = match command.spawn() {
+            Ok(mut c) => {
+                let pipes: Vec<(bool, Box<dyn std::io::Read + Send>)> = [
+                    c.stdout.take().map(|p| (false, Box::new(p) as Box<dyn std::io::Read + Send>)),
+                    c.stderr.take().map(|p| (true, Box::new(p) as Box<dyn std::io::Read + Send>)),
+                ].into_iter().flatten().collect();
+                for (is_stderr, pipe) in pipes {
+                    let tx = tx.clone();
+                    std::thread::spawn(move || {
+                        use std::io::{BufRead, Read};
+                        let mut reader = std::io::BufReader::new(pipe);
+                        let mut buf = Vec::new();
+                        while (&mut reader).take(MAX_LINE_BYTES).read_until(b'\n', &mut buf).unwrap_or(0) > 0 {
+                            // the paths get checked for existing here, off the UI thread
+                            let line = output_line(&String::from_utf8_lossy(&buf));
+                            let links = find_links(&line);
+                            if tx.send((is_stderr, line, links)).is_err() { break; }
+                            buf.clear();
+                        }
+                    });
+                }
+                Some(c)
+            }
+            Err(e) => {
+                let _ = tx.send((true, trf("Failed to run command: {}", &[&e]), Vec::new()));
+                None
+            }
+        };
+        Job {
+            name: alias.name.clone(), command: alias.command.clone(), lines: VecDeque::new(), stderr: VecDeque::new(), links: VecDeque::new(), bytes: 0, limits, received: 0, dropped: 0, spill: None, rx, child, eof: false, exit: None, started: Instant::now(), after: After::for_alias(alias), scroll: 0, follow: true, stderr_only: false,
+        }
+    }
+
+    fn running(&self) -> bool {
+        self.child.is_some() || !self.eof
+    }
+
+    // Pull in whatever output arrived, true once when the job has completely finished
+    fn poll(&mut self) -> bool {
+        let was_running = self.running();
+        for _ in 0..MAX_POLL_LINES {
+            match self.rx.try_recv() {
+                Ok((is_stderr, line, links)) => {
+                    self.bytes += line.len();
+                    self.lines.push_back(line);
+                    self.stderr.push_back(is_stderr);
+                    self.links.push_back(links);
+                    self.received += 1;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => { self.eof = true; break; }
+            }
+        }
+        self.trim();
+        if let Some(child) = &mut self.child {
+            match child.try_wait() {
+                Ok(Some(status)) => { self.exit = status.code(); self.child = None; }
+                Ok(None) => {}
+                Err(_) => { self.child = None; }
+            }
+        }
+        was_running && !self.running()
+    }
+
+    fn kill(&mut self) {
+        if let Some(child) = &mut self.child { let _ = child.kill(); }
+    }
+
+    // Once per poll rather than per line, the oldest lines come off the front of the ring
+    fn trim(&mut self) {
+        let mut n = self.lines.len().saturating_sub(self.limits.lines);
+        let mut bytes = self.bytes - self.lines.range(..n).map(|l| l.len()).sum::<usize>();
+        while bytes > self.limits.bytes && n < self.lines.len() {
+            bytes -= self.lines[n].len();
+            n += 1;
+        }
+        if n == 0 { return; }
+        if self.limits.spill { self.spill(n); }
+        self.lines.drain(..n);
+        self.stderr.drain(..n);
+        self.links.drain(..n);
+        self.bytes = bytes;
+        self.dropped += n;
+        self.scroll = self.scroll.saturating_sub(n);
+    }
+
+    fn spill(&mut self, n: usize) {
+        use std::io::Write;
+        if self.spill.is_none() {
+            self.spill = private_temp_file(&format!("tuish-{}", export_name(&self.name)), ".log").ok();
+        }
+        if let Some((_, file)) = &mut self.spill {
+            for line in self.lines.range(..n) { let _ = writeln!(file, "{}", line); }
+        }
+    }
+
+    // Everything it printed that's still around, spilled lines included
+    fn all_output(&self) -> Vec<u8> {
+        let mut data = self.spill.as_ref().and_then(|(path, _)| fs::read(path).ok()).unwrap_or_default();
+        for line in &self.lines {
+            data.extend_from_slice(line.as_bytes());
+            data.push(b'\n');
+        }
+        data
+    }
+
+    // "[1200 earlier lines dropped]", or where they went
+    fn dropped_note(&self) -> Option<String> {
+        if self.dropped == 0 { return None; }
+        Some(match &self.spill {
+            Some((path, _)) => trf("[{} earlier lines in {}]", &[&self.dropped, &path.display()]),
+            None => trf("[{} earlier lines dropped]", &[&self.dropped]),
+        })
+    }
+
+    // The lines that fit in `rows`, the tail while following, with whether they're stderr and their links
+    fn visible(&self, rows: usize) -> Vec<(&str, bool, &[Link])> {
+        let len = self.shown_len();
+        let top = if self.follow { len.saturating_sub(rows) } else { self.scroll.min(len) };
+        self.lines.iter().zip(&self.stderr).zip(&self.links)
+            .filter(|((_, e), _)| !self.stderr_only || **e)
+            .skip(top).take(rows)
+            .map(|((l, e), links)| (l.as_str(), *e, links.as_slice()))
+            .collect()
+    }
+
+    // how many lines there are to scroll through, fewer with `stderr_only`
+    fn shown_len(&self) -> usize {
+        if self.stderr_only { self.stderr.iter().filter(|e| **e).count() } else { self.lines.len() }
+    }
+
+    fn status(&self) -> (String, Color) {
+        match (self.running(), self.exit) {
+            (true, _) => (tr("running").to_string(), theme().selected),
+            (false, Some(c)) => (trf("exit {}", &[&c]), if c == 0 { theme().ok } else { theme().error }),
+            (false, None) => (tr("killed").to_string(), theme().error),
+        }
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        if let Some((path, _)) = self.spill.take() { let _ = fs::remove_file(path); }
+    }
+}
+
+// Subdirectories of `dir` for the directory picker, ".." first
+fn dir_entries(dir: &std::path::Path, hidden: bool) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir).into_iter().flatten().flatten()
+        .filter(|e| e.path().is_dir())
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .filter(|n| hidden || !n.starts_with('.'))
+        .collect();
+    names.sort_by_key(|n| n.to_lowercase());
+    if dir.parent().is_some() { names.insert(0, "..".to_string()); }
+    names
+}
+
+fn pick_dir(alias: Option<usize>, dir: PathBuf) -> UiMode {
+    let entries = dir_entries(&dir, false);
+    UiMode::PickDir { alias, dir, entries, cursor: 0, hidden: false }
+}
+
+// The program an alias runs, as man and tldr know it: no path, no VAR=value in front
+fn alias_program(alias: &Alias) -> Option<String> {
+    if alias.kind == AliasKind::Cd { return None; }
+    let program = command_program(&alias.command)?;
+    std::path::Path::new(program).file_name().map(|n| n.to_string_lossy().to_string())
+}
+
+// `man program` as plain text wrapped to `width`, for the built-in pager
+fn man_page(program: &str, width: u16) -> Result<Vec<String>, String> {
+    let out = Command::new("man").arg(program)
+        .env("MANPAGER", "cat").env("PAGER", "cat").env("MANWIDTH", width.to_string()).env("GROFF_NO_SGR", "1")
+        .env_remove("MAN_KEEP_FORMATTING")
+        .stdin(Stdio::null()).stderr(Stdio::null())
+        .output().map_err(|e| trf("Failed to run man: {}", &[&e]))?;
+    if !out.status.success() || out.stdout.is_empty() {
+        return Err(trf("No manual entry for {}", &[&program]));
+    }
+    Ok(plain_text(&String::from_utf8_lossy(&out.stdout)).lines().map(|l| l.to_string()).collect())
+}
+
+// Drops the backspace overstrikes man uses for bold and underline, and any color codes
+fn plain_text(text: &str) -> String {
+    let mut out = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            // "x\bx" is a bold x, "_\bx" an underlined one: the char after the backspace wins
+            '\u{8}' => { out.pop(); }
+            '\x1b' if chars.peek() == Some(&'[') => {
+                for c in chars.by_ref() {
+                    if c.is_ascii_alphabetic() { break; }
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+// How long a downloaded tldr page is trusted before asking for it again
+const TLDR_MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+// The tldr page for a program, from tldr/<program>.md next to the config or else from
+// tldr-pages on GitHub. An old copy is still shown when there's no network
+fn tldr_page(program: &str, cfg_path: &std::path::Path) -> Result<Vec<String>, String> {
+    let name = program.to_lowercase();
+    if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || "-_.+".contains(c)) {
+        return Err(trf("No tldr page for {}", &[&program]));
+    }
+    let cache = cfg_path.with_file_name("tldr").join(format!("{}.md", name));
+    let fresh = fs::metadata(&cache).and_then(|m| m.modified()).ok()
+        .and_then(|t| t.elapsed().ok()).is_some_and(|age| age < TLDR_MAX_AGE);
+    if !fresh {
+        let platforms = if cfg!(target_os = "macos") { ["osx", "common"] } else { ["linux", "common"] };
+        for platform in platforms {
+            let url = format!("https://raw.githubusercontent.com/tldr-pages/tldr/main/pages/{}/{}.md", platform, name);
+            let Ok(out) = Command::new("curl").args(["-fsSL", "--max-time", "10", &url]).stdin(Stdio::null()).output() else {
+                break;
+            };
+            if out.status.success() && !out.stdout.is_empty() {
+                if let Some(dir) = cache.parent() { fs::create_dir_all(dir).ok(); }
+                let _ = fs::write(&cache, &out.stdout);
+                return Ok(tldr_lines(&String::from_utf8_lossy(&out.stdout)));
+            }
+        }
+    }
+    fs::read_to_string(&cache).map(|page| tldr_lines(&page)).map_err(|_| trf("No tldr page for {}", &[&program]))
+}
+
+// tldr markdown as plain lines: the description, then each example with its command indented under it
+fn tldr_lines(page: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in page.lines() {
+        let line = line.trim_end();
+        let text = if line.starts_with('#') {
+            continue;
+        } else if let Some(desc) = line.strip_prefix("> ") {
+            desc.replace(['<', '>'], "")
+        } else if let Some(command) = line.strip_prefix('`').and_then(|l| l.strip_suffix('`')) {
+            format!("    {}", command.replace("{{", "").replace("}}", ""))
+        } else {
+            line.to_string()
+        };
+        if !(text.is_empty() && lines.last().is_none_or(|l| l.is_empty())) { lines.push(text); }
+    }
+    lines
+}
+
+// An option read from a program's --help
+#[derive(Clone)]
+struct HelpOption {
+    // long form when there is one, "--output"
+    flag: String,
+    // what it takes ("FILE"), and whether it's written --flag=VALUE rather than --flag VALUE
+    value: Option<String>,
+    eq: bool,
+    desc: String,
+}
+
+// `program --help`, from stdout or stderr, given two seconds in case it ignores --help and waits around
+fn help_text(program: &str) -> String {
+    let spawned = Command::new(program).arg("--help").stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn();
+    let Ok(mut child) = spawned else { return String::new() };
+    let started = Instant::now();
+    while started.elapsed() < Duration::from_secs(2) && matches!(child.try_wait(), Ok(None)) {
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    let _ = child.kill();
+    let Ok(out) = child.wait_with_output() else { return String::new() };
+    let text = if out.stdout.is_empty() { out.stderr } else { out.stdout };
+    String::from_utf8_lossy(&text).into_owned()
+}
+
+// help_text() and parse_help() on a thread of their own, so the UI keeps drawing while a slow
+// program makes us wait out the two seconds
+fn read_help(program: &str) -> Receiver<Vec<HelpOption>> {
+    let (tx, rx) = channel();
+    let program = program.to_string();
+    std::thread::spawn(move || { let _ = tx.send(parse_help(&help_text(&program))); });
+    rx
+}
+
+// Lines like "  -o, --output=FILE   write to FILE", with indented lines after one continuing its description
+fn parse_help(text: &str) -> Vec<HelpOption> {
+    let mut options: Vec<HelpOption> = Vec::new();
+    let mut continues = false;
+    for line in text.lines() {
+        let t = line.trim_start();
+        if !t.starts_with('-') {
+            // a wrapped description, indented under the one before
+            if continues && !t.is_empty() && line.len() - t.len() >= 8 && let Some(last) = options.last_mut() {
+                if !last.desc.is_empty() { last.desc.push(' '); }
+                last.desc.push_str(t.trim());
+            } else {
+                continues = false;
+            }
+            continue;
+        }
+        let (spec, desc) = match t.find("  ").or_else(|| t.find('\t')) {
+            Some(i) => (&t[..i], t[i..].trim()),
+            None => (t, ""),
+        };
+        let mut flag = String::new();
+        let mut value = None;
+        let mut eq = false;
+        for part in spec.split(',').map(|p| p.trim()).filter(|p| p.starts_with('-')) {
+            let end = part.find(['=', '[', ' ', '<']).unwrap_or(part.len());
+            let name = &part[..end];
+            let rest = &part[end..];
+            if value.is_none() {
+                let v = rest.trim_start_matches(['[', '=', ' ']).trim_matches(['<', '>', ']', '[']).trim();
+                if !v.is_empty() { value = Some(v.to_string()); eq = rest.contains('='); }
+            }
+            if flag.is_empty() || (name.starts_with("--") && !flag.starts_with("--")) { flag = name.to_string(); }
+        }
+        if flag.trim_start_matches('-').is_empty() { continue; }
+        options.push(HelpOption { flag, value, eq, desc: desc.to_string() });
+        continues = true;
+    }
+    options
+}
+
+// Quoted only when the shell would mangle it, and never when it's a {{parameter}}
+fn arg_quote(arg: &str) -> String {
+    let plain = arg.chars().all(|c| c.is_ascii_alphanumeric() || "_./:=,@%+-".contains(c));
+    if plain || arg.contains("{{") { arg.to_string() } else { sh_quote(arg) }
+}
+
+// The command the builder has put together so far
+fn built_command(program: &str, options: &[HelpOption], values: &[Option<String>], args: &str) -> String {
+    let mut parts = vec![program.to_string()];
+    for (o, v) in options.iter().zip(values) {
+        match (v, &o.value) {
+            (None, _) => {}
+            (Some(v), Some(_)) if !v.is_empty() => {
+                parts.push(if o.eq { format!("{}={}", o.flag, arg_quote(v)) } else { format!("{} {}", o.flag, arg_quote(v)) });
+            }
+            _ => parts.push(o.flag.clone()),
+        }
+    }
+    if !args.trim().is_empty() { parts.push(args.trim().to_string()); }
+    parts.join(" ")
+}
+
+// A process started by a job in the output pane, or one of its children (`depth` deep)
+struct ProcEntry {
+    pid: u32,
+    alias: String,
+    depth: usize,
+    command: String,
+    state: char,
+    cpu: f64,
+    rss_kb: u64,
+}
+
+// Clock ticks per second in /proc/<pid>/stat, 100 on every Linux we'd run on
+const CLK_TCK: f64 = 100.0;
+
+// (parent, state, user+system CPU ticks) from /proc/<pid>/stat
+fn proc_stat(pid: u32) -> Option<(u32, char, u64)> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // the command name in parentheses can have spaces, so count fields after its closing one
+    let fields: Vec<&str> = stat.get(stat.rfind(')')? + 2..)?.split(' ').collect();
+    let ticks = fields.get(11)?.parse::<u64>().ok()? + fields.get(12)?.parse::<u64>().ok()?;
+    Some((fields.get(1)?.parse().ok()?, fields.first()?.chars().next()?, ticks))
+}
+
+fn proc_command(pid: u32) -> String {
+    let cmdline = fs::read(format!("/proc/{}/cmdline", pid)).unwrap_or_default();
+    let command = String::from_utf8_lossy(&cmdline).replace('\0', " ").trim().to_string();
+    if command.is_empty() {
+        fs::read_to_string(format!("/proc/{}/comm", pid)).map(|c| format!("[{}]", c.trim())).unwrap_or_default()
+    } else {
+        command
+    }
+}
+
+fn proc_rss_kb(pid: u32) -> u64 {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).unwrap_or_default();
+    status.lines().find(|l| l.starts_with("VmRSS:")).and_then(|l| l.split_whitespace().nth(1)?.parse().ok()).unwrap_or(0)
+}
+
+// Every running job's process and its descendants, in tree order. CPU % is against `ticks`
+// from the last look `elapsed` ago, which gets the new readings
+fn job_processes(jobs: &[Job], ticks: &mut HashMap<u32, u64>, elapsed: Duration) -> Vec<ProcEntry> {
+    let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+    let mut stats: HashMap<u32, (char, u64)> = HashMap::new();
+    for entry in fs::read_dir("/proc").into_iter().flatten().flatten() {
+        let Some(pid) = entry.file_name().to_str().and_then(|n| n.parse::<u32>().ok()) else { continue };
+        if let Some((parent, state, t)) = proc_stat(pid) {
+            children.entry(parent).or_default().push(pid);
+            stats.insert(pid, (state, t));
+        }
+    }
+    let mut items = Vec::new();
+    let mut seen = HashMap::new();
+    for j in jobs {
+        let Some(root) = j.child.as_ref().map(|c| c.id()) else { continue };
+        let mut stack = vec![(root, 0)];
+        while let Some((pid, depth)) = stack.pop() {
+            let Some((state, t)) = stats.get(&pid).copied() else { continue };
+            let cpu = match ticks.get(&pid) {
+                Some(before) if elapsed.as_secs_f64() > 0.0 => t.saturating_sub(*before) as f64 / CLK_TCK / elapsed.as_secs_f64() * 100.0,
+                _ => 0.0,
+            };
+            seen.insert(pid, t);
+            items.push(ProcEntry { pid, alias: j.name.clone(), depth, command: proc_command(pid), state, cpu, rss_kb: proc_rss_kb(pid) });
+            let mut kids = children.get(&pid).cloned().unwrap_or_default();
+            kids.sort_unstable_by(|a, b| b.cmp(a));
+            stack.extend(kids.into_iter().map(|k| (k, depth + 1)));
+        }
+    }
+    *ticks = seen;
+    items
+}
+
+fn send_signal(pid: u32, signal: &str) -> Result<(), String> {
+    match Command::new("kill").args(["-s", signal, &pid.to_string()]).output() {
+        Ok(o) if o.status.success() => Ok(()),
+        Ok(o) => Err(String::from_utf8_lossy(&o.stderr).trim().to_string()),
+        Err(e) => Err(trf("Failed to run kill: {}", &[&e])),
+    }
+}
+
+// Finished outputs we hold on to, the oldest go first
+const MAX_KEPT_OUTPUTS: usize = 50;
+
+// Start a job, dropping older outputs of the same alias (and the oldest ones past
+// MAX_KEPT_OUTPUTS) so each alias keeps its last two runs for diffing. Returns the new job's index
+fn start_job(jobs: &mut Vec<Job>, alias: &Alias, command: Command, limits: OutputLimits) -> usize {
+    let previous = jobs.iter().rposition(|j| !j.running() && j.name == alias.name);
+    let mut i = 0;
+    jobs.retain(|j| {
+        i += 1;
+        j.running() || j.name != alias.name || Some(i - 1) == previous
+    });
+    while jobs.iter().filter(|j| !j.running()).count() >= MAX_KEPT_OUTPUTS {
+        let Some(oldest) = jobs.iter().position(|j| !j.running()) else { break };
+        jobs.remove(oldest);
+    }
+    jobs.push(Job::start(alias, command, limits));
+    jobs.len() - 1
+}
+
+// Line diff of two outputs, unchanged lines included. Plain LCS on whatever is left after the
+// common start and end, and when that's too big to be quick just everything old then everything new
+fn diff_lines(old: &[String], new: &[String]) -> Vec<(char, String)> {
+    let pre = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suf = old[pre..].iter().rev().zip(new[pre..].iter().rev()).take_while(|(a, b)| a == b).count();
+    let (a, b) = (&old[pre..old.len() - suf], &new[pre..new.len() - suf]);
+    let mut out: Vec<(char, String)> = old[..pre].iter().map(|l| (' ', l.clone())).collect();
+    if a.len() * b.len() > 4_000_000 {
+        out.extend(a.iter().map(|l| ('-', l.clone())));
+        out.extend(b.iter().map(|l| ('+', l.clone())));
+    } else {
+        // lcs[i][j] = longest common run of a[i..] and b[j..]
+        let mut lcs = vec![vec![0u32; b.len() + 1]; a.len() + 1];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lcs[i][j] = if a[i] == b[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() || j < b.len() {
+            if i < a.len() && j < b.len() && a[i] == b[j] {
+                out.push((' ', a[i].clone()));
+                i += 1;
+                j += 1;
+            } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+                out.push(('-', a[i].clone()));
+                i += 1;
             } else {
-                aliases.iter().map(|a| {
-                    let kb = match a.keybind { Some(c) => format!(" [{}]", c), None => "".into() };
-                    ListItem::new(format!("{}{} - {}", a.name, kb, a.command)).style(Style::default().fg(Color::Cyan))
-                }).collect()
+                out.push(('+', b[j].clone()));
+                j += 1;
+            }
+        }
+    }
+    out.extend(old[old.len() - suf..].iter().map(|l| (' ', l.clone())));
+    out
+}
+
+// Diff view for the alias called `name`, if it has two finished runs to compare
+fn diff_view(jobs: &[Job], name: &str) -> UiMode {
+    let runs: Vec<&Job> = jobs.iter().filter(|j| j.name == name && !j.running()).collect();
+    match runs[..] {
+        [.., old, new] => {
+            let (old, new): (Vec<String>, Vec<String>) = (old.lines.iter().cloned().collect(), new.lines.iter().cloned().collect());
+            UiMode::Diff { name: name.to_string(), lines: diff_lines(&old, &new), scroll: 0 }
+        }
+        _ => UiMode::Message(trf("'{}' needs two finished runs in the output pane to diff", &[&name])),
+    }
+}
+
+// Index of the newest output of the alias called `name`
+fn latest_job(jobs: &[Job], name: &str) -> Option<usize> {
+    jobs.iter().rposition(|j| j.name == name)
+}
+
+// `<name>-<unix time>.log` in the cwd, with anything awkward in a filename swapped for `-`
+fn default_log_path(name: &str) -> String {
+    let safe: String = name.chars().map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '-' }).collect();
+    let file = format!("{}-{}.log", safe.trim_matches('-'), unix_now());
+    std::env::current_dir().map(|d| d.join(&file).display().to_string()).unwrap_or(file)
+}
+
+// What's left of a line once progress bars have redrawn it with \r, tabs expanded
+fn output_line(raw: &str) -> String {
+    let line = raw.trim_end_matches(['\n', '\r']);
+    line.rsplit('\r').next().unwrap_or("").replace('\t', "    ")
+}
+
+// A link in a line of output: first char, char after the last, what to open
+type Link = (usize, usize, String);
+
+// URLs and existing file paths in a line of output
+fn find_links(line: &str) -> Vec<Link> {
+    let mut links = Vec::new();
+    let mut start = 0;
+    for word in line.split(' ') {
+        let len = word.chars().count();
+        // quotes, brackets and sentence punctuation around it aren't part of it
+        let lead = word.chars().take_while(|c| "\"'(<[".contains(*c)).count();
+        let text: String = word.chars().skip(lead).collect();
+        let text = text.trim_end_matches(|c| "\"')>],.;:!?".contains(c));
+        if let Some(target) = link_target(text) {
+            links.push((start + lead, start + lead + text.chars().count(), target));
+        }
+        start += len + 1;
+    }
+    links
+}
+
+fn link_target(text: &str) -> Option<String> {
+    if text.starts_with("http://") || text.starts_with("https://") {
+        return Some(text.to_string());
+    }
+    if !text.contains('/') { return None; }
+    // compiler style src/main.rs:12:5 opens the file
+    let path = text.split(':').next().unwrap_or(text);
+    let path = expand_home(path);
+    if !path.exists() { return None; }
+    let path = if path.is_absolute() { path } else { std::env::current_dir().ok()?.join(path) };
+    Some(format!("file://{}", path.display()))
+}
+
+// Terminals that would print the OSC 8 codes instead of making links
+fn hyperlinks_supported(cfg: &ConfigFile) -> bool {
+    let term = std::env::var("TERM").unwrap_or_default();
+    cfg.hyperlinks && !screen_reader() && term != "linux" && term != "dumb"
+}
+
+// Hand a link to the desktop's opener, for terminals without clickable links
+fn open_link(target: &str) -> Result<(), String> {
+    for program in ["xdg-open", "open", "wslview"] {
+        let spawned = Command::new(program).arg(target).stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null()).spawn();
+        if spawned.is_ok() { return Ok(()); }
+    }
+    Err(trf("Couldn't open {}, no xdg-open or open found", &[&target]))
+}
+
+// A finished run, the command as it was actually run (placeholders filled in)
+#[derive(Serialize, Deserialize, Clone)]
+struct RunRecord {
+    name: String,
+    command: String,
+    exit: Option<i32>,
+    #[serde(default)]
+    secs: f64,
+    // unix time it finished
+    #[serde(default)]
+    at: u64,
+}
+
+// How often an alias has been run and how the last run went, kept even after its
+// runs have dropped out of the history
+#[derive(Serialize, Deserialize, Clone, Default)]
+struct Usage {
+    runs: u64,
+    #[serde(rename = "last-run")]
+    last_run: u64,
+    #[serde(rename = "last-exit")]
+    last_exit: Option<i32>,
+}
+
+// Runtime data that changes on every run, kept in state.json next to the config so the
+// config itself only changes when the user changes something
+#[derive(Serialize, Deserialize, Default)]
+struct StateFile {
+    #[serde(default)]
+    history: Vec<RunRecord>,
+    #[serde(default)]
+    usage: HashMap<String, Usage>,
+    // values typed into each alias's parameters, newest first (never secret ones)
+    #[serde(rename = "param-history", default)]
+    param_history: HashMap<String, HashMap<String, Vec<String>>>,
+    // earlier versions of each alias, oldest first, under its current name
+    #[serde(default)]
+    versions: HashMap<String, Vec<AliasVersion>>,
+}
+
+// An alias as it was before an edit or a rename, kept so it can be put back
+#[derive(Clone, Serialize, Deserialize)]
+struct AliasVersion {
+    name: String,
+    #[serde(flatten)]
+    entry: AliasEntry,
+    // unix seconds, when it stopped being this
+    changed: u64,
+}
+
+// Runs kept in the history, the oldest go first
+const MAX_HISTORY: usize = 1000;
+// Values remembered per parameter
+const MAX_PARAM_VALUES: usize = 20;
+// Earlier versions kept per alias
+const MAX_VERSIONS: usize = 50;
+
+impl StateFile {
+    fn add_run(&mut self, run: RunRecord) {
+        let usage = self.usage.entry(run.name.clone()).or_default();
+        usage.runs += 1;
+        usage.last_run = run.at;
+        usage.last_exit = run.exit;
+        self.history.push(run);
+        if self.history.len() > MAX_HISTORY {
+            self.history.drain(..self.history.len() - MAX_HISTORY);
+        }
+    }
+
+    fn rename(&mut self, old: &str, new: &str) {
+        for r in self.history.iter_mut().filter(|r| r.name == old) { r.name = new.to_string(); }
+        if let Some(u) = self.usage.remove(old) { self.usage.insert(new.to_string(), u); }
+        if let Some(p) = self.param_history.remove(old) { self.param_history.insert(new.to_string(), p); }
+        if let Some(v) = self.versions.remove(old) { self.versions.insert(new.to_string(), v); }
+    }
+
+    // Call with the alias as it is right before changing it
+    fn remember_version(&mut self, alias: &Alias) {
+        let entry = alias_entry(alias);
+        let versions = self.versions.entry(alias.name.clone()).or_default();
+        let same = versions.last().is_some_and(|v| v.name == alias.name && serde_json::to_value(&v.entry).ok() == serde_json::to_value(&entry).ok());
+        if same { return; }
+        versions.push(AliasVersion { name: alias.name.clone(), entry, changed: unix_now() });
+        if versions.len() > MAX_VERSIONS {
+            versions.drain(..versions.len() - MAX_VERSIONS);
+        }
+    }
+
+    fn remember_param(&mut self, alias: &str, name: &str, value: &str) {
+        if value.is_empty() { return; }
+        let values = self.param_history.entry(alias.to_string()).or_default().entry(name.to_string()).or_default();
+        values.retain(|v| v != value);
+        values.insert(0, value.to_string());
+        values.truncate(MAX_PARAM_VALUES);
+    }
+
+    fn param_values(&self, alias: &str, name: &str) -> Vec<String> {
+        self.param_history.get(alias).and_then(|p| p.get(name)).cloned().unwrap_or_default()
+    }
+}
+
+fn state_path(cfg_path: &std::path::Path) -> PathBuf {
+    cfg_path.with_file_name("state.json")
+}
+
+// With `encrypt` on, the run history and parameter values are as private as the aliases, so
+// state.json becomes state.age with the same passphrase. Set by unlock_config
+static STATE_PASSPHRASE: Mutex<Option<String>> = Mutex::new(None);
+
+// Whether load_state() read what's on disk (or found nothing there), so writing over it loses nothing
+static STATE_READ: AtomicBool = AtomicBool::new(false);
+
+// A missing or broken state file just means starting over, it's nothing the user wrote
+fn load_state(path: &std::path::Path) -> StateFile {
+    let pass = STATE_PASSPHRASE.lock().unwrap().clone();
+    let age = path.with_extension("age");
+    let (file, data) = match pass {
+        Some(pass) if age.exists() => (age.clone(), fs::read(&age).ok().and_then(|d| decrypt_with(&d, &pass).ok())),
+        // no state.age yet when `encrypt` was just turned on, the plain one moves over on the next write
+        _ => (path.to_path_buf(), fs::read(path).ok()),
+    };
+    let state: Option<StateFile> = data.and_then(|d| serde_json::from_slice(&d).ok());
+    STATE_READ.store(state.is_some() || !file.exists(), Ordering::Relaxed);
+    state.unwrap_or_default()
+}
+
+fn write_state(path: &std::path::Path, state: &StateFile) {
+    let Ok(s) = serde_json::to_string(state) else { return };
+    let pass = STATE_PASSPHRASE.lock().unwrap().clone();
+    match pass {
+        // state.age or state.json we couldn't read stays as it is, written over it's gone for good
+        Some(_) if !STATE_READ.load(Ordering::Relaxed) => {}
+        Some(pass) => {
+            let path = path.to_path_buf();
+            save_in_background("state", move || {
+                write_atomic(&path.with_extension("age"), &encrypt_with(s.as_bytes(), &pass)?)?;
+                // what was there from before `encrypt` was turned on, now in state.age
+                let _ = fs::remove_file(&path);
+                Ok(())
+            });
+        }
+        None => { let _ = fs::write(path, s); }
+    }
+}
+
+// Per-alias totals for the Stats screen
+struct AliasStats {
+    name: String,
+    runs: usize,
+    failures: usize,
+    secs: f64,
+}
+
+// Totals for every alias in the history, most used first
+fn alias_stats(history: &[RunRecord]) -> Vec<AliasStats> {
+    let mut stats: Vec<AliasStats> = Vec::new();
+    for r in history {
+        let i = match stats.iter().position(|s| s.name == r.name) {
+            Some(i) => i,
+            None => { stats.push(AliasStats { name: r.name.clone(), runs: 0, failures: 0, secs: 0.0 }); stats.len() - 1 }
+        };
+        stats[i].runs += 1;
+        if r.exit != Some(0) { stats[i].failures += 1; }
+        stats[i].secs += r.secs;
+    }
+    stats.sort_by(|a, b| b.runs.cmp(&a.runs).then_with(|| a.name.cmp(&b.name)));
+    stats
+}
+
+// A pane job that finished while something else was on screen, summed up in the corner
+// for TOAST_TIME once it's shown (only on the menu and in the output pane)
+struct Toast {
+    // with `started`, which job ctrl+o jumps to
+    name: String,
+    started: Instant,
+    summary: String,
+    ok: bool,
+    last: String,
+    shown: Option<Instant>,
+}
+
+const TOAST_TIME: Duration = Duration::from_secs(8);
+
+impl Toast {
+    fn new(job: &Job) -> Toast {
+        Toast {
+            name: job.name.clone(),
+            started: job.started,
+            summary: trf("{} after {}", &[&job.status().0, &format_secs(job.started.elapsed().as_secs_f64())]),
+            ok: job.exit == Some(0),
+            last: job.lines.iter().rev().find(|l| !l.trim().is_empty()).cloned().unwrap_or_default(),
+            shown: None,
+        }
+    }
+}
+
+// 1.5s, 2m03s, for durations shown to people
+fn format_secs(secs: f64) -> String {
+    if secs < 60.0 { format!("{:.1}s", secs) } else { format!("{}m{:02}s", secs as u64 / 60, secs as u64 % 60) }
+}
+
+const RECENT_LEN: usize = 5;
+
+// Name given to commands run from the `:` bar, which don't belong to any alias
+const ADHOC_NAME: &str = ":";
+
+// Latest run of each of the last few distinct commands, newest first
+fn recent_runs(history: &[RunRecord]) -> Vec<&RunRecord> {
+    let mut recent: Vec<&RunRecord> = Vec::new();
+    for r in history.iter().rev() {
+        if recent.len() == RECENT_LEN { break; }
+        if !recent.iter().any(|x| x.name == r.name && x.command == r.command) { recent.push(r); }
+    }
+    recent
+}
+
+// What the Run history screen lists, newest first. Killed runs (no exit code) count as failed
+fn history_shown(history: &[RunRecord], failures_only: bool) -> Vec<&RunRecord> {
+    history.iter().rev().filter(|r| !failures_only || r.exit != Some(0)).collect()
+}
+
+// What to run to repeat `r`: the alias it came from if it's still around, the bare command otherwise
+fn rerun_alias(r: &RunRecord, aliases: &[Alias]) -> Alias {
+    match aliases.iter().find(|a| a.name == r.name) {
+        Some(a) => Alias { command: r.command.clone(), ..a.clone() },
+        None => Alias { name: r.name.clone(), command: r.command.clone(), ..Default::default() },
+    }
+}
+
+// Hand the terminal to an interactive program and come back once it exits, without the key prompt
+fn run_shell_session(terminal: &mut Terminal<CrosstermBackend<Stdout>>, mut command: Command) {
+    disable_raw_mode().ok();
+    leave_screen();
+    match command.spawn() {
+        Ok(mut c) => { let _ = c.wait(); }
+        Err(e) => { println!("{}", trf("Failed to spawn shell: {}", &[&e])); }
+    }
+    // re-enter TUI
+    enter_screen();
+    enable_raw_mode().ok();
+    *terminal = Terminal::new(CrosstermBackend::new(std::io::stdout())).unwrap();
+}
+
+// Ctrl+E in a command field: the command goes into a temp file for $VISUAL/$EDITOR (vi if
+// neither is set) with the TUI suspended, and what's saved comes back as one line
+fn edit_in_editor(terminal: &mut Terminal<CrosstermBackend<Stdout>>, text: &str) -> Result<String, String> {
+    use std::io::Write;
+    let editor = std::env::var("VISUAL").or_else(|_| std::env::var("EDITOR")).unwrap_or_else(|_| "vi".to_string());
+    let (file, mut handle) = private_temp_file("tuish", ".sh").map_err(|e| trf("Couldn't write {}: {}", &[&std::env::temp_dir().display(), &e]))?;
+    let written = writeln!(handle, "{}", text).map_err(|e| trf("Couldn't write {}: {}", &[&file.display(), &e]));
+    drop(handle);
+    if let Err(e) = written {
+        let _ = fs::remove_file(&file);
+        return Err(e);
+    }
+    // through sh so an EDITOR like "code -w" works
+    let mut command = Command::new("sh");
+    command.args(["-c", &format!("{} \"$1\"", editor), "sh"]).arg(&file);
+    run_shell_session(terminal, command);
+    let edited = fs::read_to_string(&file).map_err(|e| trf("Couldn't read {}: {}", &[&file.display(), &e]));
+    let _ = fs::remove_file(&file);
+    Ok(pasted_command(&edited?))
+}
+
+// Names, paths and the like are one line, so a multi-line paste into them becomes one line
+fn pasted_line(text: &str) -> String {
+    text.lines().map(|l| l.trim()).filter(|l| !l.is_empty()).collect::<Vec<_>>().join(" ")
+}
+
+// Commands keep their lines as they are, joining them with `;` would break an `if ... then`, a
+// heredoc or a comment, the shell takes them just fine
+fn pasted_command(text: &str) -> String {
+    text.replace("\r\n", "\n").trim_end().to_string()
+}
+
+// A command on one row, its line breaks shown as ⏎
+fn one_line(command: &str) -> std::borrow::Cow<'_, str> {
+    if command.contains('\n') { command.replace('\n', glyph(" ⏎ ", " newline ")).into() } else { command.into() }
+}
+
+// Set at startup when the terminal speaks the kitty keyboard protocol, which is what tells keypad keys apart
+static KEY_ENHANCEMENT: AtomicBool = AtomicBool::new(false);
+
+// Colors by what they're for, the defaults overridden by `theme` in the config
+struct Theme {
+    title: Color,
+    alias: Color,
+    selected: Color,
+    ok: Color,
+    error: Color,
+    dim: Color,
+    bundle: Color,
+    text: Color,
+    stderr: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme { title: Color::Magenta, alias: Color::Cyan, selected: Color::Yellow, ok: Color::Green, error: Color::Red, dim: Color::DarkGray, bundle: Color::Blue, text: Color::White, stderr: Color::LightRed }
+    }
+}
+
+impl Theme {
+    // Unknown names and colors that don't parse are reported and left at their default
+    fn from_config(colors: &HashMap<String, String>, depth: ColorDepth) -> (Theme, Vec<String>) {
+        let mut theme = Theme::default();
+        let mut errors = Vec::new();
+        for (name, value) in colors {
+            let Some(color) = parse_color(value, depth) else {
+                errors.push(trf("theme: can't read color {} for {}", &[value, name]));
+                continue;
+            };
+            match name.as_str() {
+                "title" => theme.title = color,
+                "alias" => theme.alias = color,
+                "selected" => theme.selected = color,
+                "ok" => theme.ok = color,
+                "error" => theme.error = color,
+                "dim" => theme.dim = color,
+                "bundle" => theme.bundle = color,
+                "text" => theme.text = color,
+                "stderr" => theme.stderr = color,
+                _ => errors.push(trf("theme: no color called {}", &[name])),
+            }
+        }
+        (theme, errors)
+    }
+}
+
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+fn theme() -> &'static Theme {
+    THEME.get_or_init(Theme::default)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ColorDepth {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+}
+
+// What the terminal says it can show, going by the usual environment variables
+fn color_depth() -> ColorDepth {
+    let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+    let term = std::env::var("TERM").unwrap_or_default();
+    if colorterm == "truecolor" || colorterm == "24bit" {
+        ColorDepth::TrueColor
+    } else if term.contains("256color") {
+        ColorDepth::Ansi256
+    } else {
+        ColorDepth::Ansi16
+    }
+}
+
+// The 16 ANSI colors as xterm shows them by default, to find the nearest one
+const ANSI16: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)), (Color::Red, (205, 0, 0)), (Color::Green, (0, 205, 0)), (Color::Yellow, (205, 205, 0)),
+    (Color::Blue, (0, 0, 238)), (Color::Magenta, (205, 0, 205)), (Color::Cyan, (0, 205, 205)), (Color::Gray, (229, 229, 229)),
+    (Color::DarkGray, (127, 127, 127)), (Color::LightRed, (255, 0, 0)), (Color::LightGreen, (0, 255, 0)), (Color::LightYellow, (255, 255, 0)),
+    (Color::LightBlue, (92, 92, 255)), (Color::LightMagenta, (255, 0, 255)), (Color::LightCyan, (0, 255, 255)), (Color::White, (255, 255, 255)),
+];
+
+// "#ff8700", "#f80", a 256-color index like "208", or a name like "lightblue"
+fn parse_color(value: &str, depth: ColorDepth) -> Option<Color> {
+    let value = value.trim().to_lowercase();
+    if let Some(hex) = value.strip_prefix('#') {
+        let hex = if hex.len() == 3 { hex.chars().flat_map(|c| [c, c]).collect() } else { hex.to_string() };
+        if hex.len() != 6 || !hex.is_ascii() { return None; }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        return Some(downsample((channel(0)?, channel(2)?, channel(4)?), depth));
+    }
+    if let Ok(index) = value.parse::<u8>() {
+        return Some(if depth == ColorDepth::Ansi16 { nearest_ansi16(xterm_rgb(index)) } else { Color::Indexed(index) });
+    }
+    let name = value.replace(['-', '_', ' '], "");
+    let named = match name.as_str() {
+        "default" | "reset" => Color::Reset,
+        "darkgray" | "darkgrey" => Color::DarkGray,
+        "gray" | "grey" => Color::Gray,
+        _ => ANSI16.iter().map(|(c, _)| *c).find(|c| format!("{:?}", c).to_lowercase() == name)?,
+    };
+    Some(named)
+}
+
+fn downsample(rgb: (u8, u8, u8), depth: ColorDepth) -> Color {
+    match depth {
+        ColorDepth::TrueColor => Color::Rgb(rgb.0, rgb.1, rgb.2),
+        ColorDepth::Ansi256 => Color::Indexed(nearest_xterm256(rgb)),
+        ColorDepth::Ansi16 => nearest_ansi16(rgb),
+    }
+}
+
+fn color_distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+fn nearest_ansi16(rgb: (u8, u8, u8)) -> Color {
+    ANSI16.iter().min_by_key(|(_, c)| color_distance(rgb, *c)).map(|(c, _)| *c).unwrap_or(Color::Reset)
+}
+
+// Levels of the 6x6x6 cube in the 256-color palette
+const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+// RGB of a 256-color index: 16 ANSI, then the cube, then 24 grays
+fn xterm_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => ANSI16[index as usize].1,
+        16..=231 => {
+            let i = index - 16;
+            (CUBE[(i / 36) as usize], CUBE[(i / 6 % 6) as usize], CUBE[(i % 6) as usize])
+        }
+        _ => { let v = 8 + (index - 232) * 10; (v, v, v) }
+    }
+}
+
+// Closest of the cube and gray ramp, the first 16 depend on the terminal's own scheme so they're left out
+fn nearest_xterm256(rgb: (u8, u8, u8)) -> u8 {
+    (16..=255u8).min_by_key(|i| color_distance(rgb, xterm_rgb(*i))).unwrap_or(16)
+}
+
+// Set at startup from the screen-reader setting, read wherever something is drawn
+static SCREEN_READER: AtomicBool = AtomicBool::new(false);
+
+fn screen_reader() -> bool {
+    SCREEN_READER.load(Ordering::Relaxed)
+}
+
+// Set for `tuish --popup`, the instance `tuish popup` opens: Esc on the menu closes it, and so
+// does an alias finishing in the terminal unless its `after` says otherwise
+static POPUP: AtomicBool = AtomicBool::new(false);
+
+fn popup() -> bool {
+    POPUP.load(Ordering::Relaxed)
+}
+
+// Box drawing gets read out character by character, so screen-reader mode drops the borders
+fn panel() -> Block<'static> {
+    Block::default().borders(if screen_reader() { Borders::NONE } else { Borders::ALL })
+}
+
+// A symbol, or the word for it in screen-reader mode
+fn glyph(symbol: &'static str, word: &'static str) -> &'static str {
+    if screen_reader() { word } else { symbol }
+}
+
+// The screen-reader status line for whatever popup is open, None when the alias list or
+// actions have the user's attention
+fn popup_status(mode: &UiMode, cfg: &ConfigFile) -> Option<String> {
+    let at = |cursor: usize, labels: Vec<String>| match labels.get(cursor) {
+        Some(label) => trf("{} of {}: {}", &[&(cursor + 1), &labels.len(), label]),
+        None => tr("Nothing to pick").to_string(),
+    };
+    Some(match mode {
+        UiMode::Message(msg) => trf("Info: {}", &[msg]),
+        UiMode::ConfirmRun { alias } => trf("This command looks dangerous: {}. Press y to run it, any other key to cancel", &[&alias.command]),
+        UiMode::Params { alias, names, values, field, .. } => {
+            let value = &values[*field];
+            let shown = if value.is_empty() { tr("empty").to_string() }
+                else if alias.params.get(&names[*field]).is_some_and(|p| p.secret) { trf("{} characters", &[&value.chars().count()]) }
+                else { value.clone() };
+            trf("{}, field {} of {}: {}, {}", &[&alias.name, &(field + 1), &names.len(), &names[*field], &shown])
+        }
+        UiMode::Trash { cursor } => at(*cursor, cfg.trash.iter().map(|t| format!("{} - {}", t.name, t.entry.command)).collect()),
+        UiMode::Tmux { items, cursor } => at(*cursor, items.iter().map(|e| e.label.clone()).collect()),
+        UiMode::Tasks { items, cursor, .. } => at(*cursor, items.iter().map(|e| format!("{} - {}", e.name, e.command)).collect()),
+        UiMode::ImportHistory { items, cursor, .. } => at(*cursor, items.iter().map(|(c, _)| c.clone()).collect()),
+        UiMode::PickContainer { items, cursor, .. } | UiMode::PickProfile { items, cursor }
+        | UiMode::SshHosts { items, cursor } | UiMode::Links { items, cursor, .. } => at(*cursor, items.clone()),
+        UiMode::Services { items, cursor, .. } => at(*cursor, items.iter().map(|e| format!("{}, {}", e.unit, e.sub)).collect()),
+        UiMode::Containers { items, cursor } => at(*cursor, items.iter().map(|e| format!("{}, {}", e.name, e.status)).collect()),
+        UiMode::Kube { items, cursor } => at(*cursor, items.iter().map(|e| e.label.clone()).collect()),
+        UiMode::Processes { items, cursor, .. } => at(*cursor, items.iter().map(|e| format!("{}, {}", e.alias, e.command)).collect()),
+        UiMode::BuildPick { all, filter, cursor } => at(*cursor, all.iter().filter(|p| p.contains(filter.as_str())).cloned().collect()),
+        UiMode::ReadingHelp { program, .. } => trf("Reading `{} --help` ...", &[program]),
+        UiMode::LoadingChoices { alias, .. } => trf("{}: running its choices command ...", &[&alias.name]),
+        UiMode::PickDir { dir, entries, cursor, .. } => {
+            let mut rows = vec![trf("./ (use {})", &[&dir.display()])];
+            rows.extend(entries.iter().map(|e| format!("{}/", e)));
+            at(*cursor, rows)
+        }
+        _ => return None,
+    })
+}
+
+// Everything we switch on for the TUI, and back off for whatever runs in the terminal in between.
+// Pastes arrive as one Event::Paste instead of a burst of keys (and Enters)
+fn enter_screen() {
+    let mut out = stdout();
+    execute!(out, EnterAlternateScreen, EnableBracketedPaste).ok();
+    if KEY_ENHANCEMENT.load(Ordering::Relaxed) {
+        execute!(out, PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES)).ok();
+    }
+}
+
+fn leave_screen() {
+    let mut out = stdout();
+    if KEY_ENHANCEMENT.load(Ordering::Relaxed) {
+        execute!(out, PopKeyboardEnhancementFlags).ok();
+    }
+    execute!(out, LeaveAlternateScreen, DisableBracketedPaste).ok();
+}
+
+fn leave_tui(terminal: &mut Terminal<CrosstermBackend<Stdout>>) {
+    finish_saves();
+    disable_raw_mode().ok();
+    terminal.clear().ok();
+    leave_screen();
+    // back to whatever title the terminal had before us
+    execute!(stdout(), Print("\x1b[23;0t")).ok();
+}
+
+// "tuish — <alias>" while something runs, so the right window/tab is easy to find
+fn set_title(title: &str) {
+    execute!(stdout(), SetTitle(title)).ok();
+}
+
+fn running_title(jobs: &[Job]) -> String {
+    let running: Vec<&str> = jobs.iter().filter(|j| j.running()).map(|j| j.name.as_str()).collect();
+    if running.is_empty() { "tuish".to_string() } else { format!("tuish — {}", running.join(", ")) }
+}
+
+// A tmux session or window; `target` is what `tmux attach -t` takes
+struct TmuxEntry {
+    target: String,
+    label: String,
+    is_window: bool,
+}
+
+fn tmux_entries() -> Vec<TmuxEntry> {
+    let out = Command::new("tmux").args(["list-windows", "-a", "-F", "#{session_name}\t#{window_index}\t#{window_name}\t#{session_attached}"])
+        .stderr(Stdio::null()).output();
+    let Ok(out) = out else { return Vec::new() };
+    let mut entries: Vec<TmuxEntry> = Vec::new();
+    for line in String::from_utf8_lossy(&out.stdout).lines() {
+        let parts: Vec<&str> = line.split('\t').collect();
+        if parts.len() < 4 { continue; }
+        let (session, index, window, attached) = (parts[0], parts[1], parts[2], parts[3]);
+        if !entries.iter().any(|e| !e.is_window && e.target == session) {
+            let mark = if attached != "0" { " (attached)" } else { "" };
+            entries.push(TmuxEntry { target: session.to_string(), label: format!("{}{}", session, mark), is_window: false });
+        }
+        entries.push(TmuxEntry { target: format!("{}:{}", session, index), label: format!("    {}: {}", index, window), is_window: true });
+    }
+    entries
+}
+
+// Inside tmux, attaching would nest sessions, so switch the current client instead
+fn tmux_attach(target: &str) -> Command {
+    let mut c = Command::new("tmux");
+    if std::env::var_os("TMUX").is_some() { c.args(["switch-client", "-t", target]); } else { c.args(["attach-session", "-t", target]); }
+    c
+}
+
+// `tuish --bench`: the real UI driven by a fixed list of keys instead of the keyboard, timing
+// the loading, every frame and how long each key takes to show up on screen
+#[derive(Default)]
+struct Bench {
+    script: VecDeque<Event>,
+    load: Vec<(&'static str, Duration)>,
+    draws: Vec<Duration>,
+    inputs: Vec<Duration>,
+    // when the last scripted key was handed over, until the frame showing it is drawn
+    input_at: Option<Instant>,
+}
+
+impl Bench {
+    fn new() -> Bench {
+        Bench { script: bench_script(), ..Default::default() }
+    }
+
+    fn report(&self, aliases: usize) -> String {
+        let ms = |d: Duration| format!("{:.2}ms", d.as_secs_f64() * 1000.0);
+        let mut out = format!("tuish --bench, {} aliases\n\n", aliases);
+        for (what, took) in &self.load {
+            out.push_str(&format!("{:<16}{}\n", what, ms(*took)));
+        }
+        for (what, times) in [("draw", &self.draws), ("input latency", &self.inputs)] {
+            let mut sorted = times.clone();
+            sorted.sort();
+            let at = |p: usize| sorted.get((sorted.len() * p / 100).min(sorted.len().saturating_sub(1))).copied().unwrap_or_default();
+            out.push_str(&format!("{:<16}median {}  p95 {}  max {}  ({} samples)\n", what, ms(at(50)), ms(at(95)), ms(at(100)), sorted.len()));
+        }
+        out
+    }
+}
+
+// Moving around the list, searching, sorting and resizing. Nothing in it runs or changes anything
+fn bench_script() -> VecDeque<Event> {
+    let key = |code| Event::Key(KeyEvent::new(code, KeyModifiers::NONE));
+    let mut script = vec![key(KeyCode::Tab)];
+    script.extend(std::iter::repeat_n(key(KeyCode::Down), 100));
+    script.extend(std::iter::repeat_n(key(KeyCode::Up), 30));
+    script.push(key(KeyCode::Char('/')));
+    script.extend("dep".chars().map(|c| key(KeyCode::Char(c))));
+    script.push(Event::Key(KeyEvent::new(KeyCode::Char('r'), KeyModifiers::CONTROL)));
+    script.extend(std::iter::repeat_n(key(KeyCode::Backspace), 3));
+    script.push(key(KeyCode::Esc));
+    script.extend(std::iter::repeat_n(key(KeyCode::Char('S')), 3));
+    script.extend(std::iter::repeat_n(key(KeyCode::Char('M')), 2));
+    script.extend(std::iter::repeat_n(key(KeyCode::Char('|')), 2));
+    let (width, height) = crossterm::terminal::size().unwrap_or((80, 24));
+    script.extend([Event::Resize(width, height), Event::Resize(width, height)]);
+    script.push(key(KeyCode::Tab));
+    script.into()
+}
+
+// `tuish --record file` writes every key, paste and resize with when it came, one JSON object
+// per line so the file is good up to the last key even after a crash. `tuish --replay file`
+// feeds them back at the same pace, timers and jobs running in between as they would
+#[derive(Serialize, Deserialize)]
+struct LoggedEvent {
+    // milliseconds since the main loop started
+    at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    paste: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    resize: Option<(u16, u16)>,
+}
+
+// Names for keys that only show up in recordings, on top of NAMED_KEYS
+const LOGGED_KEYS: [(&str, KeyCode); 9] = [
+    ("esc", KeyCode::Esc), ("tab", KeyCode::Tab), ("backtab", KeyCode::BackTab), ("backspace", KeyCode::Backspace),
+    ("up", KeyCode::Up), ("down", KeyCode::Down), ("left", KeyCode::Left), ("right", KeyCode::Right), ("null", KeyCode::Null),
+];
+
+// Like KeyPress's "ctrl+r", but keeping every modifier as it came so replaying hands over the same event
+fn logged_key(key: &KeyEvent) -> String {
+    let mut out = String::new();
+    for (m, name) in [(KeyModifiers::CONTROL, "ctrl+"), (KeyModifiers::ALT, "alt+"), (KeyModifiers::SHIFT, "shift+")] {
+        if key.modifiers.contains(m) { out.push_str(name); }
+    }
+    if key.state.contains(KeyEventState::KEYPAD) { out.push_str("kp"); }
+    match key.code {
+        KeyCode::F(n) => out.push_str(&format!("f{}", n)),
+        KeyCode::Char(' ') if !key.state.contains(KeyEventState::KEYPAD) => out.push_str("space"),
+        KeyCode::Char(c) => out.push(c),
+        code => out.push_str(NAMED_KEYS.iter().chain(&LOGGED_KEYS).find(|(_, k)| *k == code).map(|(n, _)| *n).unwrap_or("null")),
+    }
+    out
+}
+
+fn logged_key_event(s: &str) -> Option<KeyEvent> {
+    let mut mods = KeyModifiers::NONE;
+    let mut key = s;
+    while let Some(i) = key.find('+') {
+        if i + 1 == key.len() { break; }
+        match &key[..i] {
+            "ctrl" => mods.insert(KeyModifiers::CONTROL),
+            "alt" => mods.insert(KeyModifiers::ALT),
+            "shift" => mods.insert(KeyModifiers::SHIFT),
+            _ => return None,
+        }
+        key = &key[i + 1..];
+    }
+    let (code, keypad) = match LOGGED_KEYS.iter().find(|(n, _)| *n == key) {
+        Some((_, code)) => (*code, false),
+        None => KeyPress::key_name(key)?,
+    };
+    let mut ev = KeyEvent::new(code, mods);
+    if keypad { ev.state = KeyEventState::KEYPAD; }
+    Some(ev)
+}
+
+struct EventLog {
+    file: fs::File,
+    start: Instant,
+}
+
+impl EventLog {
+    fn create(path: &std::path::Path) -> Result<EventLog, String> {
+        let file = fs::File::create(path).map_err(|e| format!("can't write {}: {}", path.display(), e))?;
+        Ok(EventLog { file, start: Instant::now() })
+    }
+
+    // focus changes and mouse events don't do anything here, so they aren't kept. Neither is
+    // what's typed or pasted while `private` (the passphrase, a secret parameter), the keys that
+    // move around still are so a replay goes through the same screens
+    fn write(&mut self, ev: &Event, private: bool) {
+        use std::io::Write;
+        let mut logged = LoggedEvent { at: self.start.elapsed().as_millis() as u64, key: None, paste: None, resize: None };
+        match ev {
+            Event::Key(KeyEvent { code: KeyCode::Char(_), modifiers, .. }) if private && !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => return,
+            Event::Paste(_) if private => return,
+            Event::Key(key) => logged.key = Some(logged_key(key)),
+            Event::Paste(text) => logged.paste = Some(text.clone()),
+            Event::Resize(w, h) => logged.resize = Some((*w, *h)),
+            _ => return,
+        }
+        if let Ok(line) = serde_json::to_string(&logged) { let _ = writeln!(self.file, "{}", line); }
+    }
+}
+
+struct Replay {
+    events: VecDeque<(Duration, Event)>,
+    start: Instant,
+    played: usize,
+}
+
+impl Replay {
+    fn load(path: &std::path::Path) -> Result<Replay, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+        let mut events = VecDeque::new();
+        for (n, line) in text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+            let bad = |e: &dyn std::fmt::Display| format!("{} line {}: {}", path.display(), n + 1, e);
+            let logged: LoggedEvent = serde_json::from_str(line).map_err(|e| bad(&e))?;
+            let ev = match (logged.key, logged.paste, logged.resize) {
+                (Some(key), _, _) => Event::Key(logged_key_event(&key).ok_or_else(|| bad(&format!("unknown key '{}'", key)))?),
+                (_, Some(text), _) => Event::Paste(text),
+                (_, _, Some((w, h))) => Event::Resize(w, h),
+                _ => return Err(bad(&"expected a key, paste or resize")),
+            };
+            events.push_back((Duration::from_millis(logged.at), ev));
+        }
+        Ok(Replay { events, start: Instant::now(), played: 0 })
+    }
+
+    // Stands in for event::poll: sleeps until the next event is due, or for `wait` if that's sooner.
+    // Once everything is played this says there's input, and next() ends it
+    fn poll(&self, wait: Option<Duration>) -> bool {
+        let due = self.events.front().map_or(Duration::ZERO, |(at, _)| at.saturating_sub(self.start.elapsed()));
+        match wait {
+            Some(wait) if wait < due => { std::thread::sleep(wait); false }
+            _ => { std::thread::sleep(due); true }
+        }
+    }
+
+    fn next(&mut self) -> Option<Event> {
+        let (_, ev) = self.events.pop_front()?;
+        self.played += 1;
+        Some(ev)
+    }
+}
+
+// Startup work the first frame can do without, started in the background right away and
+// collected once that frame is drawn, before any key is handled
+struct Deferred {
+    state: std::thread::JoinHandle<StateFile>,
+    // .git/tuish.json of the repository we're in and its aliases
+    repo: std::thread::JoinHandle<Option<(PathBuf, Vec<Alias>)>>,
+    branch: std::thread::JoinHandle<Option<String>>,
+    offset: std::thread::JoinHandle<i64>,
+}
+
+impl Deferred {
+    fn start(state_file: &std::path::Path, branch: bool, clock: bool) -> Deferred {
+        let state_file = state_file.to_path_buf();
+        Deferred {
+            state: std::thread::spawn(move || load_state(&state_file)),
+            repo: std::thread::spawn(|| repo_alias_file().map(|f| { let aliases = load_repo_aliases(&f); (f, aliases) })),
+            branch: std::thread::spawn(move || if branch { git_branch() } else { None }),
+            offset: std::thread::spawn(move || if clock { utc_offset() } else { 0 }),
+        }
+    }
+}
+
+// While more input is waiting the screen is only redrawn this often, so a held key or a
+// window being dragged bigger doesn't draw a frame per event
+const MAX_DRAW_DELAY: Duration = Duration::from_millis(50);
+
+fn main() {
+    let cfg_path = config_path();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut bench = (args.len() == 1 && args[0] == "--bench").then(Bench::new);
+    let mut event_log = None;
+    let mut replay = None;
+    match args.as_slice() {
+        [flag, file] if flag == "--record" => match EventLog::create(std::path::Path::new(file)) {
+            Ok(log) => event_log = Some(log),
+            Err(e) => { eprintln!("tuish: {}", e); std::process::exit(1); }
+        },
+        [flag, file] if flag == "--replay" => match Replay::load(std::path::Path::new(file)) {
+            Ok(r) => replay = Some(r),
+            Err(e) => { eprintln!("tuish: {}", e); std::process::exit(1); }
+        },
+        [flag] if flag == "--popup" => POPUP.store(true, Ordering::Relaxed),
+        [cmd] if cmd == "popup" => std::process::exit(open_popup(&ensure_config(&cfg_path))),
+        _ if !args.is_empty() && bench.is_none() => {
+            let mut code = run_cli(&args, &cfg_path);
+            finish_saves();
+            if let Some(e) = SAVE_ERROR.lock().unwrap().take() { eprintln!("tuish: {}", e); code = 1; }
+            std::process::exit(code);
+        }
+        _ => {}
+    }
+
+    let started = Instant::now();
+    let mut cfg = ensure_config(&cfg_path);
+    if let Some(b) = &mut bench { b.load.push(("config", started.elapsed())); }
+    // before the TUI takes over the terminal, the passphrase prompt needs it
+    if let Err(e) = unlock_config(&mut cfg, &cfg_path) {
+        eprintln!("tuish: {}", e);
+        std::process::exit(1);
+    }
+    load_catalog(&cfg, &cfg_path);
+    SCREEN_READER.store(cfg.screen_reader, Ordering::Relaxed);
+    let (colors, theme_errors) = Theme::from_config(&cfg.theme, color_depth());
+    THEME.set(colors).ok();
+    // forget trashed aliases once they're old enough, written back on the next save
+    let cutoff = unix_now().saturating_sub(cfg.trash_days * 24 * 60 * 60);
+    cfg.trash.retain(|t| t.deleted >= cutoff);
+    // snapshotting runs the user's shell, so do it before taking over the terminal
+    let mut shell_env = ShellEnv::from_config(&cfg);
+
+    enable_raw_mode().unwrap();
+    KEY_ENHANCEMENT.store(supports_keyboard_enhancement().unwrap_or(false), Ordering::Relaxed);
+    // save the terminal's own title (xterm title stack), leave_tui puts it back
+    execute!(stdout(), Print("\x1b[22;0t")).ok();
+    enter_screen();
+    let backend = CrosstermBackend::new(stdout());
+    let mut terminal = Terminal::new(backend).unwrap();
+
+    // the active profile's directory, before the repository aliases and the branch are looked up there
+    let start_dir = std::env::current_dir().ok();
+    let cwd_error = profile_cwd(&cfg).and_then(|dir| std::env::set_current_dir(&dir).err().map(|e| trf("Couldn't change to {}: {}", &[&dir.display(), &e])));
+
+    // state.json, repository aliases, the branch and the time zone come in after the first frame
+    let state_file = state_path(&cfg_path);
+    let clock = cfg.header.iter().any(|w| w == "clock");
+    let mut deferred = Some(Deferred::start(&state_file, cfg.header.iter().any(|w| w == "branch"), clock));
+
+    // Load aliases from config
+    let started = Instant::now();
+    let mut aliases: Vec<Alias> = load_aliases(&cfg);
+    // bundles go last, anyone's own keybinds win over a shared one
+    let (bundled, bundle_errors) = load_bundles(&cfg, &cfg_path, false);
+    aliases.extend(bundled);
+    if let Some(b) = &mut bench { b.load.push(("aliases", started.elapsed())); }
+    // the branch comes with the deferred startup work, the other widgets (if any) from the ticker
+    let mut header = HeaderStatus::default();
+    let header_rx = cfg.header.iter().any(|w| w != "branch" && w != "clock").then(|| header_ticker(cfg.header.clone()));
+    let mut offset = 0;
+    // when each alias with `every` runs next
+    let mut schedule: HashMap<String, Instant> = HashMap::new();
+
+    let options = action_list(&cfg);
+    let mut opt_state = ListState::default();
+    opt_state.select(Some(0));
+
+    // selection state for aliases list and focus
+    let mut alias_state = ListState::default();
+    if !aliases.is_empty() { alias_state.select(Some(0)); } else { alias_state.select(None); }
+    let mut focus = Focus::Actions;
+
+    let mut ui_mode = UiMode::Main;
+    // ambiguous Tab completions for the command being typed
+    let mut completions: Vec<String> = Vec::new();
+    // the program typed in Add alias when it isn't on PATH, and the closest one that is
+    let mut not_found: Option<(String, Option<String>)> = None;
+    let mut path_var = shell_env.path_var();
+    // (results, how many are done, out of) while a big config is still being checked
+    let mut checking = bench.is_none().then(|| (check_in_background(&aliases, &path_var), 0, aliases.len()));
+    if checking.is_none() {
+        let started = Instant::now();
+        check_aliases(&mut aliases, &path_var);
+        if let Some(b) = &mut bench { b.load.push(("PATH check", started.elapsed())); }
+    }
+    let mut selected_opt: usize = 0;
+    // everything run so far (across sessions), oldest first
+    let mut state = StateFile::default();
+    let mut show_recent = true;
+    // first key of a chord keybind, waiting for the second
+    let mut pending_leader: Option<KeyPress> = None;
+    // group opened with its key from `groups`, the next key is looked up among its aliases
+    let mut open_group: Option<String> = None;
+    // first alias row on screen, kept in step with the list's own scrolling
+    let mut alias_offset: usize = 0;
+    // names and commands ready for `/`, brought up to date on each search
+    let mut search_index = SearchIndex::default();
+    // formatted alias rows, only the ones on screen are ever built
+    let mut row_cache = RowCache::default();
+    // commands run in the output pane, and how many lines it showed last frame
+    let mut jobs: Vec<Job> = Vec::new();
+    let mut output_rows: usize = 0;
+    // alias list on the left, live output on the right
+    let mut split_view = false;
+    // the last pane job to finish out of sight
+    let mut toast: Option<Toast> = None;
+    // how the alias list is ordered, and the order it was loaded in to go back to
+    let mut sort = AliasSort::Config;
+    // `M`, the list follows what gets run for the rest of the session, whatever `sort` says
+    let mut mru = false;
+    let mut loaded_order: Vec<String> = aliases.iter().map(|a| a.name.clone()).collect();
+    // last window title we set, redone when what's running changes
+    let mut shown_title = String::new();
+    // links in the output pane this frame, (column, row, text, target), written over it as OSC 8 after drawing
+    let hyperlinks = hyperlinks_supported(&cfg);
+    let mut links_on_screen: Vec<(u16, u16, String, String)> = Vec::new();
+    // when the config was last seen as we know it
+    let mut cfg_stamp = config_stamp(&cfg_path);
+    let mut cfg_checked = Instant::now();
+    // whether anything on screen changed since it was last drawn, and when that was
+    let mut dirty = true;
+    let mut drawn_at = Instant::now();
+    let mut drawn_second = unix_now();
+
+    if !bundle_errors.is_empty() || !theme_errors.is_empty() || cwd_error.is_some() {
+        ui_mode = UiMode::Message([bundle_errors, theme_errors, cwd_error.into_iter().collect()].concat().join("; "));
+    }
+
+    // idle lock, only when there's a passphrase to unlock it with
+    let lock_pass = cfg.lock_after.and_then(|_| lock_passphrase(&cfg));
+    let lock_after = cfg.lock_after.filter(|_| lock_pass.is_some()).map(|m| Duration::from_secs(m * 60));
+    let mut last_input = Instant::now();
+    if cfg.lock_after.is_some() && lock_pass.is_none() {
+        ui_mode = UiMode::Message(tr("lock-after needs a passphrase: turn on encrypt or run tuish secret set lock").to_string());
+    }
+
+    // the startup alias comes up first in the output pane, Esc gets to the menu. It's looked up
+    // once the repo's aliases are in, it can be one of those
+    let mut startup_alias = cfg.startup_alias.clone().filter(|_| bench.is_none());
+
+    // timing in a recording (or a replay) starts here, so the time spent loading doesn't count
+    if let Some(log) = &mut event_log {
+        log.start = Instant::now();
+        let (width, height) = crossterm::terminal::size().unwrap_or((80, 24));
+        log.write(&Event::Resize(width, height), false);
+    }
+    if let Some(r) = &mut replay { r.start = Instant::now(); }
+    loop {
+        if let Some(rx) = &header_rx {
+            while let Ok(status) = rx.try_recv() { dirty |= status != header; header = status; }
+        }
+        if let UiMode::Importing { rx, done, items } = &mut ui_mode {
+            while let Ok((source, found)) = rx.try_recv() {
+                done.push((source, found.len()));
+                items.extend(found);
+                dirty = true;
+            }
+            if done.len() == IMPORT_SOURCES.len() {
+                let items = merge_imports(std::mem::take(items));
+                ui_mode = if items.is_empty() {
+                    UiMode::Message(tr("Nothing new to import").to_string())
+                } else {
+                    let picked = vec![false; items.len()];
+                    UiMode::Tasks { title: "Import aliases (Enter: run, Space: pick, s: save as aliases)", items, picked, cursor: 0 }
+                };
+            }
+        }
+        if let UiMode::LoadingChoices { alias, names, rx } = &mut ui_mode && let Ok(choices) = rx.try_recv() {
+            ui_mode = params_form(std::mem::take(alias), std::mem::take(names), choices, &state);
+            dirty = true;
+        }
+        if let UiMode::ReadingHelp { program, rx } = &mut ui_mode && let Ok(options) = rx.try_recv() {
+            let values = vec![None; options.len()];
+            ui_mode = UiMode::BuildFlags { program: std::mem::take(program), options, values, args: String::new(), cursor: 0, editing: false };
+            dirty = true;
+        }
+        if let Some((rx, done, _)) = &mut checking {
+            // whatever came in since last time goes on in one pass over the list
+            let mut checked: HashMap<String, bool> = HashMap::new();
+            let finished = loop {
+                match rx.try_recv() {
+                    Ok(batch) => { *done += batch.len(); checked.extend(batch); }
+                    Err(TryRecvError::Empty) => break false,
+                    Err(TryRecvError::Disconnected) => break true,
+                }
             };
-            let mut alias_list = List::new(alias_items)
-                .block(Block::default().borders(Borders::ALL).title("Aliases"));
-            // highlight style only when aliases have focus
-            alias_list = alias_list.highlight_style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)).highlight_symbol("-> ");
-            f.render_stateful_widget(alias_list, chunks[1], &mut alias_state);
-
-            // Options
-            let opt_items: Vec<ListItem> = options.iter().map(|o| ListItem::new(o.to_string()).style(Style::default().fg(Color::White))).collect();
-            let opt_list = List::new(opt_items)
-                .block(Block::default().borders(Borders::ALL).title("Actions").style(Style::default().fg(Color::Green)))
-                .highlight_style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))
-                .highlight_symbol("> ");
-            f.render_stateful_widget(opt_list, chunks[2], &mut opt_state);
-
-            // If in adding/editing mode, show a small popup
-            match &ui_mode {
-                UiMode::Main => { /* nothing to draw on top */ }
-                UiMode::Adding { step, name, command, keybind } => {
-                    let area = ratatui::layout::Rect::new(size.width/6, size.height/3, size.width*2/3, 7);
-                    let mut text = vec![format!("Step {}", step)];
-                    if *step == 1 { text.push(format!("Name: {}", name)); }
-                    if *step == 2 { text.push(format!("Command: {}", command)); }
-                    if *step == 3 { text.push(format!("Keybind (single char, or empty): {}", keybind.map(|c| c.to_string()).unwrap_or_default())); }
-                    let p = Paragraph::new(text.join("\n")).block(Block::default().borders(Borders::ALL).title("Add alias"));
-                    f.render_widget(Clear, area);
-                    f.render_widget(p, area);
+            if !checked.is_empty() {
+                for a in aliases.iter_mut() {
+                    if let Some(missing) = checked.get(&a.name) { a.missing = *missing; }
                 }
-                UiMode::Editing { index, command } => {
-                    let area = ratatui::layout::Rect::new(size.width/6, size.height/3, size.width*2/3, 5);
-                    let title = format!("Edit command for: {}", aliases.get(*index).map(|a| a.name.clone()).unwrap_or_default());
-                    let p = Paragraph::new(command.clone()).block(Block::default().borders(Borders::ALL).title(title));
-                    f.render_widget(Clear, area);
-                    f.render_widget(p, area);
-                }
-                UiMode::EditingSelect => {
-                    // use alias_state so selection is shared and list auto-scrolls when too long
-                    let area_height = (size.height / 3).max(3);
-                    let area = ratatui::layout::Rect::new(size.width/6, size.height/3, size.width*2/3, area_height);
-                    let items: Vec<ListItem> = aliases.iter().map(|a| ListItem::new(format!("{} - {}", a.name, a.command))).collect();
-                    let mut sel_state = ListState::default();
-                    sel_state.select(alias_state.selected());
-                    let list = List::new(items).block(Block::default().borders(Borders::ALL).title("Select alias to edit"))
-                        .highlight_style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)).highlight_symbol("> ");
-                    f.render_stateful_widget(list, area, &mut sel_state);
-                }
-                UiMode::RemovingSelect => {
-                    let area_height = (size.height / 3).max(3);
-                    let area = ratatui::layout::Rect::new(size.width/6, size.height/3, size.width*2/3, area_height);
-                    let items: Vec<ListItem> = aliases.iter().map(|a| ListItem::new(format!("{} - {}", a.name, a.command))).collect();
-                    let mut sel_state = ListState::default();
-                    sel_state.select(alias_state.selected());
-                    let list = List::new(items).block(Block::default().borders(Borders::ALL).title("Select alias to remove"))
-                        .highlight_style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)).highlight_symbol("> ");
-                    f.render_stateful_widget(list, area, &mut sel_state);
-                }
-                UiMode::Message(msg) => {
-                    let w = (size.width / 3).max(20);
-                    let h = 3;
-                    let area = ratatui::layout::Rect::new((size.width.saturating_sub(w))/2, (size.height.saturating_sub(h))/2, w, h);
-                    let p = Paragraph::new(msg.clone()).style(Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)).block(Block::default().borders(Borders::ALL).title("Info"));
+                dirty = true;
+            }
+            if finished { checking = None; dirty = true; }
+        }
+        // the process list is read again every second, for the CPU % and to drop what exited
+        if let UiMode::Processes { items, cursor, ticks, sampled } = &mut ui_mode && sampled.elapsed() >= Duration::from_secs(1) {
+            *items = job_processes(&jobs, ticks, sampled.elapsed());
+            *cursor = (*cursor).min(items.len().saturating_sub(1));
+            *sampled = Instant::now();
+            dirty = true;
+        }
+        let title = running_title(&jobs);
+        if title != shown_title {
+            set_title(&title);
+            shown_title = title;
+        }
+
+        // Draw UI
+        // a burst of input is handled as a whole before drawing, as long as the screen doesn't fall behind
+        // gone after a while, or once its output is on screen anyway
+        if let Some(t) = &toast {
+            let expired = t.shown.is_some_and(|at| at.elapsed() >= TOAST_TIME);
+            let viewed = matches!(ui_mode, UiMode::Output { job } if jobs.get(job).is_some_and(|j| j.name == t.name && j.started == t.started));
+            if expired || viewed { toast = None; dirty = true; }
+        }
+        let catching_up = dirty && drawn_at.elapsed() < MAX_DRAW_DELAY && event::poll(Duration::ZERO).unwrap_or(false);
+        if dirty && !catching_up {
+            dirty = false;
+            drawn_at = Instant::now();
+            drawn_second = unix_now();
+            links_on_screen.clear();
+            let started = Instant::now();
+            let toast_shown = matches!(ui_mode, UiMode::Main | UiMode::Output { .. });
+            if let Some(t) = &mut toast && toast_shown { t.shown.get_or_insert_with(Instant::now); }
+            terminal.draw(|f| {
+                let size = f.size();
+
+                // small tmux splits get no margin, a one-line actions hint and stacked panes
+                let small = compact(size);
+
+                let chunks = Layout::default()
+                    .direction(Direction::Vertical)
+                    .margin(if small { 0 } else { 1 })
+                    .constraints([
+                        Constraint::Length(1), // header
+                        Constraint::Min(if small { 1 } else { 3 }), // aliases (will be clipped if too large)
+                        Constraint::Length(if small { 1 } else { 7 }), // actions
+                        Constraint::Length(if screen_reader() { 1 } else { 0 }), // status line
+                    ].as_ref())
+                    .split(size);
+
+                let mut header_spans = vec![Span::styled("tuish", Style::default().fg(theme().title).add_modifier(Modifier::BOLD))];
+                // the active profile stands out, nobody should have to wonder whether this is prod
+                if let Some(name) = &cfg.profile {
+                    let color = cfg.profiles.get(name).and_then(|p| p.color.as_deref()).and_then(|c| parse_color(c, color_depth()))
+                        .unwrap_or(if matches!(name.as_str(), "prod" | "production") { theme().error } else { theme().selected });
+                    header_spans.push(Span::raw("  "));
+                    header_spans.push(Span::styled(format!(" {} ", name), Style::default().fg(Color::Black).bg(color).add_modifier(Modifier::BOLD)));
+                }
+                header_spans.extend(header_widgets(&header, &cfg.header));
+                // the clock and the next scheduled run on the right
+                let mut right = Vec::new();
+                if let Some((name, at)) = schedule.iter().min_by_key(|(_, at)| **at) {
+                    let left = at.saturating_duration_since(Instant::now());
+                    right.push(Span::styled(trf("{} in {}", &[name, &format_countdown(left)]), Style::default().fg(theme().dim)));
+                }
+                if clock {
+                    right.push(Span::styled(format!("  {}", clock_text(offset)), Style::default().fg(theme().title)));
+                }
+                f.render_widget(Paragraph::new(Spans::from(right)).alignment(Alignment::Right), chunks[0]);
+                if recording() {
+                    header_spans.push(Span::styled(glyph("  ● REC", "  recording"), Style::default().fg(theme().error).add_modifier(Modifier::BOLD)));
+                }
+                let header = Paragraph::new(Spans::from(header_spans));
+                f.render_widget(header, chunks[0]);
+
+                // split view: the lists keep the left half, the right half follows the selected alias's output
+                let list_area = if split_view && !screen_reader() {
+                    let halves = Layout::default()
+                        .direction(if small { Direction::Vertical } else { Direction::Horizontal })
+                        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+                        .split(chunks[1]);
+                    let shown = alias_state.selected().and_then(|i| aliases.get(i)).and_then(|a| latest_job(&jobs, &a.name))
+                        .or_else(|| jobs.len().checked_sub(1));
+                    let rows = halves[1].height.saturating_sub(2) as usize;
+                    let (title, text) = match shown.map(|i| &jobs[i]) {
+                        Some(j) => {
+                            let (status, color) = j.status();
+                            let title = Spans::from(vec![
+                                Span::raw(format!("{} ", j.name)),
+                                Span::styled(format!("[{}]", status), Style::default().fg(color)),
+                            ]);
+                            (title, j.visible(rows).into_iter().map(|(l, e, _)| output_spans(l, e)).collect())
+                        }
+                        None => (Spans::from(tr("Output")), vec![Spans::from(Span::styled(tr("run an alias with o to see its output here"), Style::default().fg(theme().dim)))]),
+                    };
+                    f.render_widget(Paragraph::new(text).block(panel().title(title)), halves[1]);
+                    halves[0]
+                } else {
+                    chunks[1]
+                };
+
+                // last few runs on top of the aliases, Alt+1.. runs them again
+                let recent = recent_runs(&state.history);
+                let alias_area = if show_recent && !recent.is_empty() && !screen_reader() && !small {
+                    let parts = Layout::default()
+                        .direction(Direction::Vertical)
+                        .constraints([Constraint::Length(recent.len() as u16 + 2), Constraint::Min(3)].as_ref())
+                        .split(list_area);
+                    let rows: Vec<ListItem> = recent.iter().enumerate().map(|(i, r)| {
+                        let status = match r.exit {
+                            Some(0) => Span::styled(" ok", Style::default().fg(theme().ok)),
+                            Some(c) => Span::styled(format!(" {}", c), Style::default().fg(theme().error)),
+                            None => Span::styled(" ?", Style::default().fg(theme().dim)),
+                        };
+                        ListItem::new(Spans::from(vec![
+                            Span::styled(format!("M-{} ", i + 1), Style::default().fg(theme().dim)),
+                            Span::raw(if r.name == ADHOC_NAME { format!(":{}", r.command) } else { format!("{} - {}", r.name, r.command) }),
+                            status,
+                        ]))
+                    }).collect();
+                    f.render_widget(List::new(rows).block(panel().title(brief(tr("Recent (r: hide, Ctrl+S: save last as alias)"), size))), parts[0]);
+                    parts[1]
+                } else {
+                    list_area
+                };
+
+                // Aliases block (clipped if too many) - make it selectable when focused
+                // follow the list's scrolling (same rule as ratatui) so the 1-9 numbers match the rows on screen
+                let rows_shown = alias_area.height.saturating_sub(2) as usize;
+                if let Some(sel) = alias_state.selected() {
+                    if sel >= alias_offset + rows_shown { alias_offset = sel + 1 - rows_shown; } else if sel < alias_offset { alias_offset = sel; }
+                }
+                // only the rows on screen go to the list, with the selection counted from the first of them
+                let visible = alias_offset.min(aliases.len())..(alias_offset + rows_shown).min(aliases.len());
+                let mut visible_state = ListState::default();
+                visible_state.select(alias_state.selected().and_then(|i| i.checked_sub(visible.start)));
+                if row_cache.patterns != cfg.danger_patterns || row_cache.rows.len() > aliases.len() * 2 + 64 {
+                    row_cache = RowCache { patterns: cfg.danger_patterns.clone(), rows: HashMap::new() };
+                }
+                for a in &aliases[visible.clone()] {
+                    if !row_cache.rows.get(&a.name).is_some_and(|r| r.shows(a)) {
+                        row_cache.rows.insert(a.name.clone(), AliasRow::new(a, &cfg.danger_patterns));
+                    }
+                }
+                let alias_items: Vec<ListItem> = if aliases.is_empty() {
+                    vec![ListItem::new(tr("(no aliases)")).style(Style::default().fg(theme().dim))]
+                } else {
+                    visible.clone().map(|i| {
+                        let row = &row_cache.rows[&aliases[i].name];
+                        let mut spans = Vec::new();
+                        let number = if i - alias_offset < 9 { format!("{} ", i - alias_offset + 1) } else { "  ".to_string() };
+                        spans.push(Span::styled(number, Style::default().fg(theme().dim)));
+                        spans.extend(row.badges.iter().map(|(text, style)| Span::styled(text.as_str(), *style)));
+                        match &ui_mode {
+                            UiMode::InlineEdit { index, command } if *index == i => {
+                                spans.push(Span::raw(format!("{} - ", row.label)));
+                                spans.push(Span::styled(format!("{}_", one_line(command)), Style::default().fg(theme().text).add_modifier(Modifier::UNDERLINED)));
+                            }
+                            _ => spans.push(Span::raw(row.line.as_str())),
+                        }
+                        ListItem::new(Spans::from(spans)).style(Style::default().fg(theme().alias))
+                    }).collect()
+                };
+                let mut alias_title = match if mru { Some(tr("recently used")) } else { sort.label() } {
+                    Some(by) => format!("{} ({})", tr("Aliases"), by),
+                    None => tr("Aliases").to_string(),
+                };
+                if let Some((_, done, total)) = &checking && *total >= BACKGROUND_CHECK_MIN {
+                    alias_title.push_str(&trf(" checking {}/{}", &[done, total]));
+                }
+                let mut alias_list = List::new(alias_items)
+                    .block(panel().title(brief(&alias_title, size)));
+                // highlight style only when aliases have focus
+                alias_list = alias_list.highlight_style(Style::default().fg(theme().selected).add_modifier(Modifier::BOLD)).highlight_symbol(glyph("-> ", ""));
+                f.render_stateful_widget(alias_list, alias_area, &mut visible_state);
+
+                // Options
+                if small {
+                    // no room for the list, just the selected action (or how to get to it)
+                    let hint = match focus {
+                        Focus::Actions => format!("{}{}", glyph("> ", ""), tr(options[opt_state.selected().unwrap_or(0)])),
+                        Focus::Aliases => tr("Tab: actions").to_string(),
+                    };
+                    f.render_widget(Paragraph::new(hint).style(Style::default().fg(theme().ok)), chunks[2]);
+                } else {
+                    let opt_items: Vec<ListItem> = options.iter().map(|o| ListItem::new(tr(o)).style(Style::default().fg(theme().text))).collect();
+                    let opt_list = List::new(opt_items)
+                        .block(panel().title(tr("Actions")).style(Style::default().fg(theme().ok)))
+                        .highlight_style(Style::default().fg(theme().selected).add_modifier(Modifier::BOLD))
+                        .highlight_symbol(glyph("> ", ""));
+                    f.render_stateful_widget(opt_list, chunks[2], &mut opt_state);
+                }
+
+                // one predictable line saying what's selected, with the cursor on it so the reader follows along
+                if screen_reader() {
+                    let status = match (popup_status(&ui_mode, &cfg), &focus) {
+                        (Some(status), _) => status,
+                        (None, Focus::Aliases) => match alias_state.selected().and_then(|i| aliases.get(i).map(|a| (i, a))) {
+                            Some((i, a)) => {
+                                let mut line = trf("Alias {} of {}: {}, {}", &[&(i + 1), &aliases.len(), &a.name, &a.command]);
+                                if let Some(k) = keybind_label(a) { line.push_str(&trf(", key {}", &[&k])); }
+                                if a.missing { line.push_str(tr(", missing")); }
+                                line
+                            }
+                            None => tr("No aliases").to_string(),
+                        },
+                        (None, Focus::Actions) => {
+                            let i = opt_state.selected().unwrap_or(0);
+                            trf("Action {} of {}: {}", &[&(i + 1), &options.len(), &tr(options[i])])
+                        }
+                    };
+                    f.render_widget(Paragraph::new(status), chunks[3]);
+                    f.set_cursor(chunks[3].x, chunks[3].y);
+                }
+
+                // If in adding/editing mode, show a small popup
+                match &ui_mode {
+                    UiMode::Main if pending_leader.is_some() || open_group.is_some() => {
+                        // which-key style hint with everything the leader or group can continue into
+                        let rows: Vec<ListItem> = aliases.iter().filter_map(|a| {
+                            let k = a.keybind?;
+                            if a.group != open_group { return None; }
+                            let k = match pending_leader {
+                                Some(_) if k.leader != pending_leader => return None,
+                                Some(_) => k.key.to_string(),
+                                None => k.to_string(),
+                            };
+                            Some(ListItem::new(Spans::from(vec![
+                                Span::styled(format!("{:<8}", k), Style::default().fg(theme().selected)),
+                                Span::raw(a.name.clone()),
+                            ])))
+                        }).collect();
+                        let height = (rows.len() as u16 + 2).min(size.height / 2);
+                        let width = (size.width / 3).max(30).min(size.width);
+                        let area = fit(Rect::new(size.width - width, size.height - height, width, height), size);
+                        let title = match (&open_group, pending_leader) {
+                            (Some(g), Some(l)) => format!("{}: {} ...", g, l),
+                            (Some(g), None) => format!("{} ...", g),
+                            (None, l) => format!("{} ...", l.map(|l| l.to_string()).unwrap_or_default()),
+                        };
+                        f.render_widget(Clear, area);
+                        f.render_widget(List::new(rows).block(panel().title(title)), area);
+                    }
+                    UiMode::Main => { /* nothing to draw on top */ }
+                    UiMode::SaveOutput { job, path } => {
+                        let area = fit(Rect::new(size.width/6, size.height/3, size.width*2/3, if completions.is_empty() { 3 } else { 4 }), size);
+                        let title = trf("Save output of {} to", &[&jobs[*job].name]);
+                        let mut text = path.clone();
+                        if !completions.is_empty() { text = format!("{}\n{}", text, completions.join("  ")); }
+                        let p = Paragraph::new(text).block(panel().title(title));
+                        f.render_widget(Clear, area);
+                        f.render_widget(p, area);
+                    }
+                    UiMode::Stats => {
+                        let area = fit(Rect::new(1, 1, size.width.saturating_sub(2), size.height.saturating_sub(2)), size);
+                        let stats = alias_stats(&state.history);
+                        let label = |name: &str| if name == ADHOC_NAME { tr("(one-off)").to_string() } else { name.to_string() };
+                        let parts = Layout::default()
+                            .direction(Direction::Vertical)
+                            .constraints([Constraint::Length(3), Constraint::Length(10), Constraint::Length(5), Constraint::Min(3)].as_ref())
+                            .split(area);
+                        f.render_widget(Clear, area);
+
+                        let failed = state.history.iter().filter(|r| r.exit != Some(0)).count();
+                        let total_secs: f64 = state.history.iter().map(|r| r.secs).sum();
+                        let summary = trf("{} runs, {} failed ({}%), {} on average", &[
+                            &state.history.len(), &failed, &format!("{:.0}", failed as f64 * 100.0 / state.history.len() as f64),
+                            &format_secs(total_secs / state.history.len() as f64)]);
+                        f.render_widget(Paragraph::new(summary).block(panel().title(brief(tr("Stats (Esc to close)"), size))), parts[0]);
+
+                        // most used aliases, as many bars as fit
+                        let labels: Vec<String> = stats.iter().map(|s| label(&s.name)).collect();
+                        let bars: Vec<(&str, u64)> = labels.iter().zip(&stats).take((parts[1].width / 9).max(1) as usize)
+                            .map(|(l, s)| (l.as_str(), s.runs as u64)).collect();
+                        let chart = BarChart::default().data(&bars).bar_width(8).bar_gap(1)
+                            .bar_style(Style::default().fg(theme().alias))
+                            .value_style(Style::default().fg(Color::Black).bg(theme().alias))
+                            .block(panel().title(tr("Most used")));
+                        f.render_widget(chart, parts[1]);
+
+                        // how long the last runs took, newest on the right
+                        let width = parts[2].width.saturating_sub(2) as usize;
+                        let durations: Vec<u64> = state.history.iter().rev().take(width).rev().map(|r| (r.secs * 1000.0) as u64).collect();
+                        let spark = Sparkline::default().data(&durations).style(Style::default().fg(theme().selected))
+                            .block(panel().title(tr("Duration of the last runs")));
+                        f.render_widget(spark, parts[2]);
+
+                        let rows: Vec<ListItem> = stats.iter().map(|s| {
+                            let rate = s.failures as f64 * 100.0 / s.runs as f64;
+                            let color = if s.failures == 0 { theme().ok } else if s.failures < s.runs { theme().selected } else { theme().error };
+                            ListItem::new(Spans::from(vec![
+                                Span::raw(format!("{:<24} {:>5} runs  ", label(&s.name), s.runs)),
+                                Span::styled(format!("{:>3.0}% failed", rate), Style::default().fg(color)),
+                                Span::raw(format!("  {} avg", format_secs(s.secs / s.runs as f64))),
+                            ]))
+                        }).collect();
+                        f.render_widget(List::new(rows).block(panel().title(tr("Per alias"))), parts[3]);
+                    }
+                    UiMode::RunHistory { cursor, failures_only } => {
+                        let area = fit(Rect::new(1, 1, size.width.saturating_sub(2), size.height.saturating_sub(2)), size);
+                        let now = unix_now();
+                        let shown = history_shown(&state.history, *failures_only);
+                        let rows: Vec<ListItem> = if shown.is_empty() {
+                            vec![ListItem::new(Span::styled(tr("no failed runs"), Style::default().fg(theme().dim)))]
+                        } else {
+                            shown.iter().map(|r| {
+                                let (status, color) = match r.exit {
+                                    Some(0) => (tr("ok").to_string(), theme().ok),
+                                    Some(c) => (trf("exit {}", &[&c]), theme().error),
+                                    None => (tr("killed").to_string(), theme().error),
+                                };
+                                let when = if r.at == 0 { String::new() } else { age_text(now.saturating_sub(r.at)) };
+                                let label = if r.name == ADHOC_NAME { format!(":{}", r.command) } else { format!("{} - {}", r.name, r.command) };
+                                ListItem::new(Spans::from(vec![
+                                    Span::styled(format!("{:<8}", status), Style::default().fg(color).add_modifier(Modifier::BOLD)),
+                                    Span::styled(format!("{:>8} {:>7}  ", when, format_secs(r.secs)), Style::default().fg(theme().dim)),
+                                    Span::styled(label, Style::default().fg(color)),
+                                ]))
+                            }).collect()
+                        };
+                        let title = if *failures_only { tr("Run history, failures only (f: all, Enter: run again, Esc to close)") } else { tr("Run history (f: failures only, Enter: run again, Esc to close)") };
+                        let mut sel_state = ListState::default();
+                        sel_state.select(Some(*cursor));
+                        let list = List::new(rows).block(panel().title(brief(title, size)))
+                            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+                        f.render_widget(Clear, area);
+                        f.render_stateful_widget(list, area, &mut sel_state);
+                    }
+                    UiMode::Diff { name, lines, scroll } => {
+                        let area = fit(Rect::new(1, 1, size.width.saturating_sub(2), size.height.saturating_sub(2)), size);
+                        let changed = lines.iter().filter(|(c, _)| *c != ' ').count();
+                        let text: Vec<Spans> = lines.iter().skip(*scroll as usize).take(area.height as usize).map(|(c, l)| {
+                            let style = match c {
+                                '+' => Style::default().fg(theme().ok),
+                                '-' => Style::default().fg(theme().error),
+                                _ => Style::default().fg(theme().dim),
+                            };
+                            Spans::from(Span::styled(format!("{} {}", c, l), style))
+                        }).collect();
+                        let title = trf("{}: last run vs the one before, {} lines changed (Esc to close)", &[&name, &changed]);
+                        f.render_widget(Clear, area);
+                        f.render_widget(Paragraph::new(text).block(panel().title(title)), area);
+                    }
+                    UiMode::Output { job } => {
+                        let j = &jobs[*job];
+                        let mut area = fit(Rect::new(1, 1, size.width.saturating_sub(2), size.height.saturating_sub(2)), size);
+                        // with more than one output around, a tab per job on top, colored by its status
+                        if jobs.len() > 1 {
+                            let titles: Vec<Spans> = jobs.iter().map(|j| {
+                                let (_, color) = j.status();
+                                let mark = if j.running() { glyph("●", "running") } else if j.exit == Some(0) { glyph("✓", "ok") } else { glyph("✗", "failed") };
+                                Spans::from(vec![Span::raw(format!("{} ", j.name)), Span::styled(mark, Style::default().fg(color))])
+                            }).collect();
+                            let bar = Rect::new(area.x, area.y, area.width, 1);
+                            let tabs = Tabs::new(titles).select(*job).highlight_style(Style::default().fg(theme().selected).add_modifier(Modifier::BOLD));
+                            f.render_widget(Clear, bar);
+                            f.render_widget(tabs, bar);
+                            area.y += 1;
+                            area.height = area.height.saturating_sub(1);
+                        }
+                        output_rows = area.height.saturating_sub(2) as usize;
+                        let text: Vec<Spans> = j.visible(output_rows).into_iter().map(|(l, e, _)| output_spans(l, e)).collect();
+                        if hyperlinks {
+                            let inner = panel().inner(area);
+                            for (row, (line, _, links)) in j.visible(output_rows).into_iter().enumerate() {
+                                for (from, to, target) in links {
+                                    let (from, to) = (*from, *to);
+                                    // only links that aren't cut off at the edge, and where counting chars gives the column
+                                    if to > inner.width as usize || !line.chars().take(from).all(|c| c.is_ascii()) { break; }
+                                    let text: String = line.chars().skip(from).take(to - from).collect();
+                                    links_on_screen.push((inner.x + from as u16, inner.y + row as u16, text, target.clone()));
+                                }
+                            }
+                        }
+                        let (status, color) = j.status();
+                        let title = Spans::from(vec![
+                            Span::raw(format!("{} ", j.name)),
+                            Span::styled(format!("[{}]", status), Style::default().fg(color)),
+                            Span::styled(j.dropped_note().map(|n| format!(" {}", n)).unwrap_or_default(), Style::default().fg(theme().dim)),
+                            Span::styled(if j.stderr_only { tr(" [stderr only]") } else { "" }, Style::default().fg(theme().stderr)),
+                            Span::raw(brief(&trf(" follow {} (f: toggle, e: stderr only, k: kill, s: save, d: diff, o: open link, ←/→: tabs, Esc: back)", &[&tr(if j.follow { "on" } else { "off" })]), size).to_string()),
+                        ]);
+                        let p = Paragraph::new(text).block(panel().title(title));
+                        f.render_widget(Clear, area);
+                        f.render_widget(p, area);
+                    }
+                    UiMode::InlineEdit { .. } => {
+                        // the row itself is the input, only Tab candidates need somewhere to go
+                        if !completions.is_empty() {
+                            let area = fit(Rect::new(0, size.height.saturating_sub(3), size.width, 3), size);
+                            let p = Paragraph::new(completions.join("  ")).block(panel().title(tr("Completions")));
+                            f.render_widget(Clear, area);
+                            f.render_widget(p, area);
+                        }
+                    }
+                    UiMode::Adding { step, kind, name, command, keybind, ask_command, error } => {
+                        let area = fit(Rect::new(size.width/6, size.height/3, size.width*2/3, if not_found.is_some() || error.is_some() { 8 } else { 7 }), size);
+                        let mut text = vec![trf("Step {}", &[step])];
+                        if *step == 1 { text.push(trf("Name: {}", &[name])); }
+                        if *step == 1 && !*ask_command { text.push(trf("Command: {}", &[command])); }
+                        if *step == 2 {
+                            let label = if *kind == AliasKind::Cd { tr("Directory") } else { tr("Command (Ctrl+E: $EDITOR)") };
+                            text.push(format!("{}: {}", label, one_line(command)));
+                            if !completions.is_empty() { text.push(completions.join("  ")); }
+                            match &not_found {
+                                Some((prog, Some(guess))) => text.push(trf("`{}` not found — did you mean `{}`? (Tab: use it, Enter: keep it)", &[prog, guess])),
+                                Some((prog, None)) => text.push(trf("`{}` not found on PATH (Enter: keep it)", &[prog])),
+                                None => {}
+                            }
+                        }
+                        if *step == 3 { text.push(trf("Keybind (a key or two for a chord, ctrl/alt allowed, Backspace to clear): {}", &[&keybind.map(|k| k.to_string()).unwrap_or_default()])); }
+                        if let Some(e) = error { text.push(e.clone()); }
+                        let p = Paragraph::new(text.join("\n")).block(panel().title(tr("Add alias")));
+                        f.render_widget(Clear, area);
+                        f.render_widget(p, area);
+                    }
+                    UiMode::CommandBar { input } => {
+                        let height = if completions.is_empty() { 3 } else { 4 };
+                        let area = fit(Rect::new(0, size.height.saturating_sub(height), size.width, height), size);
+                        let mut text = format!(":{}", one_line(input));
+                        if !completions.is_empty() { text = format!("{}\n{}", text, completions.join("  ")); }
+                        let p = Paragraph::new(text).block(panel().title(brief(tr("Run a command (Enter: run, Ctrl+E: $EDITOR, Esc: cancel)"), size)));
+                        f.render_widget(Clear, area);
+                        f.render_widget(p, area);
+                    }
+                    UiMode::ImportShared { input } => {
+                        let area = fit(Rect::new(size.width/6, size.height/3, size.width*2/3, 3), size);
+                        let p = Paragraph::new(input.clone()).block(panel().title(brief(tr("Paste a tuish:... share string (Enter: import, Esc: cancel)"), size)));
+                        f.render_widget(Clear, area);
+                        f.render_widget(p, area);
+                    }
+                    UiMode::Editing { index, command } => {
+                        let area = fit(Rect::new(size.width/6, size.height/3, size.width*2/3, 5), size);
+                        let title = trf("Edit command for: {} (Ctrl+E: $EDITOR)", &[&aliases.get(*index).map(|a| a.name.clone()).unwrap_or_default()]);
+                        let mut text = one_line(command).into_owned();
+                        if !completions.is_empty() { text = format!("{}\n{}", text, completions.join("  ")); }
+                        let p = Paragraph::new(text).block(panel().title(title));
+                        f.render_widget(Clear, area);
+                        f.render_widget(p, area);
+                    }
+                    UiMode::Renaming { index, name, error } => {
+                        let area = fit(Rect::new(size.width/6, size.height/3, size.width*2/3, 5), size);
+                        let title = trf("Rename: {}", &[&aliases.get(*index).map(|a| a.name.clone()).unwrap_or_default()]);
+                        let mut text = vec![Spans::from(name.clone())];
+                        if let Some(e) = error { text.push(Spans::from(Span::styled(e.clone(), Style::default().fg(theme().error)))); }
+                        let p = Paragraph::new(text).block(panel().title(title));
+                        f.render_widget(Clear, area);
+                        f.render_widget(p, area);
+                    }
+                    UiMode::EditKeybind { index, keybind, error } => {
+                        let area = fit(Rect::new(size.width/6, size.height/3, size.width*2/3, 6), size);
+                        let title = trf("Edit keybind for: {}", &[&aliases.get(*index).map(|a| a.name.clone()).unwrap_or_default()]);
+                        let mut text = vec![
+                            Spans::from(keybind.map(|k| k.to_string()).unwrap_or_else(|| tr("(none)").to_string())),
+                            Spans::from(Span::styled(tr("a key or two for a chord, Backspace clears, Enter keeps"), Style::default().fg(theme().dim))),
+                        ];
+                        if let Some(e) = error { text.push(Spans::from(Span::styled(e.clone(), Style::default().fg(theme().error)))); }
+                        let p = Paragraph::new(text).block(panel().title(title));
+                        f.render_widget(Clear, area);
+                        f.render_widget(p, area);
+                    }
+                    UiMode::EditingSelect | UiMode::RenameSelect => {
+                        // use alias_state so selection is shared and list auto-scrolls when too long
+                        let area_height = (size.height / 3).max(3);
+                        let area = fit(Rect::new(size.width/6, size.height/3, size.width*2/3, area_height), size);
+                        let items: Vec<ListItem> = aliases.iter().map(|a| ListItem::new(format!("{} - {}", a.name, a.command))).collect();
+                        let mut sel_state = ListState::default();
+                        sel_state.select(alias_state.selected());
+                        let title = if matches!(ui_mode, UiMode::RenameSelect) { tr("Select alias to rename") } else { tr("Select alias to edit") };
+                        let list = List::new(items).block(panel().title(title))
+                            .highlight_style(Style::default().fg(theme().selected).add_modifier(Modifier::BOLD)).highlight_symbol(glyph("> ", ""));
+                        f.render_stateful_widget(list, area, &mut sel_state);
+                    }
+                    UiMode::RemovingSelect => {
+                        let area_height = (size.height / 3).max(3);
+                        let area = fit(Rect::new(size.width/6, size.height/3, size.width*2/3, area_height), size);
+                        let items: Vec<ListItem> = aliases.iter().map(|a| ListItem::new(format!("{} - {}", a.name, a.command))).collect();
+                        let mut sel_state = ListState::default();
+                        sel_state.select(alias_state.selected());
+                        let list = List::new(items).block(panel().title(tr("Select alias to remove")))
+                            .highlight_style(Style::default().fg(theme().selected).add_modifier(Modifier::BOLD)).highlight_symbol(glyph("> ", ""));
+                        f.render_stateful_widget(list, area, &mut sel_state);
+                    }
+                    UiMode::Trash { cursor } => {
+                        let area_height = (size.height / 3).max(3);
+                        let area = fit(Rect::new(size.width/6, size.height/3, size.width*2/3, area_height), size);
+                        let now = unix_now();
+                        let items: Vec<ListItem> = cfg.trash.iter().map(|t| {
+                            let days = now.saturating_sub(t.deleted) / (24 * 60 * 60);
+                            ListItem::new(Spans::from(vec![
+                                Span::raw(format!("{} - {}", t.name, t.entry.command)),
+                                Span::styled(format!("  {}", trf("{}d ago", &[&days])), Style::default().fg(theme().dim)),
+                            ]))
+                        }).collect();
+                        let mut sel_state = ListState::default();
+                        sel_state.select(Some(*cursor));
+                        let title = trf("Trash, kept {} days (Enter: restore, d: delete for good)", &[&cfg.trash_days]);
+                        let list = List::new(items).block(panel().title(title))
+                            .highlight_style(Style::default().fg(theme().selected).add_modifier(Modifier::BOLD)).highlight_symbol(glyph("> ", ""));
+                        f.render_widget(Clear, area);
+                        f.render_stateful_widget(list, area, &mut sel_state);
+                    }
+                    UiMode::Search { query, options, cursor } => {
+                        let area = fit(Rect::new(size.width/10, size.height/6, size.width*4/5, size.height*2/3), size);
+                        let bar = Rect { height: 3.min(area.height), ..area };
+                        let rest = Rect { y: area.y + bar.height, height: area.height - bar.height, ..area };
+                        let title = trf("Search: {} (Ctrl+R: regex, Ctrl+N: names/commands, Ctrl+S: case, Enter: run, Tab: select, Esc: close)", &[&options.describe()]);
+                        f.render_widget(Clear, area);
+                        f.render_widget(Paragraph::new(format!("/{}", query)).block(panel().title(brief(&title, size))), bar);
+                        let rows: Vec<ListItem> = match search_aliases(&aliases, &mut search_index, query, options) {
+                            Ok(found) if found.is_empty() => vec![ListItem::new(tr("(no matches)")).style(Style::default().fg(theme().dim))],
+                            Ok(found) => found.iter().map(|i| {
+                                let a = &aliases[*i];
+                                ListItem::new(Spans::from(vec![
+                                    Span::styled(a.name.clone(), Style::default().fg(theme().alias)),
+                                    Span::styled(format!(" - {}", a.command), Style::default().fg(theme().dim)),
+                                ]))
+                            }).collect(),
+                            Err(e) => vec![ListItem::new(e).style(Style::default().fg(theme().error))],
+                        };
+                        let mut sel_state = ListState::default();
+                        sel_state.select(Some(*cursor));
+                        let list = List::new(rows).block(panel())
+                            .highlight_style(Style::default().fg(theme().selected).add_modifier(Modifier::BOLD)).highlight_symbol(glyph("> ", ""));
+                        f.render_stateful_widget(list, rest, &mut sel_state);
+                    }
+                    UiMode::AliasHistory { index, cursor } => {
+                        let area = fit(Rect::new(size.width/10, size.height/6, size.width*4/5, size.height*2/3), size);
+                        let name = aliases.get(*index).map(|a| a.name.clone()).unwrap_or_default();
+                        let now = unix_now();
+                        let items: Vec<ListItem> = state.versions.get(&name).into_iter().flatten().rev().map(|v| {
+                            let mut spans = vec![Span::styled(format!("{:>8}  ", age_text(now.saturating_sub(v.changed))), Style::default().fg(theme().dim))];
+                            if v.name != name { spans.push(Span::styled(format!("{}: ", v.name), Style::default().fg(theme().alias))); }
+                            spans.push(Span::raw(v.entry.command.clone()));
+                            ListItem::new(Spans::from(spans))
+                        }).collect();
+                        let mut sel_state = ListState::default();
+                        sel_state.select(Some(*cursor));
+                        let title = trf("Earlier versions of {} (Enter: put it back, Esc: close)", &[&name]);
+                        let list = List::new(items).block(panel().title(brief(&title, size)))
+                            .highlight_style(Style::default().fg(theme().selected).add_modifier(Modifier::BOLD)).highlight_symbol(glyph("> ", ""));
+                        f.render_widget(Clear, area);
+                        f.render_stateful_widget(list, area, &mut sel_state);
+                    }
+                    UiMode::Importing { done, .. } => {
+                        let area = fit(Rect::new(size.width/4, size.height/4, size.width/2, IMPORT_SOURCES.len() as u16 + 2), size);
+                        let rows: Vec<ListItem> = IMPORT_SOURCES.iter().map(|source| match done.iter().find(|(s, _)| s == source) {
+                            Some((_, n)) => ListItem::new(format!("{} {:<8} {}", glyph("✓", "done"), source, trf("{} found", &[n]))).style(Style::default().fg(theme().ok)),
+                            None => ListItem::new(format!("{} {:<8} {}", glyph("…", "-"), source, tr("scanning"))).style(Style::default().fg(theme().dim)),
+                        }).collect();
+                        let title = trf("Import aliases {}/{} (Esc: cancel)", &[&done.len(), &IMPORT_SOURCES.len()]);
+                        f.render_widget(Clear, area);
+                        f.render_widget(List::new(rows).block(panel().title(brief(&title, size))), area);
+                    }
+                    UiMode::Tasks { title, items, picked, cursor } => {
+                        let area_height = (size.height / 2).max(3);
+                        let area = fit(Rect::new(size.width/8, size.height/4, size.width*3/4, area_height), size);
+                        let rows: Vec<ListItem> = items.iter().zip(picked.iter()).map(|(t, p)| {
+                            let mark = if *p { "[x]" } else { "[ ]" };
+                            let mut spans = vec![Span::raw(format!("{} {:<6} {}", mark, t.source, t.name))];
+                            if !t.desc.is_empty() {
+                                spans.push(Span::styled(format!("  {}", t.desc), Style::default().fg(theme().dim)));
+                            }
+                            ListItem::new(Spans::from(spans))
+                        }).collect();
+                        let mut sel_state = ListState::default();
+                        sel_state.select(Some(*cursor));
+                        let list = List::new(rows).block(panel().title(brief(tr(title), size)))
+                            .highlight_style(Style::default().fg(theme().selected).add_modifier(Modifier::BOLD)).highlight_symbol(glyph("> ", ""));
+                        f.render_widget(Clear, area);
+                        f.render_stateful_widget(list, area, &mut sel_state);
+                    }
+                    UiMode::Tmux { items, cursor } => {
+                        let area_height = (size.height / 2).max(3);
+                        let area = fit(Rect::new(size.width/6, size.height/4, size.width*2/3, area_height), size);
+                        let mut rows: Vec<ListItem> = items.iter().map(|e| {
+                            let style = if e.is_window { Style::default().fg(Color::Gray) } else { Style::default().fg(theme().alias) };
+                            ListItem::new(e.label.clone()).style(style)
+                        }).collect();
+                        rows.push(ListItem::new(tr("(new session)")).style(Style::default().fg(theme().dim)));
+                        let mut sel_state = ListState::default();
+                        sel_state.select(Some(*cursor));
+                        let list = List::new(rows).block(panel().title(brief(tr("tmux (Enter: attach, a: save as alias)"), size)))
+                            .highlight_style(Style::default().fg(theme().selected).add_modifier(Modifier::BOLD)).highlight_symbol(glyph("> ", ""));
+                        f.render_widget(Clear, area);
+                        f.render_stateful_widget(list, area, &mut sel_state);
+                    }
+                    UiMode::PickContainer { index, items, cursor } => {
+                        let area_height = (size.height / 3).max(3);
+                        let area = fit(Rect::new(size.width/6, size.height/3, size.width*2/3, area_height), size);
+                        let rows: Vec<ListItem> = items.iter().map(|i| ListItem::new(i.clone())).collect();
+                        let mut sel_state = ListState::default();
+                        sel_state.select(Some(*cursor));
+                        let title = trf("Run {} in container", &[&aliases.get(*index).map(|a| a.name.clone()).unwrap_or_default()]);
+                        let list = List::new(rows).block(panel().title(title))
+                            .highlight_style(Style::default().fg(theme().selected).add_modifier(Modifier::BOLD)).highlight_symbol(glyph("> ", ""));
+                        f.render_widget(Clear, area);
+                        f.render_stateful_widget(list, area, &mut sel_state);
+                    }
+                    UiMode::PickProfile { items, cursor } => {
+                        let area_height = (items.len() as u16 + 2).min(size.height / 2).max(3);
+                        let area = fit(Rect::new(size.width/4, size.height/4, size.width/2, area_height), size);
+                        let rows: Vec<ListItem> = items.iter().map(|i| {
+                            let active = cfg.profile.as_ref() == Some(i) || (cfg.profile.is_none() && i == "(none)");
+                            ListItem::new(format!("{}{}", i, if active { tr(" (active)") } else { "" }))
+                        }).collect();
+                        let mut sel_state = ListState::default();
+                        sel_state.select(Some(*cursor));
+                        let list = List::new(rows).block(panel().title(tr("Profile")))
+                            .highlight_style(Style::default().fg(theme().selected).add_modifier(Modifier::BOLD)).highlight_symbol(glyph("> ", ""));
+                        f.render_widget(Clear, area);
+                        f.render_stateful_widget(list, area, &mut sel_state);
+                    }
+                    UiMode::ImportHistory { items, picked, cursor } => {
+                        let area_height = (size.height / 2).max(3);
+                        let area = fit(Rect::new(size.width/8, size.height/4, size.width*3/4, area_height), size);
+                        let rows: Vec<ListItem> = items.iter().zip(picked.iter()).map(|((cmd, count), p)| {
+                            let mark = if *p { "[x]" } else { "[ ]" };
+                            ListItem::new(format!("{} {:>3}x  {}", mark, count, cmd))
+                        }).collect();
+                        let mut sel_state = ListState::default();
+                        sel_state.select(Some(*cursor));
+                        let list = List::new(rows).block(panel().title(brief(tr("Import from history (Space: pick, Enter: import)"), size)))
+                            .highlight_style(Style::default().fg(theme().selected).add_modifier(Modifier::BOLD)).highlight_symbol(glyph("> ", ""));
+                        f.render_widget(Clear, area);
+                        f.render_stateful_widget(list, area, &mut sel_state);
+                    }
+                    UiMode::EnvPanel { cursor } => {
+                        let area = fit(Rect::new(size.width/10, size.height/6, size.width*4/5, size.height*2/3), size);
+                        let rows: Vec<ListItem> = shell_env.effective().into_iter().map(|(k, v)| {
+                            if shell_env.overrides.contains_key(&k) {
+                                ListItem::new(format!("* {}={}", k, v)).style(Style::default().fg(theme().selected))
+                            } else {
+                                ListItem::new(format!("  {}={}", k, v))
+                            }
+                        }).collect();
+                        let unsaved = if shell_env.overrides != cfg.env { " [unsaved]" } else { "" };
+                        let title = trf("Environment{} (a: add, e: edit, d: drop override, p: save to config)", &[&unsaved]);
+                        let mut sel_state = ListState::default();
+                        sel_state.select(Some(*cursor));
+                        let list = List::new(rows).block(panel().title(title))
+                            .highlight_style(Style::default().add_modifier(Modifier::BOLD | Modifier::REVERSED));
+                        f.render_widget(Clear, area);
+                        f.render_stateful_widget(list, area, &mut sel_state);
+                    }
+                    UiMode::Kube { items, cursor } => {
+                        let area_height = (size.height / 2).max(3);
+                        let area = fit(Rect::new(size.width/6, size.height/4, size.width*2/3, area_height), size);
+                        let rows: Vec<ListItem> = items.iter().map(|e| {
+                            if e.current { ListItem::new(format!("{} *", e.label)).style(Style::default().fg(theme().ok)) } else { ListItem::new(e.label.clone()) }
+                        }).collect();
+                        let mut sel_state = ListState::default();
+                        sel_state.select(Some(*cursor));
+                        let list = List::new(rows).block(panel().title(brief(tr("Kubernetes (Enter: switch, a: save as alias)"), size)))
+                            .highlight_style(Style::default().fg(theme().selected).add_modifier(Modifier::BOLD)).highlight_symbol(glyph("> ", ""));
+                        f.render_widget(Clear, area);
+                        f.render_stateful_widget(list, area, &mut sel_state);
+                    }
+                    UiMode::EnvEdit { original, input } => {
+                        let area = fit(Rect::new(size.width/6, size.height/3, size.width*2/3, 3), size);
+                        let title = if original.is_some() { tr("Edit variable (NAME=value)") } else { tr("Add variable (NAME=value)") };
+                        let p = Paragraph::new(input.clone()).block(panel().title(title));
+                        f.render_widget(Clear, area);
+                        f.render_widget(p, area);
+                    }
+                    UiMode::Locked { input, wrong, .. } => {
+                        // nothing of what was on screen stays visible
+                        f.render_widget(Clear, size);
+                        let area = fit(Rect::new(size.width/4, size.height.saturating_sub(4)/2, size.width/2, 4), size);
+                        let mut text = vec![Spans::from(trf("Passphrase: {}", &[&glyph("•", "*").repeat(input.chars().count())]))];
+                        if *wrong { text.push(Spans::from(Span::styled(tr("Wrong passphrase"), Style::default().fg(theme().error)))); }
+                        let p = Paragraph::new(text).block(panel().title(tr("tuish is locked")));
+                        f.render_widget(p, area);
+                    }
+                    UiMode::Rerun { alias, exit } => {
+                        let area = fit(Rect::new(size.width/6, size.height/3, size.width*2/3, 4), size);
+                        let (status, color) = match exit {
+                            Some(0) => (tr("exited with 0").to_string(), theme().ok),
+                            Some(c) => (trf("exited with {}", &[&c]), theme().error),
+                            None => (tr("was killed").to_string(), theme().error),
+                        };
+                        let text = vec![
+                            Spans::from(vec![Span::raw(format!("{} ", alias.name)), Span::styled(status, Style::default().fg(color))]),
+                            Spans::from(tr("Enter or r: run it again, any other key: back to the menu")),
+                        ];
+                        let p = Paragraph::new(text).block(panel().title(tr("Finished")));
+                        f.render_widget(Clear, area);
+                        f.render_widget(p, area);
+                    }
+                    UiMode::ConfirmRun { alias } => {
+                        let area = fit(Rect::new(size.width/6, size.height/3, size.width*2/3, 6), size);
+                        let cmd = alias.command.clone();
+                        let text = vec![
+                            Spans::from(Span::styled(tr("This command looks dangerous:"), Style::default().fg(theme().error).add_modifier(Modifier::BOLD))),
+                            Spans::from(cmd),
+                            Spans::from(""),
+                            Spans::from(tr("Press y to run it, any other key to cancel")),
+                        ];
+                        let p = Paragraph::new(text).wrap(Wrap { trim: false })
+                            .block(panel().title(tr("Confirm")).border_style(Style::default().fg(theme().error)));
+                        f.render_widget(Clear, area);
+                        f.render_widget(p, area);
+                    }
+                    UiMode::Params { alias, names, values, choices, field, error } => {
+                        // every parameter on one screen, labels lined up
+                        let hint = error.is_some() || !choices[*field].is_empty();
+                        let picking = alias.params.get(&names[*field]).is_some_and(|p| p.is_choice()) && !choices[*field].is_empty();
+                        let listed = if picking { choices[*field].len().min(8) } else { 0 };
+                        let height = names.len() + listed + if hint { 3 } else { 2 };
+                        let area = fit(Rect::new(size.width/6, size.height/3, size.width*2/3, height as u16), size);
+                        let label_width = names.iter().map(|n| n.chars().count()).max().unwrap_or(0);
+                        let mut text: Vec<Spans> = names.iter().zip(values.iter()).enumerate().map(|(i, (name, value))| {
+                            let shown = if alias.params.get(name).is_some_and(|p| p.secret) { glyph("•", "*").repeat(value.chars().count()) } else { value.clone() };
+                            let style = if i == *field { Style::default().fg(theme().selected).add_modifier(Modifier::BOLD) } else { Style::default().fg(theme().dim) };
+                            // only typed values go red as you type, empty ones are flagged on Enter
+                            let bad = !value.is_empty() && param_error(alias.params.get(name), name, value).is_some();
+                            let optional = if alias.params.get(name).is_some_and(|p| p.optional) && value.is_empty() { tr("(optional)") } else { "" };
+                            let secret = alias.params.get(name).is_some_and(|p| p.secret || p.is_choice());
+                            let rest = if i == *field && !secret { param_completion(value, &choices[i]).unwrap_or("") } else { "" };
+                            Spans::from(vec![
+                                Span::styled(format!("{:>w$}: ", name, w = label_width), style),
+                                Span::styled(shown, if bad { Style::default().fg(theme().error) } else { Style::default() }),
+                                Span::styled(rest, Style::default().fg(theme().dim)),
+                                Span::styled(optional, Style::default().fg(theme().dim)),
+                            ])
+                        }).collect();
+                        if picking {
+                            // a window of the choices around the picked one
+                            let at = choices[*field].iter().position(|c| *c == values[*field]).unwrap_or(0);
+                            let top = at.saturating_sub(listed - 1).min(choices[*field].len() - listed);
+                            for (i, c) in choices[*field].iter().enumerate().skip(top).take(listed) {
+                                let style = if i == at { Style::default().fg(theme().selected).add_modifier(Modifier::REVERSED) } else { Style::default() };
+                                text.push(Spans::from(Span::styled(format!("{:w$}  {}", "", c, w = label_width), style)));
+                            }
+                        }
+                        if let Some(e) = error {
+                            text.push(Spans::from(Span::styled(e.clone(), Style::default().fg(theme().error))));
+                        } else if picking {
+                            text.push(Spans::from(Span::styled(trf("Up/Down to pick one of {}", &[&choices[*field].len()]), Style::default().fg(theme().dim))));
+                        } else if param_picks(alias.params.get(&names[*field]), &values[*field], &choices[*field]) {
+                            text.push(Spans::from(Span::styled(trf("Up/Down to pick from {} choices", &[&choices[*field].len()]), Style::default().fg(theme().dim))));
+                        }
+                        let title = trf("{} (Tab: next field, Enter: next/run, Esc: cancel)", &[&alias.name]);
+                        let p = Paragraph::new(text).block(panel().title(brief(&title, size)));
+                        f.render_widget(Clear, area);
+                        f.render_widget(p, area);
+                    }
+                    UiMode::Services { items, cursor, user } => {
+                        let area = fit(Rect::new(size.width/10, size.height/6, size.width*4/5, size.height*2/3), size);
+                        let rows: Vec<ListItem> = if items.is_empty() {
+                            vec![ListItem::new(tr("(no services)")).style(Style::default().fg(theme().dim))]
+                        } else {
+                            items.iter().map(|e| {
+                                let color = match e.active.as_str() { "active" => theme().ok, "failed" => theme().error, _ => Color::Gray };
+                                ListItem::new(format!("{:<40} {:<10} {}", e.unit, e.sub, e.description)).style(Style::default().fg(color))
+                            }).collect()
+                        };
+                        let scope = tr(if *user { "user" } else { "system" });
+                        let title = trf("{} services (Enter: status, s: start, t: stop, r: restart, u: user/system, a: save as alias)", &[&scope]);
+                        let mut sel_state = ListState::default();
+                        sel_state.select(Some(*cursor));
+                        let list = List::new(rows).block(panel().title(title))
+                            .highlight_style(Style::default().add_modifier(Modifier::BOLD | Modifier::REVERSED));
+                        f.render_widget(Clear, area);
+                        f.render_stateful_widget(list, area, &mut sel_state);
+                    }
+                    UiMode::Containers { items, cursor } => {
+                        let area = fit(Rect::new(size.width/10, size.height/6, size.width*4/5, size.height*2/3), size);
+                        let rows: Vec<ListItem> = if items.is_empty() {
+                            vec![ListItem::new(tr("(no running containers)")).style(Style::default().fg(theme().dim))]
+                        } else {
+                            items.iter().map(|c| ListItem::new(format!("{:<24} {:<30} {}", c.name, c.image, c.status))).collect()
+                        };
+                        let mut sel_state = ListState::default();
+                        sel_state.select(Some(*cursor));
+                        let list = List::new(rows).block(panel().title(brief(tr("Containers (l: logs, Enter: shell, t: stop, r: restart, a: save as alias)"), size)))
+                            .highlight_style(Style::default().add_modifier(Modifier::BOLD | Modifier::REVERSED));
+                        f.render_widget(Clear, area);
+                        f.render_stateful_widget(list, area, &mut sel_state);
+                    }
+                    UiMode::BuildPick { all, filter, cursor } => {
+                        let area = fit(Rect::new(size.width/6, size.height/6, size.width*2/3, size.height*2/3), size);
+                        let shown: Vec<&String> = all.iter().filter(|p| p.contains(filter.as_str())).collect();
+                        let rows: Vec<ListItem> = shown.iter().map(|p| ListItem::new(p.to_string())).collect();
+                        let mut sel_state = ListState::default();
+                        sel_state.select(if shown.is_empty() { None } else { Some(*cursor) });
+                        let title = trf("Build a command: {}_ (type to filter, Enter: pick)", &[filter]);
+                        let list = List::new(rows).block(panel().title(brief(&title, size).to_string()))
+                            .highlight_style(Style::default().fg(theme().selected).add_modifier(Modifier::BOLD)).highlight_symbol(glyph("> ", ""));
+                        f.render_widget(Clear, area);
+                        f.render_stateful_widget(list, area, &mut sel_state);
+                    }
+                    UiMode::LoadingChoices { alias, .. } => {
+                        let area = fit(Rect::new(size.width/6, size.height/3, size.width*2/3, 3), size);
+                        let title = trf("{} (Esc: cancel)", &[&alias.name]);
+                        let p = Paragraph::new(tr("Running its choices command ...")).style(Style::default().fg(theme().dim))
+                            .block(panel().title(brief(&title, size)));
+                        f.render_widget(Clear, area);
+                        f.render_widget(p, area);
+                    }
+                    UiMode::ReadingHelp { program, .. } => {
+                        let area = fit(Rect::new(size.width/6, size.height/3, size.width*2/3, 3), size);
+                        let p = Paragraph::new(trf("Reading `{} --help` ...", &[program])).style(Style::default().fg(theme().dim))
+                            .block(panel().title(brief(tr("Build a command (Esc: cancel)"), size)));
+                        f.render_widget(Clear, area);
+                        f.render_widget(p, area);
+                    }
+                    UiMode::BuildFlags { program, options, values, args, cursor, editing } => {
+                        let area = fit(Rect::new(size.width/10, size.height/6, size.width*4/5, size.height*2/3), size);
+                        let parts = Layout::default()
+                            .direction(Direction::Vertical)
+                            .constraints([Constraint::Min(3), Constraint::Length(3)].as_ref())
+                            .split(area);
+                        let mut rows: Vec<ListItem> = options.iter().zip(values.iter()).enumerate().map(|(i, (o, v))| {
+                            let mark = if v.is_some() { "[x]" } else { "[ ]" };
+                            let mut flag = o.flag.clone();
+                            if let Some(name) = &o.value {
+                                let typed = v.clone().filter(|v| !v.is_empty());
+                                let cursor_mark = if *editing && i == *cursor { "_" } else { "" };
+                                flag = format!("{} {}{}", flag, typed.unwrap_or_else(|| name.clone()), cursor_mark);
+                            }
+                            ListItem::new(Spans::from(vec![
+                                Span::styled(format!("{} {:<28} ", mark, flag), Style::default().fg(if v.is_some() { theme().ok } else { theme().alias })),
+                                Span::styled(o.desc.clone(), Style::default().fg(theme().dim)),
+                            ]))
+                        }).collect();
+                        let args_mark = if *editing && *cursor == options.len() { "_" } else { "" };
+                        rows.push(ListItem::new(trf("arguments: {}{}", &[args, &args_mark])));
+                        let mut sel_state = ListState::default();
+                        sel_state.select(Some(*cursor));
+                        let title = trf("{} (Space: toggle, e: type a value, Enter: done, Esc: back)", &[program]);
+                        let list = List::new(rows).block(panel().title(brief(&title, size).to_string()))
+                            .highlight_style(Style::default().add_modifier(Modifier::BOLD | Modifier::REVERSED));
+                        f.render_widget(Clear, area);
+                        f.render_stateful_widget(list, parts[0], &mut sel_state);
+                        let preview = Paragraph::new(built_command(program, options, values, args)).block(panel().title(tr("Command")));
+                        f.render_widget(preview, parts[1]);
+                    }
+                    UiMode::PickDir { alias, dir, entries, cursor, .. } => {
+                        let area = fit(Rect::new(size.width/6, size.height/6, size.width*2/3, size.height*2/3), size);
+                        let mut rows = vec![ListItem::new(trf("./ (use {})", &[&dir.display()])).style(Style::default().fg(theme().ok))];
+                        rows.extend(entries.iter().map(|e| ListItem::new(format!("{}/", e)).style(Style::default().fg(theme().alias))));
+                        let mut sel_state = ListState::default();
+                        sel_state.select(Some(*cursor));
+                        let title = match alias.and_then(|i| aliases.get(i)) {
+                            Some(a) => trf("Working directory for {} (Enter: open/pick, Backspace: up, .: hidden, x: clear)", &[&a.name]),
+                            None => tr("Change directory (Enter: open/pick, Backspace: up, .: hidden, ~: home)").to_string(),
+                        };
+                        let list = List::new(rows).block(panel().title(brief(&title, size).to_string()))
+                            .highlight_style(Style::default().fg(theme().selected).add_modifier(Modifier::BOLD)).highlight_symbol(glyph("> ", ""));
+                        f.render_widget(Clear, area);
+                        f.render_stateful_widget(list, area, &mut sel_state);
+                    }
+                    UiMode::Processes { items, cursor, .. } => {
+                        let area = fit(Rect::new(size.width/10, size.height/6, size.width*4/5, size.height*2/3), size);
+                        let rows: Vec<ListItem> = if items.is_empty() {
+                            vec![ListItem::new(tr("(nothing running)")).style(Style::default().fg(theme().dim))]
+                        } else {
+                            items.iter().map(|p| {
+                                let head = format!("{:>7} {:<16} {} {:>5.1}% {:>7} ", p.pid, p.alias, p.state, p.cpu, format!("{}M", p.rss_kb / 1024));
+                                ListItem::new(Spans::from(vec![
+                                    Span::raw(head),
+                                    Span::styled(format!("{}{}", "  ".repeat(p.depth), p.command), Style::default().fg(if p.depth == 0 { theme().alias } else { theme().text })),
+                                ]))
+                            }).collect()
+                        };
+                        let mut sel_state = ListState::default();
+                        sel_state.select(Some(*cursor));
+                        let list = List::new(rows).block(panel().title(brief(tr("Processes (Enter: output, t: TERM, i: INT, h: HUP, k: KILL)"), size)))
+                            .highlight_style(Style::default().add_modifier(Modifier::BOLD | Modifier::REVERSED));
+                        f.render_widget(Clear, area);
+                        f.render_stateful_widget(list, area, &mut sel_state);
+                    }
+                    UiMode::SshHosts { items, cursor } => {
+                        let area_height = (size.height / 2).max(3);
+                        let area = fit(Rect::new(size.width/6, size.height/4, size.width*2/3, area_height), size);
+                        let rows: Vec<ListItem> = items.iter().map(|h| ListItem::new(h.clone())).collect();
+                        let mut sel_state = ListState::default();
+                        sel_state.select(Some(*cursor));
+                        let list = List::new(rows).block(panel().title(brief(tr("SSH hosts (Enter: connect, a: save as alias)"), size)))
+                            .highlight_style(Style::default().fg(theme().selected).add_modifier(Modifier::BOLD)).highlight_symbol(glyph("> ", ""));
+                        f.render_widget(Clear, area);
+                        f.render_stateful_widget(list, area, &mut sel_state);
+                    }
+                    UiMode::Links { items, cursor, .. } => {
+                        let area_height = (items.len() as u16 + 2).min(size.height / 2).max(3);
+                        let area = fit(Rect::new(size.width/6, size.height/4, size.width*2/3, area_height), size);
+                        let rows: Vec<ListItem> = items.iter().map(|l| ListItem::new(l.clone())).collect();
+                        let mut sel_state = ListState::default();
+                        sel_state.select(Some(*cursor));
+                        let list = List::new(rows).block(panel().title(brief(tr("Open link (Enter: open, c: copy)"), size)))
+                            .highlight_style(Style::default().fg(theme().selected).add_modifier(Modifier::BOLD)).highlight_symbol(glyph("> ", ""));
+                        f.render_widget(Clear, area);
+                        f.render_stateful_widget(list, area, &mut sel_state);
+                    }
+                    UiMode::ConfigChanged { lines, scroll, .. } => {
+                        let area = fit(Rect::new(size.width/10, size.height/6, size.width*4/5, size.height*2/3), size);
+                        let rows: Vec<Spans> = lines.iter().map(|l| {
+                            let color = match l.chars().next() { Some('+') => theme().ok, Some('-') => theme().error, _ => theme().title };
+                            Spans::from(Span::styled(l.clone(), Style::default().fg(color)))
+                        }).collect();
+                        let p = Paragraph::new(rows).wrap(Wrap { trim: false }).scroll((*scroll, 0))
+                            .block(panel().title(brief(tr("The config changed on disk (Enter: use it, Esc: keep mine and write it back)"), size)));
+                        f.render_widget(Clear, area);
+                        f.render_widget(p, area);
+                    }
+                    UiMode::TextView { title, lines, scroll } => {
+                        let area = fit(Rect::new(size.width/10, size.height/6, size.width*4/5, size.height*2/3), size);
+                        let p = Paragraph::new(lines.join("\n")).wrap(Wrap { trim: false }).scroll((*scroll, 0))
+                            .block(panel().title(trf("{} (Esc to close)", &[&title])));
+                        f.render_widget(Clear, area);
+                        f.render_widget(p, area);
+                    }
+                    UiMode::Message(msg) => {
+                        let w = (size.width / 3).max(20);
+                        // taller for the long ones (like git's errors) so they wrap instead of getting cut off
+                        let rows = msg.chars().count().div_ceil(w.saturating_sub(2).max(1) as usize) as u16;
+                        let h = (rows + 2).clamp(3, (size.height / 2).max(3));
+                        let area = fit(Rect::new((size.width.saturating_sub(w))/2, (size.height.saturating_sub(h))/2, w, h), size);
+                        let p = Paragraph::new(msg.clone()).style(Style::default().fg(theme().error).add_modifier(Modifier::BOLD)).wrap(Wrap { trim: true }).block(panel().title(tr("Info")));
+                        f.render_widget(Clear, area);
+                        f.render_widget(p, area);
+                    }
+                }
+                if let Some(t) = toast.as_ref().filter(|_| toast_shown) {
+                    let w = size.width.min(50);
+                    let area = fit(Rect::new(size.width.saturating_sub(w), size.height.saturating_sub(5), w, 4), size);
+                    let lines = vec![
+                        Spans::from(Span::styled(t.summary.clone(), Style::default().fg(if t.ok { theme().ok } else { theme().error }).add_modifier(Modifier::BOLD))),
+                        Spans::from(Span::styled(t.last.clone(), Style::default().fg(theme().dim))),
+                    ];
                     f.render_widget(Clear, area);
-                    f.render_widget(p, area);
+                    f.render_widget(Paragraph::new(lines).block(panel().title(brief(&trf("{} finished (ctrl+o: output)", &[&t.name]), size))), area);
+                }
+            }).unwrap();
+            if let Some(b) = &mut bench {
+                b.draws.push(started.elapsed());
+                if let Some(at) = b.input_at.take() { b.inputs.push(at.elapsed()); }
+            }
+            // same text in the same place, wrapped in OSC 8 so the terminal makes it clickable
+            for (x, y, text, target) in &links_on_screen {
+                execute!(stdout(), MoveTo(*x, *y), Print(format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", target, text))).ok();
+            }
+        }
+        if let Some(d) = deferred.take() {
+            state = d.state.join().unwrap_or_default();
+            header.branch = d.branch.join().unwrap_or_default();
+            offset = d.offset.join().unwrap_or_default();
+            // repo aliases go first so their keybinds win over the user's own
+            if let Some((file, repo)) = d.repo.join().unwrap_or_default() {
+                cfg.repo_file = Some(file);
+                let n = repo.len();
+                aliases.splice(0..0, repo);
+                check_aliases(&mut aliases[..n], &path_var);
+                loaded_order.splice(0..0, aliases[..n].iter().map(|a| a.name.clone()));
+                if alias_state.selected().is_none() && !aliases.is_empty() { alias_state.select(Some(0)); }
+            }
+            dirty = true;
+        }
+        if let Some(name) = startup_alias.take() {
+            dirty = true;
+            ui_mode = match aliases.iter().find(|a| a.name == name) {
+                None => UiMode::Message(trf("startup-alias: no alias named '{}'", &[&name])),
+                Some(a) if a.kind != AliasKind::Command || !placeholders(&a.command).is_empty() => {
+                    UiMode::Message(trf("startup-alias: '{}' needs to be a command without parameters", &[&name]))
+                }
+                // nobody asked for it just now, so a dangerous one waits for a yes like any other run
+                Some(a) if is_dangerous(&a.command, &cfg.danger_patterns) => UiMode::ConfirmRun { alias: Alias { run_in: RunIn::Pane, ..a.clone() } },
+                Some(a) => {
+                    let a = Alias { run_in: RunIn::Pane, ..a.clone() };
+                    UiMode::Output { job: start_job(&mut jobs, &a, alias_invocation(&a, &shell_env), OutputLimits::from_config(&cfg)) }
+                }
+            };
+        }
+
+        for (i, job) in jobs.iter_mut().enumerate() {
+            let seen = job.received;
+            let finished = job.poll();
+            // what's new, or as much of it as is still kept
+            let new = (job.received - seen).min(job.lines.len());
+            for line in job.lines.range(job.lines.len() - new..) { record(&format!("{}\n", line)); }
+            dirty |= finished || job.received != seen;
+            if finished {
+                state.add_run(RunRecord { name: job.name.clone(), command: job.command.clone(), exit: job.exit, secs: job.started.elapsed().as_secs_f64(), at: unix_now() });
+                write_state(&state_file, &state);
+                // the post-run action only takes over while we're looking at the job (or the menu)
+                let watching = matches!(ui_mode, UiMode::Output { job: shown } if shown == i);
+                // the split view follows the selected alias, so that one's been seen too
+                let in_split = split_view && matches!(ui_mode, UiMode::Main) && alias_state.selected().and_then(|s| aliases.get(s)).is_some_and(|a| a.name == job.name);
+                match job.after {
+                    // not in the middle of something else though, like typing in a popup
+                    After::Quit if watching || matches!(ui_mode, UiMode::Main) => { leave_tui(&mut terminal); return; }
+                    After::Menu if watching => { ui_mode = UiMode::Main; }
+                    After::Rerun if watching || matches!(ui_mode, UiMode::Main) => {
+                        let alias = Alias { run_in: RunIn::Pane, ..rerun_alias(&state.history[state.history.len() - 1], &aliases) };
+                        ui_mode = UiMode::Rerun { alias, exit: job.exit };
+                    }
+                    _ if !watching && !in_split => {
+                        toast = Some(Toast::new(job));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        // scheduled aliases that are due start in the output pane, unless the last run is still going.
+        // Dangerous ones are never scheduled since nobody would be there to confirm them
+        let now = Instant::now();
+        schedule.retain(|name, _| aliases.iter().any(|a| a.name == *name && a.every.is_some()));
+        for a in &aliases {
+            // an `every` too far out for the clock just never comes round
+            let Some(due) = schedule_interval(a, &cfg.danger_patterns).and_then(|every| now.checked_add(every)) else { continue };
+            let next = schedule.entry(a.name.clone()).or_insert(due);
+            if *next > now { continue; }
+            *next = due;
+            if latest_job(&jobs, &a.name).is_some_and(|i| jobs[i].running()) { continue; }
+            let viewed = shown_job(&mut ui_mode).map(|i| (jobs[*i].name.clone(), jobs[*i].started));
+            let a = Alias { run_in: RunIn::Pane, ..a.clone() };
+            start_job(&mut jobs, &a, alias_invocation(&a, &shell_env), OutputLimits::from_config(&cfg));
+            dirty = true;
+            // the job on screen may have moved (or been dropped) to make room
+            if let Some((name, started)) = viewed {
+                match jobs.iter().position(|j| j.name == name && j.started == started) {
+                    Some(i) => { if let Some(job) = shown_job(&mut ui_mode) { *job = i; } }
+                    // behind the lock screen it's what comes back that changes, the lock stays
+                    None => match &mut ui_mode {
+                        UiMode::Locked { previous, .. } => { **previous = UiMode::Main; }
+                        _ => { ui_mode = UiMode::Main; }
+                    },
                 }
             }
-        }).unwrap();
+        }
+
+        // the config changed under us: whatever touches the aliases is shown before it's taken.
+        // Only from the menu, a popup in progress is never replaced
+        if matches!(ui_mode, UiMode::Main) && let Some(e) = SAVE_ERROR.lock().unwrap().take() {
+            ui_mode = UiMode::Message(e);
+            dirty = true;
+        }
+        // mid-save the files are ahead of CONFIG_WRITTEN, that's not someone else's change
+        if matches!(ui_mode, UiMode::Main) && cfg_checked.elapsed() >= CONFIG_CHECK && !saving() {
+            cfg_checked = Instant::now();
+            let stamp = config_stamp(&cfg_path);
+            // our own saves are already what we show
+            let changed = stamp != cfg_stamp && stamp != *CONFIG_WRITTEN.lock().unwrap();
+            cfg_stamp = stamp;
+            if changed {
+                dirty = true;
+                let parsed = fs::read_to_string(&cfg_path).map_err(|e| e.to_string())
+                    .and_then(|d| serde_json::from_str::<ConfigFile>(&d).map_err(|e| e.to_string()));
+                match parsed.and_then(|_| reload_config(&cfg_path, &cfg)) {
+                    // most likely mid-save in an editor, the fixed version shows up as another change
+                    Err(e) => { ui_mode = UiMode::Message(trf("The config changed but can't be read: {}", &[&e])); }
+                    Ok(new_cfg) => {
+                        let mine: HashMap<String, AliasEntry> = aliases.iter().filter(|a| !a.repo && a.bundle.is_none()).map(|a| (a.name.clone(), alias_entry(a))).collect();
+                        let lines = alias_changes(&mine, &new_cfg.aliases);
+                        if lines.is_empty() {
+                            shell_env.reload(&cfg, &new_cfg);
+                            let old_dir = profile_cwd(&cfg);
+                            cfg = new_cfg;
+                            path_var = shell_env.path_var();
+                            if profile_cwd(&cfg) != old_dir {
+                                match enter_profile_dir(old_dir, start_dir.as_deref(), &mut aliases, &mut cfg, &path_var) {
+                                    Ok(moved) => { if moved && cfg.header.iter().any(|w| w == "branch") { header.branch = git_branch(); } }
+                                    Err(e) => { ui_mode = UiMode::Message(e); }
+                                }
+                            }
+                        } else {
+                            ui_mode = UiMode::ConfigChanged { cfg: Box::new(new_cfg), lines, scroll: 0 };
+                        }
+                    }
+                }
+            }
+        }
+
+        // keep redrawing while output is coming in, otherwise just wait for input (or the lock)
+        let locked = matches!(ui_mode, UiMode::Locked { .. });
+        let idle_left = lock_after.filter(|_| !locked).map(|l| l.saturating_sub(last_input.elapsed()));
+        let wait = match (jobs.iter().any(|j| j.running()), idle_left) {
+            (true, Some(left)) => Some(left.min(Duration::from_millis(100))),
+            (true, None) => Some(Duration::from_millis(100)),
+            (false, left) => left,
+        };
+        // wake up for the header widgets too: every second for a countdown, every minute for the clock
+        let ticks = [
+            (!schedule.is_empty() || matches!(ui_mode, UiMode::Processes { .. })).then_some(Duration::from_secs(1)),
+            clock.then(|| Duration::from_secs(60 - unix_now() % 60)),
+            header_rx.is_some().then_some(HEADER_TICK),
+            toast.is_some().then_some(Duration::from_secs(1)),
+            saving().then_some(Duration::from_millis(100)),
+            (checking.is_some() || matches!(ui_mode, UiMode::Importing { .. } | UiMode::ReadingHelp { .. } | UiMode::LoadingChoices { .. })).then_some(Duration::from_millis(100)),
+            Some(CONFIG_CHECK),
+        ];
+        let wait = wait.into_iter().chain(ticks.into_iter().flatten()).min();
+        let ready = match (&bench, &replay, wait) {
+            (Some(_), _, _) | (None, None, None) => true,
+            (None, Some(r), wait) => r.poll(wait),
+            (None, None, Some(wait)) => event::poll(wait).unwrap_or(false),
+        };
+        if !ready {
+            if let Some(l) = lock_after && !locked && last_input.elapsed() >= l {
+                let previous = std::mem::replace(&mut ui_mode, UiMode::Main);
+                ui_mode = UiMode::Locked { previous: Box::new(previous), input: String::new(), wrong: false };
+                pending_leader = None;
+                open_group = None;
+                dirty = true;
+            }
+            // the countdown to the next scheduled run goes by the second, the clock by the minute
+            let now = unix_now();
+            dirty |= (!schedule.is_empty() && now != drawn_second) || (clock && now / 60 != drawn_second / 60);
+            continue;
+        }
+        last_input = Instant::now();
 
         // Handle input
-        let ev = event::read().unwrap();
+        let ev = match (&mut bench, &mut replay) {
+            (None, None) => event::read().unwrap(),
+            (None, Some(r)) => match r.next() {
+                Some(ev) => ev,
+                None => {
+                    leave_tui(&mut terminal);
+                    println!("tuish: replayed {} events", r.played);
+                    return;
+                }
+            },
+            (Some(b), _) => match b.script.pop_front() {
+                Some(ev) => { b.input_at = Some(Instant::now()); ev }
+                None => {
+                    leave_tui(&mut terminal);
+                    print!("{}", b.report(aliases.len()));
+                    return;
+                }
+            },
+        };
+        if let Some(log) = &mut event_log { log.write(&ev, typing_secret(&ui_mode)); }
+        // anything could change the screen, except focus changes and keys that do nothing, which put
+        // `dirty` back to what it was
+        let dirty_before = dirty;
+        dirty |= !matches!(ev, Event::FocusGained | Event::FocusLost);
+        // alias picked by this event, run once input handling is done
+        let mut run_request: Option<Alias> = None;
+        // set once the user has said yes to a dangerous command
+        let mut run_confirmed = false;
+        // placeholder values already filled in for this run
+        let mut run_values: Option<HashMap<String, String>> = None;
+        // built-in action to perform, from the Actions list or an `action-keys` binding
+        let mut action_request: Option<&str> = None;
         match ev {
             Event::Key(key) => {
                 // handle focus switching
                 match key.code {
-                    KeyCode::Tab => {
+                    KeyCode::Tab if matches!(ui_mode, UiMode::Main) => {
                         focus = match focus {
                             Focus::Actions => Focus::Aliases,
                             Focus::Aliases => Focus::Actions,
@@ -257,195 +6123,1265 @@ fn main() {
                         } else {
                             opt_state.select(Some(selected_opt));
                         }
-                        continue;
+                        continue;
+                    }
+                    // jump to the output of the job in the toast, unless an alias has ctrl+o
+                    KeyCode::Char('o') if key.modifiers.contains(KeyModifiers::CONTROL) && toast.as_ref().is_some_and(|t| t.shown.is_some())
+                        && matches!(ui_mode, UiMode::Main | UiMode::Output { .. }) && keybind_target(&aliases, None, |k| k.matches(&key)).is_none() => {
+                        if let Some(t) = toast.take() && let Some(i) = jobs.iter().position(|j| j.name == t.name && j.started == t.started) {
+                            ui_mode = UiMode::Output { job: i };
+                        }
+                        continue;
+                    }
+                    _ => {}
+                }
+
+                // candidates from the last Tab only stay up until the next key
+                if key.code != KeyCode::Tab { completions.clear(); }
+                if !matches!(key.code, KeyCode::Tab | KeyCode::Enter) { not_found = None; }
+
+                match &mut ui_mode {
+                    UiMode::Main if cfg.action_keys.values().any(|k| KeyPress::parse(k).is_some_and(|k| k.matches(&key))) => {
+                        action_request = options.iter().copied().find(|o| cfg.action_keys.get(*o).and_then(|k| KeyPress::parse(k)).is_some_and(|k| k.matches(&key)));
+                    }
+                    UiMode::Main if key.code == KeyCode::Esc && popup() && pending_leader.is_none() && open_group.is_none() => {
+                        leave_tui(&mut terminal);
+                        return;
+                    }
+                    UiMode::Main if pending_leader.is_some() => {
+                        // second key of a chord, anything that doesn't continue it just cancels
+                        let leader = pending_leader.take();
+                        run_request = keybind_target(&aliases, open_group.as_deref(), |k| k.leader == leader && k.key.matches(&key)).cloned();
+                        open_group = None;
+                    }
+                    UiMode::Main if open_group.is_some() => {
+                        // inside a group any key is one of its keybinds (plain keys included), or closes it
+                        let group = open_group.as_deref();
+                        if aliases.iter().any(|a| a.group.as_deref() == group && a.keybind.is_some_and(|k| k.starts_with(&key))) {
+                            pending_leader = Some(KeyPress::from_event(&key));
+                        } else {
+                            run_request = keybind_target(&aliases, group, |k| k.matches(&key)).cloned();
+                            open_group = None;
+                        }
+                    }
+                    UiMode::Main if cfg.groups.values().any(|k| KeyPress::parse(k).is_some_and(|k| k.matches(&key) && (k.is_global() || matches!(focus, Focus::Actions)))) => {
+                        open_group = cfg.groups.iter().find(|(_, k)| KeyPress::parse(k).is_some_and(|k| k.matches(&key))).map(|(g, _)| g.clone());
+                    }
+                    UiMode::Main if aliases.iter().any(|a| a.group.is_none() && a.keybind.is_some_and(|k| k.starts_with(&key) && (k.is_global() || matches!(focus, Focus::Actions)))) => {
+                        pending_leader = Some(KeyPress::from_event(&key));
+                    }
+                    UiMode::Main if aliases.iter().any(|a| a.group.is_none() && a.keybind.is_some_and(|k| k.is_global() && k.matches(&key))) => {
+                        run_request = keybind_target(&aliases, None, |k| k.matches(&key)).cloned();
+                    }
+                    // 1-9 run the aliases numbered on screen, unless the digit is someone's keybind
+                    UiMode::Main if key.modifiers.is_empty() && matches!(key.code, KeyCode::Char('1'..='9'))
+                        && !aliases.iter().any(|a| a.group.is_none() && a.keybind.is_some_and(|k| k.matches(&key) || k.starts_with(&key))) => {
+                        if let KeyCode::Char(c) = key.code {
+                            run_request = aliases.get(alias_offset + (c as usize - '1' as usize)).cloned();
+                        }
+                    }
+                    UiMode::Main if key.modifiers.contains(KeyModifiers::ALT) && matches!(key.code, KeyCode::Char('1'..='9')) => {
+                        if let KeyCode::Char(c) = key.code {
+                            let n = c as usize - '1' as usize;
+                            if let Some(r) = recent_runs(&state.history).get(n) {
+                                run_request = Some(rerun_alias(r, &aliases));
+                            }
+                        }
+                    }
+                    UiMode::Main if key.code == KeyCode::Char('s') && key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        // save the last thing we ran as an alias, unless it already is one
+                        match state.history.last() {
+                            None => { ui_mode = UiMode::Message(tr("Nothing has been run yet").to_string()); }
+                            Some(r) if aliases.iter().any(|a| a.command == r.command) => {
+                                ui_mode = UiMode::Message(trf("'{}' is already an alias", &[&r.command]));
+                            }
+                            Some(r) => {
+                                let name = if r.name == ADHOC_NAME { String::new() } else { r.name.clone() };
+                                ui_mode = UiMode::Adding { step: 1, kind: AliasKind::Command, name, command: r.command.clone(), keybind: None, ask_command: false, error: None };
+                            }
+                        }
+                    }
+                    UiMode::Main if key.code == KeyCode::Char(':') => {
+                        ui_mode = UiMode::CommandBar { input: String::new() };
+                    }
+                    UiMode::Main if key.code == KeyCode::Char('/') => {
+                        ui_mode = UiMode::Search { query: String::new(), options: SearchOptions::default(), cursor: 0 };
+                    }
+                    UiMode::Main if key.code == KeyCode::Char('P') && !cfg.profiles.is_empty() => {
+                        action_request = Some("Switch profile");
+                    }
+                    UiMode::Main => {
+                        match focus {
+                            Focus::Actions => {
+                                match key.code {
+                                    KeyCode::Up => {
+                                        if selected_opt == 0 { selected_opt = options.len()-1 } else { selected_opt -= 1 }
+                                        opt_state.select(Some(selected_opt));
+                                    }
+                                    KeyCode::Down => { selected_opt = (selected_opt+1) % options.len(); opt_state.select(Some(selected_opt)); }
+                                    KeyCode::Enter => { action_request = Some(options[selected_opt]); }
+                                    KeyCode::Char(_) => {
+                                        // trigger alias by keybind
+                                        match keybind_target(&aliases, None, |k| k.matches(&key)) {
+                                            Some(a) => { run_request = Some(a.clone()); }
+                                            None => { dirty = dirty_before; }
+                                        }
+                                    }
+                                    _ => { dirty = dirty_before; }
+                                }
+                            }
+                            Focus::Aliases => {
+                                match key.code {
+                                    KeyCode::Up => { if !aliases.is_empty() {
+                                            let i = alias_state.selected().unwrap_or(0);
+                                            let new = if i == 0 { aliases.len()-1 } else { i-1 };
+                                            alias_state.select(Some(new));
+                                        }
+                                    }
+                                    KeyCode::Down => { if !aliases.is_empty() {
+                                            let i = alias_state.selected().unwrap_or(0);
+                                            let new = (i+1) % aliases.len();
+                                            alias_state.select(Some(new));
+                                        }
+                                    }
+                                    KeyCode::Enter => {
+                                        if let Some(a) = alias_state.selected().and_then(|i| aliases.get(i)) {
+                                            run_request = Some(a.clone());
+                                        }
+                                    }
+                                    KeyCode::Char('r') => { show_recent = !show_recent; }
+                                    KeyCode::Char('S') => {
+                                        // the selected alias stays selected wherever it ends up
+                                        let selected = alias_state.selected().and_then(|i| aliases.get(i)).map(|a| a.name.clone());
+                                        // an explicit sort takes over from MRU
+                                        if mru { mru = false; } else { sort = sort.next(); }
+                                        sort_aliases(&mut aliases, sort, &state.usage, &loaded_order);
+                                        if let Some(name) = selected { alias_state.select(aliases.iter().position(|a| a.name == name)); }
+                                    }
+                                    KeyCode::Char('M') => {
+                                        let selected = alias_state.selected().and_then(|i| aliases.get(i)).map(|a| a.name.clone());
+                                        mru = !mru;
+                                        // starts out from the last runs in state.json, then moves with every run
+                                        sort_aliases(&mut aliases, if mru { AliasSort::LastRun } else { sort }, &state.usage, &loaded_order);
+                                        if let Some(name) = selected { alias_state.select(aliases.iter().position(|a| a.name == name)); }
+                                    }
+                                    KeyCode::Char('|') => { split_view = !split_view; }
+                                    KeyCode::Char('o') => {
+                                        if let Some(a) = alias_state.selected().and_then(|i| aliases.get(i)) {
+                                            run_request = Some(Alias { run_in: RunIn::Pane, ..a.clone() });
+                                        }
+                                    }
+                                    KeyCode::Char('e') => {
+                                        if let Some(i) = alias_state.selected().filter(|i| *i < aliases.len()) {
+                                            ui_mode = match read_only_reason(&aliases[i]) {
+                                                Some(reason) => UiMode::Message(reason),
+                                                None => UiMode::InlineEdit { index: i, command: aliases[i].command.clone() },
+                                            };
+                                        }
+                                    }
+                                    KeyCode::Char('y') => {
+                                        if let Some(a) = alias_state.selected().and_then(|i| aliases.get(i)) {
+                                            let command = resolved_command(a);
+                                            ui_mode = match copy_to_clipboard(&command, &cfg.clipboard) {
+                                                Ok(()) => UiMode::Message(trf("Copied: {}", &[&command])),
+                                                Err(e) => UiMode::Message(e),
+                                            };
+                                        }
+                                    }
+                                    KeyCode::Char('s') => {
+                                        if let Some(a) = alias_state.selected().and_then(|i| aliases.get(i)) {
+                                            let code = share_string(a);
+                                            ui_mode = match copy_to_clipboard(&code, &cfg.clipboard) {
+                                                Ok(()) => UiMode::Message(trf("Copied a share string for '{}', import it with tuish import", &[&a.name])),
+                                                Err(e) => UiMode::TextView { title: format!("Share '{}'", a.name), lines: vec![e, String::new(), code], scroll: 0 },
+                                            };
+                                        }
+                                    }
+                                    KeyCode::Char('v') => {
+                                        if let Some(a) = alias_state.selected().and_then(|i| aliases.get(i)) {
+                                            ui_mode = match latest_job(&jobs, &a.name) {
+                                                Some(job) => UiMode::Output { job },
+                                                None => UiMode::Message(trf("No output kept for '{}', run it with o first", &[&a.name])),
+                                            };
+                                        }
+                                    }
+                                    KeyCode::Char('d') => {
+                                        if let Some(a) = alias_state.selected().and_then(|i| aliases.get(i)) {
+                                            ui_mode = diff_view(&jobs, &a.name);
+                                        }
+                                    }
+                                    KeyCode::Char('x') => {
+                                        if let Some(a) = alias_state.selected().and_then(|i| aliases.get(i)) {
+                                            let mut lines = explain_alias(a, &shell_env, &cfg.danger_patterns);
+                                            if let Some(j) = jobs.iter().rev().find(|j| j.name == a.name) {
+                                                lines.push(trf("Last output: {} lines, {} (v to view)", &[&j.lines.len(), &j.status().0]));
+                                            }
+                                            ui_mode = UiMode::TextView { title: tr("Explain").to_string(), lines, scroll: 0 };
+                                        }
+                                    }
+                                    KeyCode::Char('m') => {
+                                        if let Some(a) = alias_state.selected().and_then(|i| aliases.get(i)) {
+                                            let width = crossterm::terminal::size().map(|(w, _)| w * 4 / 5).unwrap_or(80).saturating_sub(2).max(20);
+                                            ui_mode = match alias_program(a).ok_or_else(|| tr("Bookmarks have no manual").to_string()).and_then(|p| man_page(&p, width).map(|lines| (p, lines))) {
+                                                Ok((program, lines)) => UiMode::TextView { title: format!("man {}", program), lines, scroll: 0 },
+                                                Err(e) => UiMode::Message(e),
+                                            };
+                                        }
+                                    }
+                                    KeyCode::Char('t') => {
+                                        if let Some(a) = alias_state.selected().and_then(|i| aliases.get(i)) {
+                                            ui_mode = match alias_program(a).ok_or_else(|| tr("Bookmarks have no tldr page").to_string()).and_then(|p| tldr_page(&p, &cfg_path).map(|lines| (p, lines))) {
+                                                Ok((program, lines)) => UiMode::TextView { title: format!("tldr {}", program), lines, scroll: 0 },
+                                                Err(e) => UiMode::Message(e),
+                                            };
+                                        }
+                                    }
+                                    KeyCode::Char('h') => {
+                                        if let Some(i) = alias_state.selected().filter(|i| *i < aliases.len()) {
+                                            let a = &aliases[i];
+                                            ui_mode = if let Some(reason) = read_only_reason(a) {
+                                                UiMode::Message(reason)
+                                            } else if state.versions.get(&a.name).is_none_or(|v| v.is_empty()) {
+                                                UiMode::Message(trf("No earlier versions of '{}' yet", &[&a.name]))
+                                            } else {
+                                                UiMode::AliasHistory { index: i, cursor: 0 }
+                                            };
+                                        }
+                                    }
+                                    KeyCode::Char('w') => {
+                                        if let Some(i) = alias_state.selected().filter(|i| *i < aliases.len()) {
+                                            let start = aliases[i].cwd.as_deref().map(expand_home).filter(|d| d.is_dir())
+                                                .or_else(|| std::env::current_dir().ok()).unwrap_or_else(|| PathBuf::from("/"));
+                                            ui_mode = match (read_only_reason(&aliases[i]), &aliases[i].container) {
+                                                (Some(reason), _) => UiMode::Message(reason),
+                                                // the browser only sees this machine, not the container's files
+                                                (None, Some(c)) => UiMode::Message(trf("'{}' runs in container {}, set its `cwd` in the config to a directory in there", &[&aliases[i].name, c])),
+                                                (None, None) => pick_dir(Some(i), start),
+                                            };
+                                        }
+                                    }
+                                    KeyCode::Char('l') => {
+                                        if let Some(a) = alias_state.selected().and_then(|i| aliases.get(i)) {
+                                            ui_mode = match shellcheck(&a.command, &shell_env.shell) {
+                                                Ok(lines) if lines.is_empty() => UiMode::Message(tr("shellcheck: no issues").to_string()),
+                                                Ok(lines) => UiMode::TextView { title: trf("shellcheck: {}", &[&a.name]), lines, scroll: 0 },
+                                                Err(e) => UiMode::Message(e),
+                                            };
+                                        }
+                                    }
+                                    _ => { dirty = dirty_before; }
+                                }
+                            }
+                        }
+                    }
+                    UiMode::Adding { step, kind, name, command, keybind, ask_command, error } => {
+                        match key.code {
+                            KeyCode::Esc => { ui_mode = UiMode::Main; }
+                            KeyCode::Enter => {
+                                if *step == 1 { *step = if *ask_command { 2 } else { 3 }; }
+                                else if *step == 2 {
+                                    // a typo in the program is caught here, a second Enter keeps it anyway
+                                    let typed = Alias { command: command.clone(), kind: *kind, ..Default::default() };
+                                    if *kind == AliasKind::Command && not_found.is_none() && !alias_resolves(&typed, &path_var)
+                                        && let Some(prog) = command_program(command) {
+                                        let guess = if prog.contains('/') { None } else { did_you_mean(prog, &path_var) };
+                                        not_found = Some((prog.to_string(), guess));
+                                    } else {
+                                        not_found = None;
+                                        *step = 3;
+                                    }
+                                }
+                                else if let Some(problem) = keybind.and_then(|kb| keybind_problem(&aliases, &cfg.groups, kb, None, usize::MAX)) {
+                                    *error = Some(problem);
+                                }
+                                else {
+                                    // finalize
+                                    let mut alias = Alias { name: name.clone(), command: command.clone(), keybind: *keybind, kind: *kind, ..Default::default() };
+                                    alias.missing = !alias_resolves(&alias, &path_var);
+                                    let warning = missing_warning(&alias);
+                                    aliases.push(alias);
+                                    write_config(&cfg_path, &aliases, &cfg);
+                                    // update alias_state
+                                    if alias_state.selected().is_none() {
+                                        alias_state.select(Some(0));
+                                    } else {
+                                        alias_state.select(Some(aliases.len().saturating_sub(1)));
+                                    }
+                                    ui_mode = match warning { Some(w) => UiMode::Message(w), None => UiMode::Main };
+                                }
+                            }
+                            KeyCode::Tab if *step == 2 && matches!(not_found, Some((_, Some(_)))) => {
+                                if let Some((prog, Some(guess))) = not_found.take()
+                                    && let Some(at) = command_program(command).map(|p| p.as_ptr() as usize - command.as_ptr() as usize) {
+                                    command.replace_range(at..at + prog.len(), &guess);
+                                }
+                            }
+                            KeyCode::Tab if *step == 2 => {
+                                let (completed, rest) = complete_input(command, &path_var, *kind == AliasKind::Cd);
+                                *command = completed;
+                                completions = rest;
+                            }
+                            KeyCode::Char('e') if *step == 2 && key.modifiers.contains(KeyModifiers::CONTROL) => {
+                                match edit_in_editor(&mut terminal, command) {
+                                    Ok(edited) => { *command = edited; }
+                                    Err(e) => { ui_mode = UiMode::Message(e); }
+                                }
+                            }
+                            _ if *step == 3 => { *keybind = next_keybind(*keybind, &key); *error = None; }
+                            KeyCode::Backspace => {
+                                if *step == 1 { name.pop(); } else { command.pop(); }
+                            }
+                            KeyCode::Char(c) => {
+                                if *step == 1 { name.push(c); } else { command.push(c); }
+                            }
+                            _ => {}
+                        }
                     }
-                    _ => {}
-                }
-
-                match &mut ui_mode {
-                    UiMode::Main => {
-                        match focus {
-                            Focus::Actions => {
-                                match key.code {
-                                    KeyCode::Up => {
-                                        if selected_opt == 0 { selected_opt = options.len()-1 } else { selected_opt -= 1 }
-                                        opt_state.select(Some(selected_opt));
+                    UiMode::EditKeybind { index, keybind, error } => {
+                        match key.code {
+                            KeyCode::Esc => { ui_mode = UiMode::Main; }
+                            KeyCode::Enter => {
+                                let group = aliases.get(*index).and_then(|a| a.group.as_deref());
+                                if let Some(problem) = keybind.and_then(|kb| keybind_problem(&aliases, &cfg.groups, kb, group, *index)) {
+                                    *error = Some(problem);
+                                    continue;
+                                }
+                                let mut warning = None;
+                                if let Some(a) = aliases.get_mut(*index) {
+                                    a.keybind = *keybind;
+                                    warning = missing_warning(a);
+                                }
+                                write_config(&cfg_path, &aliases, &cfg);
+                                // offer the running containers as targets, if there are any
+                                let containers = docker_containers();
+                                if containers.is_empty() {
+                                    ui_mode = match warning { Some(w) => UiMode::Message(w), None => UiMode::Main };
+                                } else {
+                                    let mut items = vec![tr("(run locally)").to_string()];
+                                    items.extend(containers);
+                                    let current = aliases.get(*index).and_then(|a| a.container.clone());
+                                    let cursor = current.and_then(|c| items.iter().position(|i| *i == c)).unwrap_or(0);
+                                    ui_mode = UiMode::PickContainer { index: *index, items, cursor };
+                                }
+                            }
+                            _ => {
+                                *keybind = next_keybind(*keybind, &key);
+                                *error = None;
+                            }
+                        }
+                    }
+                    mode @ (UiMode::EditingSelect | UiMode::RenameSelect) => {
+                        let renaming = matches!(mode, UiMode::RenameSelect);
+                        // navigate aliases and select using alias_state
+                        match key.code {
+                            KeyCode::Up => {
+                                if aliases.is_empty() { continue };
+                                let i = alias_state.selected().unwrap_or(0);
+                                let new = if i == 0 { aliases.len()-1 } else { i-1 };
+                                alias_state.select(Some(new));
+                            }
+                            KeyCode::Down => { if !aliases.is_empty() { let i = alias_state.selected().unwrap_or(0); alias_state.select(Some((i+1) % aliases.len())); } }
+                            KeyCode::Enter => {
+                                if let Some(idx) = alias_state.selected() {
+                                    ui_mode = if let Some(reason) = read_only_reason(&aliases[idx]) {
+                                        UiMode::Message(reason)
+                                    } else if renaming {
+                                        UiMode::Renaming { index: idx, name: aliases[idx].name.clone(), error: None }
+                                    } else {
+                                        UiMode::Editing { index: idx, command: aliases[idx].command.clone() }
+                                    };
+                                }
+                            }
+                            KeyCode::Esc => { ui_mode = UiMode::Main; }
+                            _ => {}
+                        }
+                    }
+                    UiMode::Output { job } => {
+                        let j = &mut jobs[*job];
+                        let last_top = j.shown_len().saturating_sub(output_rows);
+                        // scrolling by hand stops following, from wherever follow had us
+                        if j.follow && matches!(key.code, KeyCode::Up | KeyCode::PageUp | KeyCode::Home) {
+                            j.follow = false;
+                            j.scroll = last_top;
+                        }
+                        match key.code {
+                            KeyCode::Esc | KeyCode::Char('q') => { ui_mode = UiMode::Main; }
+                            KeyCode::Char('f') => {
+                                j.follow = !j.follow;
+                                j.scroll = last_top;
+                            }
+                            KeyCode::Char('k') => j.kill(),
+                            // the two views don't line up, so start the other one at the bottom
+                            KeyCode::Char('e') => {
+                                j.stderr_only = !j.stderr_only;
+                                j.follow = true;
+                            }
+                            KeyCode::Char('d') => {
+                                let name = j.name.clone();
+                                ui_mode = diff_view(&jobs, &name);
+                            }
+                            KeyCode::Char('s') => {
+                                ui_mode = UiMode::SaveOutput { job: *job, path: default_log_path(&j.name) };
+                            }
+                            // for when the terminal can't click them: the links on screen, opened from here
+                            KeyCode::Char('o') => {
+                                let mut items: Vec<String> = j.visible(output_rows).into_iter().flat_map(|(_, _, links)| links).map(|(_, _, t)| t.clone()).collect();
+                                // each link once, in the order they're on screen
+                                let mut seen = HashSet::new();
+                                items.retain(|t| seen.insert(t.clone()));
+                                ui_mode = match items.len() {
+                                    0 => UiMode::Message(tr("No links on screen").to_string()),
+                                    1 => match open_link(&items[0]) { Ok(()) => UiMode::Output { job: *job }, Err(e) => UiMode::Message(e) },
+                                    _ => UiMode::Links { job: *job, items, cursor: 0 },
+                                };
+                            }
+                            KeyCode::Up => { j.scroll = j.scroll.saturating_sub(1); }
+                            KeyCode::Down => { j.scroll = (j.scroll + 1).min(last_top); }
+                            KeyCode::PageUp => { j.scroll = j.scroll.saturating_sub(output_rows); }
+                            KeyCode::PageDown => { j.scroll = (j.scroll + output_rows).min(last_top); }
+                            KeyCode::Home => { j.scroll = 0; }
+                            KeyCode::End => { j.follow = true; }
+                            // each job keeps its own scroll and follow, switching tabs just changes which one we look at
+                            KeyCode::Right | KeyCode::Tab => { *job = (*job + 1) % jobs.len(); }
+                            KeyCode::Left | KeyCode::BackTab => { *job = (*job + jobs.len() - 1) % jobs.len(); }
+                            _ => {}
+                        }
+                    }
+                    UiMode::Links { job, items, cursor } => {
+                        match key.code {
+                            KeyCode::Up => { *cursor = if *cursor == 0 { items.len()-1 } else { *cursor-1 }; }
+                            KeyCode::Down => { *cursor = (*cursor+1) % items.len(); }
+                            KeyCode::Enter => {
+                                ui_mode = match open_link(&items[*cursor]) { Ok(()) => UiMode::Output { job: *job }, Err(e) => UiMode::Message(e) };
+                            }
+                            KeyCode::Char('c') => {
+                                ui_mode = match copy_to_clipboard(&items[*cursor], &cfg.clipboard) {
+                                    Ok(()) => UiMode::Message(trf("Copied: {}", &[&items[*cursor]])),
+                                    Err(e) => UiMode::Message(e),
+                                };
+                            }
+                            KeyCode::Esc => { ui_mode = UiMode::Output { job: *job }; }
+                            _ => {}
+                        }
+                    }
+                    UiMode::SaveOutput { job, path } => {
+                        match key.code {
+                            KeyCode::Esc => { ui_mode = UiMode::Output { job: *job }; }
+                            KeyCode::Enter => {
+                                let file = expand_home(path.trim());
+                                ui_mode = match fs::write(&file, jobs[*job].all_output()) {
+                                    Ok(()) => UiMode::Message(trf("Saved to {}", &[&file.display()])),
+                                    Err(e) => UiMode::Message(trf("Couldn't write {}: {}", &[&file.display(), &e])),
+                                };
+                            }
+                            KeyCode::Tab => {
+                                let (completed, rest) = complete_input(path, &path_var, true);
+                                *path = completed;
+                                completions = rest;
+                            }
+                            KeyCode::Backspace => { path.pop(); }
+                            KeyCode::Char(c) => { path.push(c); }
+                            _ => {}
+                        }
+                    }
+                    UiMode::InlineEdit { index, command } => {
+                        match key.code {
+                            KeyCode::Esc => { ui_mode = UiMode::Main; }
+                            KeyCode::Enter => {
+                                let mut warning = None;
+                                if let Some(a) = aliases.get_mut(*index) {
+                                    if a.command != *command {
+                                        state.remember_version(a);
+                                        write_state(&state_file, &state);
                                     }
-                                    KeyCode::Down => { selected_opt = (selected_opt+1) % options.len(); opt_state.select(Some(selected_opt)); }
-                                    KeyCode::Enter => {
-                                        match selected_opt {
-                                            0 => { ui_mode = UiMode::Adding { step: 1, name: String::new(), command: String::new(), keybind: None }; }
-                                            1 => { ui_mode = if aliases.is_empty() { UiMode::Main } else { UiMode::EditingSelect }; }
-                                            2 => {
-                                                if aliases.is_empty() {
-                                                    ui_mode = UiMode::Message("No aliases to remove".to_string());
-                                                } else {
-                                                    ui_mode = UiMode::RemovingSelect;
-                                                }
-                                            }
-                                            3 => { // Go to shell
-                                                // leave TUI and spawn user's default shell
-                                                disable_raw_mode().ok();
-                                                execute!(terminal.backend_mut(), LeaveAlternateScreen).ok();
-                                                let shell = default_shell.clone();
-                                                let child = Command::new(shell).spawn();
-                                                match child {
-                                                    Ok(mut c) => { let _ = c.wait(); }
-                                                    Err(e) => { println!("Failed to spawn shell: {}", e); }
-                                                }
-                                                // re-enter TUI
-                                                execute!(std::io::stdout(), EnterAlternateScreen).ok();
-                                                enable_raw_mode().ok();
-                                                terminal = Terminal::new(CrosstermBackend::new(std::io::stdout())).unwrap();
-                                            }
-                                            4 => { // Quit shell
-                                                disable_raw_mode().ok();
-                                                terminal.clear().ok();
-                                                execute!(terminal.backend_mut(), LeaveAlternateScreen).ok();
-                                                return;
-                                            }
-                                            _ => {}
-                                        }
+                                    a.command = command.clone();
+                                    a.missing = !alias_resolves(a, &path_var);
+                                    warning = missing_warning(a);
+                                }
+                                write_config(&cfg_path, &aliases, &cfg);
+                                ui_mode = match warning { Some(w) => UiMode::Message(w), None => UiMode::Main };
+                            }
+                            KeyCode::Tab => {
+                                let is_cd = aliases.get(*index).map(|a| a.kind == AliasKind::Cd).unwrap_or(false);
+                                let (completed, rest) = complete_input(command, &path_var, is_cd);
+                                *command = completed;
+                                completions = rest;
+                            }
+                            KeyCode::Char('e') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                                match edit_in_editor(&mut terminal, command) {
+                                    Ok(edited) => { *command = edited; }
+                                    Err(e) => { ui_mode = UiMode::Message(e); }
+                                }
+                            }
+                            KeyCode::Backspace => { command.pop(); }
+                            KeyCode::Char(c) => { command.push(c); }
+                            _ => {}
+                        }
+                    }
+                    UiMode::Renaming { index, name, error } => {
+                        match key.code {
+                            KeyCode::Esc => { ui_mode = UiMode::Main; }
+                            KeyCode::Enter => {
+                                let before = aliases[*index].clone();
+                                match rename_alias(&mut aliases, *index, name) {
+                                    Ok(()) => {
+                                        // keep past runs (and versions) attached to the alias
+                                        if before.name != name.trim() { state.remember_version(&before); }
+                                        state.rename(&before.name, name.trim());
+                                        write_state(&state_file, &state);
+                                        write_config(&cfg_path, &aliases, &cfg);
+                                        ui_mode = UiMode::Main;
                                     }
-                                    KeyCode::Char(c) => {
-                                        // trigger alias by keybind
-                                        if let Some(idx) = aliases.iter().position(|a| a.keybind == Some(c)) {
-                                            // Run alias
-                                            let cmd = aliases[idx].command.clone();
-                                            // leave alternate screen and run
-                                            disable_raw_mode().ok();
-                                            execute!(terminal.backend_mut(), LeaveAlternateScreen).ok();
-                                            run_shell_command_with_shell(&cmd, &default_shell);
-                                            // after key press, re-enter
-                                            execute!(std::io::stdout(), EnterAlternateScreen).ok();
-                                            enable_raw_mode().ok();
-                                            terminal = Terminal::new(CrosstermBackend::new(std::io::stdout())).unwrap();
-                                        }
+                                    Err(e) => { *error = Some(e); }
+                                }
+                            }
+                            KeyCode::Backspace => { name.pop(); *error = None; }
+                            KeyCode::Char(c) => { name.push(c); *error = None; }
+                            _ => {}
+                        }
+                    }
+                    UiMode::ImportShared { input } => {
+                        match key.code {
+                            KeyCode::Enter => {
+                                ui_mode = match import_shared(&mut aliases, input) {
+                                    Ok(msg) => {
+                                        check_aliases(&mut aliases, &path_var);
+                                        write_config(&cfg_path, &aliases, &cfg);
+                                        UiMode::Message(msg)
                                     }
-                                    _ => {}
+                                    Err(e) => UiMode::Message(e),
+                                };
+                            }
+                            KeyCode::Backspace => { input.pop(); }
+                            KeyCode::Char(c) => { input.push(c); }
+                            KeyCode::Esc => { ui_mode = UiMode::Main; }
+                            _ => {}
+                        }
+                    }
+                    UiMode::CommandBar { input } => {
+                        match key.code {
+                            KeyCode::Esc => { ui_mode = UiMode::Main; }
+                            KeyCode::Enter => {
+                                if !input.trim().is_empty() {
+                                    run_request = Some(Alias { name: ADHOC_NAME.to_string(), command: input.trim().to_string(), ..Default::default() });
                                 }
+                                ui_mode = UiMode::Main;
                             }
-                            Focus::Aliases => {
-                                match key.code {
-                                    KeyCode::Up => { if !aliases.is_empty() {
-                                            let i = alias_state.selected().unwrap_or(0);
-                                            let new = if i == 0 { aliases.len()-1 } else { i-1 };
-                                            alias_state.select(Some(new));
-                                        }
+                            KeyCode::Tab => {
+                                let (completed, rest) = complete_input(input, &path_var, false);
+                                *input = completed;
+                                completions = rest;
+                            }
+                            KeyCode::Char('e') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                                match edit_in_editor(&mut terminal, input) {
+                                    Ok(edited) => { *input = edited; }
+                                    Err(e) => { ui_mode = UiMode::Message(e); }
+                                }
+                            }
+                            KeyCode::Backspace => { input.pop(); }
+                            KeyCode::Char(c) => { input.push(c); }
+                            _ => {}
+                        }
+                    }
+                    UiMode::Editing { index, command } => {
+                        match key.code {
+                            KeyCode::Esc => { ui_mode = UiMode::Main; }
+                            KeyCode::Enter => {
+                                let mut keybind = None;
+                                if let Some(a) = aliases.get_mut(*index) {
+                                    if a.command != *command {
+                                        state.remember_version(a);
+                                        write_state(&state_file, &state);
                                     }
-                                    KeyCode::Down => { if !aliases.is_empty() {
-                                            let i = alias_state.selected().unwrap_or(0);
-                                            let new = (i+1) % aliases.len();
-                                            alias_state.select(Some(new));
-                                        }
+                                    a.command = command.clone();
+                                    a.missing = !alias_resolves(a, &path_var);
+                                    keybind = a.keybind;
+                                }
+                                write_config(&cfg_path, &aliases, &cfg);
+                                ui_mode = UiMode::EditKeybind { index: *index, keybind, error: None };
+                            }
+                            KeyCode::Tab => {
+                                let is_cd = aliases.get(*index).map(|a| a.kind == AliasKind::Cd).unwrap_or(false);
+                                let (completed, rest) = complete_input(command, &path_var, is_cd);
+                                *command = completed;
+                                completions = rest;
+                            }
+                            KeyCode::Char('e') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                                match edit_in_editor(&mut terminal, command) {
+                                    Ok(edited) => { *command = edited; }
+                                    Err(e) => { ui_mode = UiMode::Message(e); }
+                                }
+                            }
+                            KeyCode::Backspace => { command.pop(); }
+                            KeyCode::Char(c) => { command.push(c); }
+                            _ => {}
+                        }
+                    }
+                    UiMode::RemovingSelect => {
+                        match key.code {
+                            KeyCode::Up => {
+                                if aliases.is_empty() { ui_mode = UiMode::Main; continue };
+                                let i = alias_state.selected().unwrap_or(0);
+                                let new = if i == 0 { aliases.len()-1 } else { i-1 };
+                                alias_state.select(Some(new));
+                            }
+                            KeyCode::Down => {
+                                if !aliases.is_empty() {
+                                    let i = alias_state.selected().unwrap_or(0);
+                                    alias_state.select(Some((i+1) % aliases.len()));
+                                }
+                            }
+                            KeyCode::Enter => {
+                                if let Some(reason) = alias_state.selected().and_then(|i| read_only_reason(&aliases[i])) {
+                                    ui_mode = UiMode::Message(reason);
+                                } else if let Some(idx) = alias_state.selected() {
+                                    let removed = aliases.remove(idx);
+                                    cfg.trash.push(TrashEntry { name: removed.name.clone(), entry: alias_entry(&removed), deleted: unix_now() });
+                                    write_config(&cfg_path, &aliases, &cfg);
+                                    // update alias_state selection
+                                    if aliases.is_empty() { alias_state.select(None); } else { alias_state.select(Some(0)); }
+                                    ui_mode = UiMode::Main;
+                                }
+                            }
+                            KeyCode::Esc => { ui_mode = UiMode::Main; }
+                            _ => {}
+                        }
+                    }
+                    UiMode::Trash { cursor } => {
+                        match key.code {
+                            KeyCode::Up => { *cursor = if *cursor == 0 { cfg.trash.len()-1 } else { *cursor-1 }; }
+                            KeyCode::Down => { *cursor = (*cursor+1) % cfg.trash.len(); }
+                            KeyCode::Enter => {
+                                let t = &cfg.trash[*cursor];
+                                if aliases.iter().any(|a| a.name == t.name) {
+                                    ui_mode = UiMode::Message(trf("An alias named '{}' already exists", &[&t.name]));
+                                    continue;
+                                }
+                                let t = cfg.trash.remove(*cursor);
+                                aliases.extend(aliases_from(&HashMap::from([(t.name, t.entry)]), false));
+                                write_config(&cfg_path, &aliases, &cfg);
+                                if alias_state.selected().is_none() { alias_state.select(Some(0)); }
+                                ui_mode = UiMode::Main;
+                            }
+                            KeyCode::Char('d') | KeyCode::Delete => {
+                                let _ = backup_config(&cfg_path, None, true);
+                                cfg.trash.remove(*cursor);
+                                write_config(&cfg_path, &aliases, &cfg);
+                                if cfg.trash.is_empty() { ui_mode = UiMode::Main; } else { *cursor = (*cursor).min(cfg.trash.len()-1); }
+                            }
+                            KeyCode::Esc => { ui_mode = UiMode::Main; }
+                            _ => {}
+                        }
+                    }
+                    UiMode::Importing { .. } => {
+                        // the scanners finish on their own, nobody's listening anymore
+                        if key.code == KeyCode::Esc { ui_mode = UiMode::Main; }
+                    }
+                    UiMode::ReadingHelp { .. } | UiMode::LoadingChoices { .. } => {
+                        if key.code == KeyCode::Esc { ui_mode = UiMode::Main; }
+                    }
+                    UiMode::Tasks { items, picked, cursor, .. } => {
+                        match key.code {
+                            KeyCode::Up => { *cursor = if *cursor == 0 { items.len()-1 } else { *cursor-1 }; }
+                            KeyCode::Down => { *cursor = (*cursor+1) % items.len(); }
+                            KeyCode::Char(' ') => { picked[*cursor] = !picked[*cursor]; }
+                            KeyCode::Enter => {
+                                let t = &items[*cursor];
+                                run_request = Some(Alias { name: t.alias_name(), command: t.command.clone(), ..Default::default() });
+                            }
+                            KeyCode::Char('s') => {
+                                // with nothing picked, save just the highlighted task
+                                if !picked.contains(&true) { picked[*cursor] = true; }
+                                let _ = backup_config(&cfg_path, None, true);
+                                let mut added = 0;
+                                for (t, p) in items.iter().zip(picked.iter()) {
+                                    let name = t.alias_name();
+                                    if !*p || aliases.iter().any(|a| a.name == name) { continue; }
+                                    aliases.push(Alias { name, command: t.command.clone(), ..Default::default() });
+                                    added += 1;
+                                }
+                                write_config(&cfg_path, &aliases, &cfg);
+                                if alias_state.selected().is_none() { alias_state.select(Some(0)); }
+                                ui_mode = UiMode::Message(trf("Saved {} alias(es)", &[&added]));
+                            }
+                            KeyCode::Esc => { ui_mode = UiMode::Main; }
+                            _ => {}
+                        }
+                    }
+                    UiMode::Tmux { items, cursor } => {
+                        // the extra last row is "(new session)"
+                        let len = items.len() + 1;
+                        match key.code {
+                            KeyCode::Up => { *cursor = if *cursor == 0 { len-1 } else { *cursor-1 }; }
+                            KeyCode::Down => { *cursor = (*cursor+1) % len; }
+                            KeyCode::Enter => {
+                                if let Some(e) = items.get(*cursor) {
+                                    let cmd = tmux_attach(&e.target);
+                                    ui_mode = UiMode::Main;
+                                    run_and_return(&mut terminal, cmd, Pause::Always);
+                                } else {
+                                    // let the user name the new session in the add flow
+                                    ui_mode = UiMode::Adding { step: 1, kind: AliasKind::Command, name: "tmux ".to_string(), command: "tmux new-session -A -s ".to_string(), keybind: None, ask_command: true, error: None };
+                                }
+                            }
+                            KeyCode::Char('a') => {
+                                // `new-session -A` attaches when the session exists and creates it otherwise
+                                let (name, command) = match items.get(*cursor) {
+                                    Some(e) if e.is_window => (format!("tmux {}", e.target), format!("tmux attach-session -t {}", e.target)),
+                                    Some(e) => (format!("tmux {}", e.target), format!("tmux new-session -A -s {}", e.target)),
+                                    None => ("tmux ".to_string(), "tmux new-session -A -s ".to_string()),
+                                };
+                                ui_mode = UiMode::Adding { step: 1, kind: AliasKind::Command, name, command, keybind: None, ask_command: true, error: None };
+                            }
+                            KeyCode::Esc => { ui_mode = UiMode::Main; }
+                            _ => {}
+                        }
+                    }
+                    UiMode::PickContainer { index, items, cursor } => {
+                        match key.code {
+                            KeyCode::Up => { *cursor = if *cursor == 0 { items.len()-1 } else { *cursor-1 }; }
+                            KeyCode::Down => { *cursor = (*cursor+1) % items.len(); }
+                            KeyCode::Enter => {
+                                let container = if *cursor == 0 { None } else { Some(items[*cursor].clone()) };
+                                if let Some(a) = aliases.get_mut(*index) {
+                                    a.container = container;
+                                    a.missing = !alias_resolves(a, &path_var);
+                                }
+                                write_config(&cfg_path, &aliases, &cfg);
+                                ui_mode = UiMode::Main;
+                            }
+                            // keep whatever target the alias already had
+                            KeyCode::Esc => { ui_mode = UiMode::Main; }
+                            _ => {}
+                        }
+                    }
+                    UiMode::Search { query, options, cursor } => {
+                        let found = search_aliases(&aliases, &mut search_index, query, options).unwrap_or_default();
+                        match key.code {
+                            KeyCode::Esc => { ui_mode = UiMode::Main; }
+                            KeyCode::Enter => {
+                                if let Some(i) = found.get(*cursor) {
+                                    run_request = Some(aliases[*i].clone());
+                                    ui_mode = UiMode::Main;
+                                }
+                            }
+                            // back to the list with the match selected
+                            KeyCode::Tab => {
+                                if let Some(i) = found.get(*cursor) {
+                                    alias_state.select(Some(*i));
+                                    focus = Focus::Aliases;
+                                    ui_mode = UiMode::Main;
+                                }
+                            }
+                            KeyCode::Up => { *cursor = cursor.saturating_sub(1); }
+                            KeyCode::Down => { *cursor = (*cursor + 1).min(found.len().saturating_sub(1)); }
+                            KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => { options.regex = !options.regex; *cursor = 0; }
+                            KeyCode::Char('n') if key.modifiers.contains(KeyModifiers::CONTROL) => { options.scope = options.scope.next(); *cursor = 0; }
+                            KeyCode::Char('s') if key.modifiers.contains(KeyModifiers::CONTROL) => { options.case_sensitive = !options.case_sensitive; *cursor = 0; }
+                            KeyCode::Backspace => { query.pop(); *cursor = 0; }
+                            KeyCode::Char(c) => { query.push(c); *cursor = 0; }
+                            _ => {}
+                        }
+                    }
+                    UiMode::AliasHistory { index, cursor } => {
+                        let name = aliases[*index].name.clone();
+                        let count = state.versions.get(&name).map(|v| v.len()).unwrap_or(0);
+                        match key.code {
+                            KeyCode::Up => { *cursor = cursor.saturating_sub(1); }
+                            KeyCode::Down => { *cursor = (*cursor + 1).min(count.saturating_sub(1)); }
+                            KeyCode::Enter => {
+                                let Some(v) = state.versions.get(&name).and_then(|v| v.iter().rev().nth(*cursor)).cloned() else { continue };
+                                if v.name != name && aliases.iter().any(|a| a.name == v.name) {
+                                    ui_mode = UiMode::Message(trf("There's already an alias named '{}'", &[&v.name]));
+                                    continue;
+                                }
+                                // what it is now becomes a version too, so going back can be undone
+                                state.remember_version(&aliases[*index]);
+                                let mut restored = aliases_from(&HashMap::from([(v.name.clone(), v.entry.clone())]), aliases[*index].repo).remove(0);
+                                restored.missing = !alias_resolves(&restored, &path_var);
+                                aliases[*index] = restored;
+                                state.rename(&name, &v.name);
+                                write_state(&state_file, &state);
+                                write_config(&cfg_path, &aliases, &cfg);
+                                ui_mode = UiMode::Message(trf("'{}' is back to: {}", &[&v.name, &v.entry.command]));
+                            }
+                            KeyCode::Esc | KeyCode::Char('q') => { ui_mode = UiMode::Main; }
+                            _ => {}
+                        }
+                    }
+                    UiMode::PickProfile { items, cursor } => {
+                        match key.code {
+                            KeyCode::Up => { *cursor = if *cursor == 0 { items.len()-1 } else { *cursor-1 }; }
+                            KeyCode::Down => { *cursor = (*cursor+1) % items.len(); }
+                            KeyCode::Enter => {
+                                let old_dir = profile_cwd(&cfg);
+                                cfg.profile = if *cursor == 0 { None } else { Some(items[*cursor].clone()) };
+                                shell_env.use_profile(&cfg);
+                                write_config(&cfg_path, &aliases, &cfg);
+                                ui_mode = UiMode::Main;
+                                match enter_profile_dir(old_dir, start_dir.as_deref(), &mut aliases, &mut cfg, &path_var) {
+                                    Ok(true) => {
+                                        alias_state.select(if aliases.is_empty() { None } else { Some(0) });
+                                        if cfg.header.iter().any(|w| w == "branch") { header.branch = git_branch(); }
+                                    }
+                                    Ok(false) => {}
+                                    Err(e) => { ui_mode = UiMode::Message(e); }
+                                }
+                            }
+                            KeyCode::Esc => { ui_mode = UiMode::Main; }
+                            _ => {}
+                        }
+                    }
+                    UiMode::ImportHistory { items, picked, cursor } => {
+                        match key.code {
+                            KeyCode::Up => { *cursor = if *cursor == 0 { items.len()-1 } else { *cursor-1 }; }
+                            KeyCode::Down => { *cursor = (*cursor+1) % items.len(); }
+                            KeyCode::Char(' ') => { picked[*cursor] = !picked[*cursor]; }
+                            KeyCode::Enter => {
+                                // with nothing picked, import just the highlighted command
+                                if !picked.contains(&true) { picked[*cursor] = true; }
+                                let _ = backup_config(&cfg_path, None, true);
+                                let mut added = 0;
+                                for ((cmd, _), p) in items.iter().zip(picked.iter()) {
+                                    if !*p { continue; }
+                                    let name = suggest_alias_name(cmd, &aliases);
+                                    aliases.push(Alias { name, command: cmd.clone(), ..Default::default() });
+                                    added += 1;
+                                }
+                                write_config(&cfg_path, &aliases, &cfg);
+                                if alias_state.selected().is_none() { alias_state.select(Some(0)); }
+                                ui_mode = UiMode::Message(trf("Imported {} alias(es)", &[&added]));
+                            }
+                            KeyCode::Esc => { ui_mode = UiMode::Main; }
+                            _ => {}
+                        }
+                    }
+                    UiMode::EnvPanel { cursor } => {
+                        let vars = shell_env.effective();
+                        let last = vars.len().saturating_sub(1);
+                        match key.code {
+                            KeyCode::Up => { *cursor = cursor.saturating_sub(1); }
+                            KeyCode::Down => { *cursor = (*cursor + 1).min(last); }
+                            KeyCode::PageUp => { *cursor = cursor.saturating_sub(10); }
+                            KeyCode::PageDown => { *cursor = (*cursor + 10).min(last); }
+                            KeyCode::Char('a') => { ui_mode = UiMode::EnvEdit { original: None, input: String::new() }; }
+                            KeyCode::Char('e') | KeyCode::Enter => {
+                                if let Some((k, v)) = vars.get(*cursor) {
+                                    ui_mode = UiMode::EnvEdit { original: Some(k.clone()), input: format!("{}={}", k, v) };
+                                }
+                            }
+                            KeyCode::Char('d') => {
+                                if let Some((k, _)) = vars.get(*cursor) { shell_env.overrides.remove(k); shell_env.resolve(); path_var = shell_env.path_var(); }
+                            }
+                            KeyCode::Char('p') => {
+                                cfg.env = shell_env.overrides.clone();
+                                write_config(&cfg_path, &aliases, &cfg);
+                            }
+                            KeyCode::Esc => { ui_mode = UiMode::Main; }
+                            _ => {}
+                        }
+                    }
+                    UiMode::Kube { items, cursor } => {
+                        match key.code {
+                            KeyCode::Up => { *cursor = if *cursor == 0 { items.len()-1 } else { *cursor-1 }; }
+                            KeyCode::Down => { *cursor = (*cursor+1) % items.len(); }
+                            KeyCode::Enter => {
+                                let out = Command::new("kubectl").args(&items[*cursor].switch).stdin(Stdio::null()).output();
+                                match out {
+                                    Ok(o) if o.status.success() => {
+                                        // reload so the current markers move
+                                        *items = kube_entries();
+                                        if *cursor >= items.len() { *cursor = 0; }
+                                        if items.is_empty() { ui_mode = UiMode::Main; }
                                     }
-                                    KeyCode::Enter => {
-                                        if let Some(i) = alias_state.selected() {
-                                            let cmd = aliases[i].command.clone();
-                                            disable_raw_mode().ok();
-                                            execute!(terminal.backend_mut(), LeaveAlternateScreen).ok();
-                                            run_shell_command_with_shell(&cmd, &default_shell);
-                                            execute!(std::io::stdout(), EnterAlternateScreen).ok();
-                                            enable_raw_mode().ok();
-                                            terminal = Terminal::new(CrosstermBackend::new(std::io::stdout())).unwrap();
-                                        }
+                                    Ok(o) => { ui_mode = UiMode::Message(String::from_utf8_lossy(&o.stderr).trim().to_string()); }
+                                    Err(e) => { ui_mode = UiMode::Message(trf("Failed to run kubectl: {}", &[&e])); }
+                                }
+                            }
+                            KeyCode::Char('a') => {
+                                let e = &items[*cursor];
+                                let name = format!("kube {}", e.label.split_whitespace().collect::<Vec<_>>().join(" "));
+                                ui_mode = UiMode::Adding { step: 1, kind: AliasKind::Command, name, command: format!("kubectl {}", e.switch.join(" ")), keybind: None, ask_command: true, error: None };
+                            }
+                            KeyCode::Esc => { ui_mode = UiMode::Main; }
+                            _ => {}
+                        }
+                    }
+                    UiMode::EnvEdit { original, input } => {
+                        match key.code {
+                            KeyCode::Enter => {
+                                match input.split_once('=') {
+                                    Some((k, v)) if !k.trim().is_empty() => {
+                                        let k = k.trim().to_string();
+                                        // renaming drops the old override
+                                        if let Some(old) = original.as_ref().filter(|o| **o != k) { shell_env.overrides.remove(old); }
+                                        shell_env.overrides.insert(k.clone(), v.to_string());
+                                        shell_env.resolve();
+                                        path_var = shell_env.path_var();
+                                        let cursor = shell_env.effective().iter().position(|(n, _)| *n == k).unwrap_or(0);
+                                        ui_mode = UiMode::EnvPanel { cursor };
                                     }
-                                    _ => {}
+                                    _ => { ui_mode = UiMode::Message(tr("Expected NAME=value").to_string()); }
                                 }
                             }
+                            KeyCode::Backspace => { input.pop(); }
+                            KeyCode::Char(c) => { input.push(c); }
+                            KeyCode::Esc => { ui_mode = UiMode::EnvPanel { cursor: 0 }; }
+                            _ => {}
                         }
                     }
-                    UiMode::Adding { step, name, command, keybind } => {
+                    UiMode::Locked { previous, input, wrong } => {
                         match key.code {
-                            KeyCode::Esc => { ui_mode = UiMode::Main; }
+                            KeyCode::Enter if Some(input.as_str()) == lock_pass.as_deref() => {
+                                let previous = std::mem::replace(previous, Box::new(UiMode::Main));
+                                ui_mode = *previous;
+                            }
+                            KeyCode::Enter => { input.clear(); *wrong = true; }
+                            KeyCode::Backspace => { input.pop(); }
+                            KeyCode::Esc => { input.clear(); }
+                            KeyCode::Char(c) => { input.push(c); }
+                            _ => {}
+                        }
+                    }
+                    UiMode::Rerun { alias, .. } => {
+                        if matches!(key.code, KeyCode::Enter | KeyCode::Char('r')) {
+                            run_request = Some(alias.clone());
+                        }
+                        ui_mode = UiMode::Main;
+                    }
+                    UiMode::ConfirmRun { alias } => {
+                        if key.code == KeyCode::Char('y') {
+                            run_request = Some(alias.clone());
+                            run_confirmed = true;
+                        }
+                        ui_mode = UiMode::Main;
+                    }
+                    UiMode::Params { alias, names, values, choices, field, error } => {
+                        if key.code != KeyCode::Enter { *error = None; }
+                        match key.code {
+                            KeyCode::Tab => { *field = (*field + 1) % names.len(); }
+                            KeyCode::BackTab => { *field = if *field == 0 { names.len() - 1 } else { *field - 1 }; }
+                            KeyCode::Enter if *field + 1 < names.len() => { *field += 1; }
                             KeyCode::Enter => {
-                                if *step == 1 { *step = 2; }
-                                else if *step == 2 { *step = 3; }
-                                else {
-                                    // finalize
-                                    aliases.push(Alias { name: name.clone(), command: command.clone(), keybind: *keybind });
-                                    write_config(&cfg_path, &aliases, &default_shell);
-                                    // update alias_state
-                                    if alias_state.selected().is_none() {
-                                        alias_state.select(Some(0));
-                                    } else {
-                                        alias_state.select(Some(aliases.len().saturating_sub(1)));
+                                // nothing runs until every field checks out, the first bad one gets the cursor
+                                match names.iter().zip(values.iter()).enumerate().find_map(|(i, (n, v))| param_error(alias.params.get(n), n, v).map(|e| (i, e))) {
+                                    Some((i, e)) => {
+                                        *field = i;
+                                        *error = Some(e);
+                                    }
+                                    None => {
+                                        for (n, v) in names.iter().zip(values.iter()) {
+                                            if !alias.params.get(n).is_some_and(|p| p.secret) { state.remember_param(&alias.name, n, v); }
+                                        }
+                                        write_state(&state_file, &state);
+                                        run_request = Some(alias.clone());
+                                        run_values = Some(names.iter().cloned().zip(values.iter().cloned()).collect());
+                                        ui_mode = UiMode::Main;
                                     }
-                                    ui_mode = UiMode::Main;
                                 }
                             }
-                            KeyCode::Backspace => {
-                                if *step == 1 { name.pop(); } else if *step == 2 { command.pop(); } else { /* keybind step - ignore */ }
+                            KeyCode::Up | KeyCode::Down if param_picks(alias.params.get(&names[*field]), &values[*field], &choices[*field]) => {
+                                let opts = &choices[*field];
+                                let pos = opts.iter().position(|o| *o == values[*field]);
+                                let next = match (pos, key.code) {
+                                    (None, _) => 0,
+                                    (Some(p), KeyCode::Up) => if p == 0 { opts.len() - 1 } else { p - 1 },
+                                    (Some(p), _) => (p + 1) % opts.len(),
+                                };
+                                values[*field] = opts[next].clone();
                             }
-                            KeyCode::Char(c) => {
-                                if *step == 1 { name.push(c); }
-                                else if *step == 2 { command.push(c); }
-                                else if *step == 3 {
-                                    *keybind = Some(c);
+                            KeyCode::Right | KeyCode::End if !alias.params.get(&names[*field]).is_some_and(|p| p.secret || p.is_choice()) => {
+                                if let Some(rest) = param_completion(&values[*field], &choices[*field]) {
+                                    values[*field].push_str(rest);
                                 }
                             }
+                            KeyCode::Up => { *field = field.saturating_sub(1); }
+                            KeyCode::Down => { *field = (*field + 1).min(names.len() - 1); }
+                            // a choice is picked, not typed
+                            KeyCode::Backspace | KeyCode::Char(_) if alias.params.get(&names[*field]).is_some_and(|p| p.is_choice()) && !choices[*field].is_empty() => {}
+                            KeyCode::Backspace => { values[*field].pop(); }
+                            KeyCode::Char(c) => { values[*field].push(c); }
+                            KeyCode::Esc => { ui_mode = UiMode::Main; }
                             _ => {}
                         }
                     }
-                    UiMode::EditingSelect => {
-                        // navigate aliases and select using alias_state
+                    UiMode::Services { items, cursor, user } => {
+                        let verb = match key.code {
+                            KeyCode::Enter => Some("status"),
+                            KeyCode::Char('s') => Some("start"),
+                            KeyCode::Char('t') => Some("stop"),
+                            KeyCode::Char('r') => Some("restart"),
+                            _ => None,
+                        };
                         match key.code {
-                            KeyCode::Up => {
-                                if aliases.is_empty() { continue };
-                                let i = alias_state.selected().unwrap_or(0);
-                                let new = if i == 0 { aliases.len()-1 } else { i-1 };
-                                alias_state.select(Some(new));
+                            _ if verb.is_some() => {
+                                // run through the shell like any alias, then refresh the states
+                                if let (Some(verb), Some(e)) = (verb, items.get(*cursor)) {
+                                    run_and_return(&mut terminal, shell_command(&shell_env, &systemctl_line(*user, verb, &e.unit)), Pause::Always);
+                                    *items = systemd_services(*user);
+                                    *cursor = (*cursor).min(items.len().saturating_sub(1));
+                                }
                             }
-                            KeyCode::Down => { if !aliases.is_empty() { let i = alias_state.selected().unwrap_or(0); alias_state.select(Some((i+1) % aliases.len())); } }
-                            KeyCode::Enter => {
-                                if let Some(idx) = alias_state.selected() {
-                                    let cur_cmd = aliases[idx].command.clone();
-                                    ui_mode = UiMode::Editing { index: idx, command: cur_cmd };
+                            KeyCode::Up => { *cursor = cursor.saturating_sub(1); }
+                            KeyCode::Down => { *cursor = (*cursor + 1).min(items.len().saturating_sub(1)); }
+                            KeyCode::PageUp => { *cursor = cursor.saturating_sub(10); }
+                            KeyCode::PageDown => { *cursor = (*cursor + 10).min(items.len().saturating_sub(1)); }
+                            KeyCode::Char('u') => {
+                                *user = !*user;
+                                *items = systemd_services(*user);
+                                *cursor = 0;
+                            }
+                            KeyCode::Char('a') => {
+                                if let Some(e) = items.get(*cursor) {
+                                    let command = systemctl_line(*user, "restart", &e.unit);
+                                    ui_mode = UiMode::Adding { step: 1, kind: AliasKind::Command, name: format!("restart {}", e.unit.trim_end_matches(".service")), command, keybind: None, ask_command: true, error: None };
                                 }
                             }
                             KeyCode::Esc => { ui_mode = UiMode::Main; }
                             _ => {}
                         }
                     }
-                    UiMode::Editing { index, command } => {
+                    UiMode::Containers { items, cursor } => {
+                        let name = items.get(*cursor).map(|c| c.name.clone());
+                        match (key.code, name) {
+                            (KeyCode::Up, _) => { *cursor = cursor.saturating_sub(1); }
+                            (KeyCode::Down, _) => { *cursor = (*cursor + 1).min(items.len().saturating_sub(1)); }
+                            (KeyCode::Char('l'), Some(name)) => {
+                                run_and_return(&mut terminal, shell_command(&shell_env, &format!("docker logs --tail 200 {}", name)), Pause::Always);
+                            }
+                            (KeyCode::Enter, Some(name)) => {
+                                let mut c = Command::new("docker");
+                                c.args(["exec", "-it", name.as_str(), "sh"]);
+                                run_shell_session(&mut terminal, c);
+                            }
+                            (KeyCode::Char('t'), Some(name)) | (KeyCode::Char('r'), Some(name)) => {
+                                let verb = if key.code == KeyCode::Char('t') { "stop" } else { "restart" };
+                                run_and_return(&mut terminal, shell_command(&shell_env, &format!("docker {} {}", verb, name)), Pause::Always);
+                                *items = docker_ps();
+                                *cursor = (*cursor).min(items.len().saturating_sub(1));
+                            }
+                            (KeyCode::Char('a'), Some(name)) => {
+                                ui_mode = UiMode::Adding { step: 1, kind: AliasKind::Command, name: format!("logs {}", name), command: format!("docker logs -f {}", name), keybind: None, ask_command: true, error: None };
+                            }
+                            (KeyCode::Esc, _) => { ui_mode = UiMode::Main; }
+                            _ => {}
+                        }
+                    }
+                    UiMode::SshHosts { items, cursor } => {
                         match key.code {
+                            KeyCode::Up => { *cursor = if *cursor == 0 { items.len()-1 } else { *cursor-1 }; }
+                            KeyCode::Down => { *cursor = (*cursor+1) % items.len(); }
+                            KeyCode::Enter => {
+                                let mut ssh = Command::new("ssh");
+                                ssh.arg(&items[*cursor]);
+                                ui_mode = UiMode::Main;
+                                run_shell_session(&mut terminal, ssh);
+                            }
+                            KeyCode::Char('a') => {
+                                let host = items[*cursor].clone();
+                                ui_mode = UiMode::Adding { step: 1, kind: AliasKind::Command, name: format!("ssh {}", host), command: format!("ssh {}", host), keybind: None, ask_command: true, error: None };
+                            }
                             KeyCode::Esc => { ui_mode = UiMode::Main; }
+                            _ => {}
+                        }
+                    }
+                    UiMode::ConfigChanged { cfg: new_cfg, lines, scroll } => {
+                        let max = lines.len().saturating_sub(1) as u16;
+                        match key.code {
+                            KeyCode::Up => { *scroll = scroll.saturating_sub(1); }
+                            KeyCode::Down => { *scroll = (*scroll + 1).min(max); }
+                            KeyCode::PageUp => { *scroll = scroll.saturating_sub(10); }
+                            KeyCode::PageDown => { *scroll = (*scroll + 10).min(max); }
                             KeyCode::Enter => {
-                                if let Some(a) = aliases.get_mut(*index) { a.command = command.clone(); }
-                                write_config(&cfg_path, &aliases, &default_shell);
+                                shell_env.reload(&cfg, new_cfg);
+                                let old_dir = profile_cwd(&cfg);
+                                cfg = std::mem::take(&mut **new_cfg);
+                                path_var = shell_env.path_var();
+                                replace_user_aliases(&mut aliases, &cfg, &path_var);
+                                ui_mode = UiMode::Main;
+                                if profile_cwd(&cfg) != old_dir {
+                                    match enter_profile_dir(old_dir, start_dir.as_deref(), &mut aliases, &mut cfg, &path_var) {
+                                        Ok(moved) => { if moved && cfg.header.iter().any(|w| w == "branch") { header.branch = git_branch(); } }
+                                        Err(e) => { ui_mode = UiMode::Message(e); }
+                                    }
+                                }
+                                alias_state.select(if aliases.is_empty() { None } else { Some(0) });
+                            }
+                            // what's on disk gets overwritten with what we have
+                            KeyCode::Esc => {
+                                write_config(&cfg_path, &aliases, &cfg);
                                 ui_mode = UiMode::Main;
                             }
-                            KeyCode::Backspace => { command.pop(); }
-                            KeyCode::Char(c) => { command.push(c); }
                             _ => {}
                         }
                     }
-                    UiMode::RemovingSelect => {
+                    UiMode::TextView { lines, scroll, .. } => {
+                        let max = lines.len().saturating_sub(1) as u16;
                         match key.code {
-                            KeyCode::Up => {
-                                if aliases.is_empty() { ui_mode = UiMode::Main; continue };
-                                let i = alias_state.selected().unwrap_or(0);
-                                let new = if i == 0 { aliases.len()-1 } else { i-1 };
-                                alias_state.select(Some(new));
+                            KeyCode::Up => { *scroll = scroll.saturating_sub(1); }
+                            KeyCode::Down => { *scroll = (*scroll + 1).min(max); }
+                            KeyCode::PageUp => { *scroll = scroll.saturating_sub(10); }
+                            KeyCode::PageDown => { *scroll = (*scroll + 10).min(max); }
+                            KeyCode::Home => { *scroll = 0; }
+                            KeyCode::End => { *scroll = max; }
+                            KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q') => { ui_mode = UiMode::Main; }
+                            _ => {}
+                        }
+                    }
+                    UiMode::Stats => {
+                        if matches!(key.code, KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q')) { ui_mode = UiMode::Main; }
+                    }
+                    UiMode::RunHistory { cursor, failures_only } => {
+                        let shown = history_shown(&state.history, *failures_only);
+                        match key.code {
+                            KeyCode::Up => { *cursor = cursor.saturating_sub(1); }
+                            KeyCode::Down => { *cursor = (*cursor + 1).min(shown.len().saturating_sub(1)); }
+                            KeyCode::PageUp => { *cursor = cursor.saturating_sub(10); }
+                            KeyCode::PageDown => { *cursor = (*cursor + 10).min(shown.len().saturating_sub(1)); }
+                            KeyCode::Char('f') => { *failures_only = !*failures_only; *cursor = 0; }
+                            KeyCode::Enter => {
+                                if let Some(r) = shown.get(*cursor) {
+                                    run_request = Some(rerun_alias(r, &aliases));
+                                    ui_mode = UiMode::Main;
+                                }
                             }
-                            KeyCode::Down => {
-                                if !aliases.is_empty() {
-                                    let i = alias_state.selected().unwrap_or(0);
-                                    alias_state.select(Some((i+1) % aliases.len()));
+                            KeyCode::Esc | KeyCode::Char('q') => { ui_mode = UiMode::Main; }
+                            _ => {}
+                        }
+                    }
+                    UiMode::BuildPick { all, filter, cursor } => {
+                        let shown: Vec<String> = all.iter().filter(|p| p.contains(filter.as_str())).cloned().collect();
+                        match key.code {
+                            KeyCode::Up => { *cursor = cursor.saturating_sub(1); }
+                            KeyCode::Down => { *cursor = (*cursor + 1).min(shown.len().saturating_sub(1)); }
+                            KeyCode::Enter => {
+                                if let Some(program) = shown.get(*cursor) {
+                                    ui_mode = UiMode::ReadingHelp { program: program.clone(), rx: read_help(program) };
+                                }
+                            }
+                            KeyCode::Backspace => { filter.pop(); *cursor = 0; }
+                            KeyCode::Char(c) => { filter.push(c); *cursor = 0; }
+                            KeyCode::Esc => { ui_mode = UiMode::Main; }
+                            _ => {}
+                        }
+                    }
+                    UiMode::BuildFlags { program, options, values, args, cursor, editing } => {
+                        // what typed keys go into while editing: the arguments row, or the option's value
+                        let field = if *cursor == options.len() { Some(&mut *args) } else { values[*cursor].as_mut() };
+                        match (key.code, field) {
+                            (KeyCode::Enter | KeyCode::Esc, _) if *editing => { *editing = false; }
+                            (KeyCode::Backspace, Some(text)) if *editing => { text.pop(); }
+                            (KeyCode::Char(c), Some(text)) if *editing => { text.push(c); }
+                            (KeyCode::Up, _) => { *cursor = cursor.saturating_sub(1); }
+                            (KeyCode::Down, _) => { *cursor = (*cursor + 1).min(options.len()); }
+                            (KeyCode::Char(' '), _) if *cursor < options.len() => {
+                                let on = values[*cursor].is_none();
+                                values[*cursor] = on.then(String::new);
+                                // straight to typing the value when it takes one
+                                *editing = on && options[*cursor].value.is_some();
+                            }
+                            (KeyCode::Char('e') | KeyCode::Char(' '), _) => {
+                                if *cursor < options.len() && options[*cursor].value.is_some() && values[*cursor].is_none() {
+                                    values[*cursor] = Some(String::new());
+                                }
+                                *editing = *cursor == options.len() || options[*cursor].value.is_some();
+                            }
+                            (KeyCode::Enter, _) => {
+                                let command = built_command(program, options, values, args);
+                                ui_mode = UiMode::Adding { step: 1, kind: AliasKind::Command, name: program.clone(), command, keybind: None, ask_command: true, error: None };
+                            }
+                            (KeyCode::Esc, _) => {
+                                let mut all = path_executables("", &path_var);
+                                all.sort();
+                                all.dedup();
+                                ui_mode = UiMode::BuildPick { all, filter: program.clone(), cursor: 0 };
+                            }
+                            _ => {}
+                        }
+                    }
+                    UiMode::PickDir { alias, dir, entries, cursor, hidden } => {
+                        let rows = entries.len() + 1;
+                        let mut open = None;
+                        match key.code {
+                            KeyCode::Up => { *cursor = if *cursor == 0 { rows - 1 } else { *cursor - 1 }; }
+                            KeyCode::Down => { *cursor = (*cursor + 1) % rows; }
+                            KeyCode::Enter | KeyCode::Right if *cursor > 0 => {
+                                let name = &entries[*cursor - 1];
+                                open = Some(if name == ".." { dir.parent().map(|p| p.to_path_buf()).unwrap_or_else(|| dir.clone()) } else { dir.join(name) });
+                            }
+                            KeyCode::Backspace | KeyCode::Left => { open = dir.parent().map(|p| p.to_path_buf()); }
+                            KeyCode::Char('~') => { open = dirs::home_dir(); }
+                            KeyCode::Char('.') => {
+                                *hidden = !*hidden;
+                                *entries = dir_entries(dir, *hidden);
+                                *cursor = (*cursor).min(entries.len());
+                            }
+                            KeyCode::Char('x') => {
+                                if let Some(i) = *alias {
+                                    aliases[i].cwd = None;
+                                    write_config(&cfg_path, &aliases, &cfg);
+                                    ui_mode = UiMode::Message(trf("'{}' runs wherever tuish is again", &[&aliases[i].name]));
                                 }
                             }
                             KeyCode::Enter => {
-                                if let Some(idx) = alias_state.selected() {
-                                    aliases.remove(idx);
-                                    write_config(&cfg_path, &aliases, &default_shell);
-                                    // update alias_state selection
-                                    if aliases.is_empty() { alias_state.select(None); } else { alias_state.select(Some(0)); }
-                                    ui_mode = UiMode::Main;
+                                let chosen = dir.clone();
+                                match *alias {
+                                    Some(i) => {
+                                        aliases[i].cwd = Some(chosen.display().to_string());
+                                        write_config(&cfg_path, &aliases, &cfg);
+                                        ui_mode = UiMode::Message(trf("'{}' runs in {}", &[&aliases[i].name, &chosen.display()]));
+                                    }
+                                    None => match enter_dir(&chosen, &mut aliases, &mut cfg, &path_var) {
+                                        Ok(()) => {
+                                            // the branch belongs to wherever we are now too
+                                            alias_state.select(if aliases.is_empty() { None } else { Some(0) });
+                                            if cfg.header.iter().any(|w| w == "branch") { header.branch = git_branch(); }
+                                            ui_mode = UiMode::Main;
+                                        }
+                                        Err(e) => { ui_mode = UiMode::Message(trf("Couldn't change to {}: {}", &[&chosen.display(), &e])); }
+                                    },
                                 }
                             }
                             KeyCode::Esc => { ui_mode = UiMode::Main; }
                             _ => {}
                         }
+                        if let (Some(next), UiMode::PickDir { dir, entries, cursor, hidden, .. }) = (open, &mut ui_mode) {
+                            *entries = dir_entries(&next, *hidden);
+                            *dir = next;
+                            *cursor = 0;
+                        }
+                    }
+                    UiMode::Processes { items, cursor, .. } => {
+                        let picked = items.get(*cursor).map(|p| (p.pid, p.alias.clone()));
+                        match (key.code, picked) {
+                            (KeyCode::Up, _) => { *cursor = cursor.saturating_sub(1); }
+                            (KeyCode::Down, _) => { *cursor = (*cursor + 1).min(items.len().saturating_sub(1)); }
+                            (KeyCode::Enter, Some((_, alias))) => {
+                                if let Some(job) = latest_job(&jobs, &alias) { ui_mode = UiMode::Output { job }; }
+                            }
+                            (KeyCode::Char(c @ ('t' | 'i' | 'h' | 'k')), Some((pid, _))) => {
+                                let signal = match c { 't' => "TERM", 'i' => "INT", 'h' => "HUP", _ => "KILL" };
+                                if let Err(e) = send_signal(pid, signal) { ui_mode = UiMode::Message(e); }
+                            }
+                            (KeyCode::Esc | KeyCode::Char('q'), _) => { ui_mode = UiMode::Main; }
+                            _ => {}
+                        }
+                    }
+                    UiMode::Diff { lines, scroll, .. } => {
+                        let max = lines.len().saturating_sub(1) as u16;
+                        match key.code {
+                            KeyCode::Up => { *scroll = scroll.saturating_sub(1); }
+                            KeyCode::Down => { *scroll = (*scroll + 1).min(max); }
+                            KeyCode::PageUp => { *scroll = scroll.saturating_sub(10); }
+                            KeyCode::PageDown => { *scroll = (*scroll + 10).min(max); }
+                            // jump to the next change
+                            KeyCode::Char('n') => {
+                                let from = *scroll as usize + 1;
+                                if let Some(i) = lines.iter().skip(from).position(|(c, _)| *c != ' ') { *scroll = (from + i) as u16; }
+                            }
+                            KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q') => { ui_mode = UiMode::Main; }
+                            _ => {}
+                        }
                     }
                     UiMode::Message(_) => {
                         // any key dismisses the message
@@ -453,8 +7389,706 @@ fn main() {
                     }
                 }
             }
+            Event::Paste(pasted) => {
+                let text = pasted_line(&pasted);
+                match &mut ui_mode {
+                    UiMode::Adding { step: 1, name, .. } => name.push_str(&text),
+                    UiMode::Adding { step: 2, kind: AliasKind::Command, command, .. } => command.push_str(&pasted_command(&pasted)),
+                    UiMode::Adding { step: 2, command, .. } => command.push_str(&text),
+                    UiMode::Editing { command, .. } | UiMode::InlineEdit { command, .. } | UiMode::CommandBar { input: command } => command.push_str(&pasted_command(&pasted)),
+                    UiMode::ImportShared { input } => input.push_str(&text),
+                    UiMode::SaveOutput { path, .. } => path.push_str(&text),
+                    UiMode::EnvEdit { input, .. } => input.push_str(&text),
+                    UiMode::Renaming { name, .. } => name.push_str(&text),
+                    UiMode::Params { alias, names, values, field, .. } if !alias.params.get(&names[*field]).is_some_and(|p| p.is_choice()) => values[*field].push_str(&text),
+                    UiMode::Locked { input, .. } => input.push_str(&text),
+                    UiMode::Search { query, cursor, .. } => { query.push_str(&text); *cursor = 0; }
+                    _ => {}
+                }
+            }
             Event::Resize(_, _) => { /* simply redraw on next loop */ }
             _ => {}
         }
+
+        // Actions picked from the list or by their key
+        if let Some(action) = action_request {
+            match action {
+                "Add an alias" => { ui_mode = UiMode::Adding { step: 1, kind: AliasKind::Command, name: String::new(), command: String::new(), keybind: None, ask_command: true, error: None }; }
+                "Add a bookmark" => {
+                    let cwd = std::env::current_dir().map(|d| d.to_string_lossy().to_string()).unwrap_or_default();
+                    ui_mode = UiMode::Adding { step: 1, kind: AliasKind::Cd, name: String::new(), command: cwd, keybind: None, ask_command: true, error: None };
+                }
+                "Edit an alias" => { ui_mode = if aliases.is_empty() { UiMode::Main } else { UiMode::EditingSelect }; }
+                "Rename an alias" => { ui_mode = if aliases.is_empty() { UiMode::Main } else { UiMode::RenameSelect }; }
+                "Remove an alias" => {
+                    if aliases.is_empty() {
+                        ui_mode = UiMode::Message(tr("No aliases to remove").to_string());
+                    } else {
+                        ui_mode = UiMode::RemovingSelect;
+                    }
+                }
+                "Import from history" => {
+                    let items = frequent_history_commands(&aliases);
+                    if items.is_empty() {
+                        ui_mode = UiMode::Message(tr("No history to import").to_string());
+                    } else {
+                        let picked = vec![false; items.len()];
+                        ui_mode = UiMode::ImportHistory { items, picked, cursor: 0 };
+                    }
+                }
+                "Import aliases" => {
+                    ui_mode = UiMode::Importing { rx: scan_import_sources(&aliases), done: Vec::new(), items: Vec::new() };
+                }
+                "Project tasks" => {
+                    let items = project_tasks();
+                    if items.is_empty() {
+                        ui_mode = UiMode::Message(tr("No Makefile, justfile, Taskfile, package.json or Cargo.toml found here").to_string());
+                    } else {
+                        let picked = vec![false; items.len()];
+                        ui_mode = UiMode::Tasks { title: "Project tasks (Enter: run, Space: pick, s: save as aliases)", items, picked, cursor: 0 };
+                    }
+                }
+                "tmux sessions" => {
+                    ui_mode = UiMode::Tmux { items: tmux_entries(), cursor: 0 };
+                }
+                "Kubernetes" => {
+                    let items = kube_entries();
+                    ui_mode = if items.is_empty() { UiMode::Message(tr("kubectl returned no contexts").to_string()) } else { UiMode::Kube { items, cursor: 0 } };
+                }
+                "SSH hosts" => {
+                    let items = ssh_hosts();
+                    ui_mode = if items.is_empty() { UiMode::Message(tr("No hosts in ~/.ssh/config").to_string()) } else { UiMode::SshHosts { items, cursor: 0 } };
+                }
+                "Services" => {
+                    ui_mode = UiMode::Services { items: systemd_services(true), cursor: 0, user: true };
+                }
+                "Containers" => {
+                    ui_mode = UiMode::Containers { items: docker_ps(), cursor: 0 };
+                }
+                "Trash" => {
+                    ui_mode = if cfg.trash.is_empty() { UiMode::Message(tr("Trash is empty").to_string()) } else { UiMode::Trash { cursor: 0 } };
+                }
+                "Environment" => { ui_mode = UiMode::EnvPanel { cursor: 0 }; }
+                "Build a command" => {
+                    let mut all = path_executables("", &path_var);
+                    all.sort();
+                    all.dedup();
+                    ui_mode = UiMode::BuildPick { all, filter: String::new(), cursor: 0 };
+                }
+                "Change directory" => {
+                    ui_mode = pick_dir(None, std::env::current_dir().unwrap_or_else(|_| PathBuf::from("/")));
+                }
+                "Stats" => {
+                    ui_mode = if state.history.is_empty() { UiMode::Message(tr("Nothing has been run yet").to_string()) } else { UiMode::Stats };
+                }
+                "Run history" => {
+                    ui_mode = if state.history.is_empty() { UiMode::Message(tr("Nothing has been run yet").to_string()) } else { UiMode::RunHistory { cursor: 0, failures_only: false } };
+                }
+                "Import shared alias" => { ui_mode = UiMode::ImportShared { input: String::new() }; }
+                "Processes" => {
+                    let mut ticks = HashMap::new();
+                    let items = job_processes(&jobs, &mut ticks, Duration::ZERO);
+                    ui_mode = UiMode::Processes { items, cursor: 0, ticks, sampled: Instant::now() };
+                }
+                "Sync config" => {
+                    let remote = cfg.sync_remote.clone().unwrap_or_default();
+                    let _ = backup_config(&cfg_path, None, true);
+                    ui_mode = match sync_config(&cfg_path, &remote) {
+                        // the conflicts come with the files and what to do about them
+                        Err(lines) if lines.len() > 1 => UiMode::TextView { title: tr("Sync failed").to_string(), lines, scroll: 0 },
+                        Err(lines) => UiMode::Message(trf("Sync failed: {}", &[&lines.join(" ")])),
+                        Ok((mut lines, pulled)) => {
+                            // what came in replaces our copy, or the next save would undo it
+                            if pulled {
+                                match reload_config(&cfg_path, &cfg) {
+                                    Ok(new_cfg) => {
+                                        shell_env.reload(&cfg, &new_cfg);
+                                        let old_dir = profile_cwd(&cfg);
+                                        cfg = new_cfg;
+                                        path_var = shell_env.path_var();
+                                        replace_user_aliases(&mut aliases, &cfg, &path_var);
+                                        lines.push(tr("Reloaded the config").to_string());
+                                        if profile_cwd(&cfg) != old_dir {
+                                            match enter_profile_dir(old_dir, start_dir.as_deref(), &mut aliases, &mut cfg, &path_var) {
+                                                Ok(moved) => { if moved && cfg.header.iter().any(|w| w == "branch") { header.branch = git_branch(); } }
+                                                Err(e) => lines.push(e),
+                                            }
+                                        }
+                                        alias_state.select(if aliases.is_empty() { None } else { Some(0) });
+                                    }
+                                    Err(e) => lines.push(e),
+                                }
+                            }
+                            UiMode::TextView { title: tr("Sync").to_string(), lines, scroll: 0 }
+                        }
+                    };
+                }
+                "Switch profile" => {
+                    let mut items = vec!["(none)".to_string()];
+                    let mut names: Vec<String> = cfg.profiles.keys().cloned().collect();
+                    names.sort();
+                    items.extend(names);
+                    let cursor = cfg.profile.as_ref().and_then(|p| items.iter().position(|i| i == p)).unwrap_or(0);
+                    ui_mode = UiMode::PickProfile { items, cursor };
+                }
+                "Refresh bundles" => {
+                    let (bundled, errors) = load_bundles(&cfg, &cfg_path, true);
+                    aliases.retain(|a| a.bundle.is_none());
+                    let count = bundled.len();
+                    aliases.extend(bundled);
+                    check_aliases(&mut aliases, &path_var);
+                    if alias_state.selected().is_some_and(|i| i >= aliases.len()) { alias_state.select(aliases.len().checked_sub(1)); }
+                    ui_mode = if errors.is_empty() {
+                        UiMode::Message(trf("{} aliases from {} bundles", &[&count, &cfg.bundles.len()]))
+                    } else {
+                        UiMode::Message(errors.join("; "))
+                    };
+                }
+                "Record session" => {
+                    let mut rec = RECORDING.lock().unwrap();
+                    ui_mode = match rec.take() {
+                        Some(r) => UiMode::Message(trf("Recording saved to {}", &[&r.path.display()])),
+                        None => {
+                            let file = format!("tuish-{}.cast", unix_now());
+                            let path = std::env::current_dir().map(|d| d.join(&file)).unwrap_or_else(|_| PathBuf::from(file));
+                            match Recorder::start(path.clone()) {
+                                Ok(r) => {
+                                    *rec = Some(r);
+                                    UiMode::Message(trf("Recording to {}, pick Record session again to stop", &[&path.display()]))
+                                }
+                                Err(e) => UiMode::Message(trf("Couldn't create {}: {}", &[&path.display(), &e])),
+                            }
+                        }
+                    };
+                }
+                "Go to shell" => {
+                    // leave TUI and spawn user's default shell
+                    run_shell_session(&mut terminal, interactive_shell(&shell_env));
+                }
+                "Quit shell" => {
+                    leave_tui(&mut terminal);
+                    return;
+                }
+                _ => {}
+            }
+        }
+
+        // Every way of triggering an alias (or any other command) ends up here, so they all behave the same
+        if let Some(mut alias) = run_request {
+            'run: {
+                let values = run_values.take().unwrap_or_default();
+                if alias.kind == AliasKind::Command {
+                    let names: Vec<String> = placeholders(&alias.command).into_iter()
+                        .filter(|n| !values.contains_key(n) && !alias.secret_values.contains_key(n)).collect();
+                    if !names.is_empty() {
+                        let (choices, commands): (Vec<Vec<String>>, Vec<Option<Command>>) = names.iter().map(|n| param_choices(&alias, n, &shell_env)).unzip();
+                        ui_mode = if commands.iter().any(Option::is_some) {
+                            UiMode::LoadingChoices { rx: command_choices(choices, commands), alias, names }
+                        } else {
+                            params_form(alias, names, choices, &state)
+                        };
+                        break 'run;
+                    }
+                    // secret values only go into the Command we spawn, the placeholder stays in `command`
+                    let (secret, values): (HashMap<String, String>, HashMap<String, String>) =
+                        values.into_iter().partition(|(n, _)| alias.params.get(n).is_some_and(|p| p.secret));
+                    alias.secret_values.extend(secret);
+                    alias.command = fill_placeholders(&alias.command, &values, param_quote(&alias, &shell_env));
+                    let missing = missing_secrets(&alias.command);
+                    if !missing.is_empty() {
+                        ui_mode = UiMode::Message(trf("No '{}' in the keyring, add it with: tuish secret set {}", &[&missing[0], &missing[0]]));
+                        break 'run;
+                    }
+                    let unset = missing_profile_vars(&alias.command, &shell_env);
+                    if !unset.is_empty() {
+                        ui_mode = UiMode::Message(match &cfg.profile {
+                            Some(p) => trf("Profile '{}' has no {}, switch profiles with P", &[p, &unset[0]]),
+                            None => trf("'{}' needs {} from a profile, pick one with P", &[&alias.name, &unset[0]]),
+                        });
+                        break 'run;
+                    }
+                }
+                let asks_first = alias.kind == AliasKind::Command && !run_confirmed && is_dangerous(&alias.command, &cfg.danger_patterns);
+                // in MRU order what just ran goes to the top, with the cursor on it like a buffer switcher
+                if mru && !asks_first && let Some(i) = aliases.iter().position(|a| a.name == alias.name) {
+                    let ran = aliases.remove(i);
+                    aliases.insert(0, ran);
+                    alias_state.select(Some(0));
+                }
+                if alias.kind == AliasKind::Command && !asks_first {
+                    record(&format!("$ {}\n", alias.command));
+                }
+                match alias.kind {
+                    // keybinds included, a dangerous command never runs without asking first
+                    AliasKind::Command if asks_first => {
+                        ui_mode = UiMode::ConfirmRun { alias };
+                    }
+                    AliasKind::Command if alias.run_in == RunIn::Pane => {
+                        let job = start_job(&mut jobs, &alias, alias_invocation(&alias, &shell_env), OutputLimits::from_config(&cfg));
+                        // the split view already shows it, stay on the list to line up the next one
+                        if !split_view { ui_mode = UiMode::Output { job }; }
+                    }
+                    AliasKind::Command if alias.run_in == RunIn::Window => {
+                        // the window is on its own from here, so there's no exit code for the history
+                        let spawned = in_new_window(cfg.terminal.as_deref(), &alias_invocation(&alias, &shell_env))
+                            .and_then(|mut c| c.spawn().map_err(|e| trf("Couldn't open a terminal window: {}", &[&e])));
+                        match spawned {
+                            Err(e) => { ui_mode = UiMode::Message(e); }
+                            Ok(_) if After::for_alias(&alias) == After::Quit => { leave_tui(&mut terminal); return; }
+                            Ok(mut child) => { std::thread::spawn(move || child.wait()); }
+                        }
+                    }
+                    AliasKind::Command => {
+                        // Run alias, returning to the menu after a key press
+                        set_title(&format!("tuish — {}", alias.name));
+                        let (exit, took) = run_and_return(&mut terminal, alias_invocation(&alias, &shell_env), Pause::for_alias(&alias, &cfg.pause));
+                        // the loop puts the title back next time round
+                        shown_title.clear();
+                        state.add_run(RunRecord { name: alias.name.clone(), command: alias.command.clone(), exit, secs: took.as_secs_f64(), at: unix_now() });
+                        write_state(&state_file, &state);
+                        match After::for_alias(&alias) {
+                            After::Quit => { leave_tui(&mut terminal); return; }
+                            After::Rerun => { ui_mode = UiMode::Rerun { alias, exit }; }
+                            _ => {}
+                        }
+                    }
+                    AliasKind::Cd => match bookmark_dir(&alias.command) {
+                        Ok(dir) => {
+                            if let Some(file) = std::env::var_os(CD_FILE_VAR) {
+                                // the wrapper from `tuish init` cds there once we exit
+                                fs::write(file, dir.to_string_lossy().as_bytes()).ok();
+                                leave_tui(&mut terminal);
+                                return;
+                            }
+                            // no wrapper around us, so the best we can do is a shell in that directory
+                            let mut shell = interactive_shell(&shell_env);
+                            shell.current_dir(&dir);
+                            run_shell_session(&mut terminal, shell);
+                        }
+                        Err(msg) => { ui_mode = UiMode::Message(msg); }
+                    },
+                }
+                // the command may well have switched branches
+                if header.branch.is_some() { header.branch = git_branch(); }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scheduler_skips_bundle_and_repo_aliases() {
+        let mine = Alias { name: "uptime".to_string(), command: "uptime".to_string(), every: Some("5m".to_string()), ..Default::default() };
+        let patterns = default_danger_patterns();
+        assert_eq!(schedule_interval(&mine, &patterns), Some(Duration::from_secs(300)));
+        let bundled = Alias { bundle: Some("team".to_string()), ..mine.clone() };
+        assert_eq!(schedule_interval(&bundled, &patterns), None);
+        let repo = Alias { repo: true, ..mine.clone() };
+        assert_eq!(schedule_interval(&repo, &patterns), None);
+    }
+
+    #[test]
+    fn scheduler_only_runs_what_needs_nobody_there() {
+        let patterns = default_danger_patterns();
+        let every = |command: &str| Alias { name: "job".to_string(), command: command.to_string(), every: Some("1h".to_string()), ..Default::default() };
+        assert_eq!(schedule_interval(&every("df -h"), &patterns), Some(Duration::from_secs(3600)));
+        // it would have to ask for the host, or for confirmation
+        assert_eq!(schedule_interval(&every("ssh {{host}} uptime"), &patterns), None);
+        assert_eq!(schedule_interval(&every("rm -rf /tmp/cache"), &patterns), None);
+        // template variables and secrets don't need anyone
+        assert!(schedule_interval(&every("echo {{branch}} {{secret:token}}"), &patterns).is_some());
+        assert_eq!(schedule_interval(&Alias { every: Some("soon".to_string()), ..every("df -h") }, &patterns), None);
+        assert_eq!(schedule_interval(&Alias { every: None, ..every("df -h") }, &patterns), None);
+    }
+
+    #[test]
+    fn placeholders_leave_out_templates_secrets_and_profile_vars() {
+        let command = "deploy {{target}} {{branch}} {{secret:token}} {{env:REGION}} {{ target }} {{kube_context}} {{tag}}";
+        assert_eq!(placeholders(command), ["target", "tag"]);
+    }
+
+    #[test]
+    fn profiles_fill_env_placeholders_and_the_environment() {
+        let profile = Profile {
+            vars: HashMap::from([("REGION".to_string(), "eu-west-1".to_string())]),
+            shell: Some("/bin/bash".to_string()),
+            env: HashMap::from([("AWS_PROFILE".to_string(), "prod".to_string())]),
+            ..Default::default()
+        };
+        let mut cfg = ConfigFile {
+            default_shell: "/bin/sh".to_string(), login_shell: false, snapshot_env: false,
+            profiles: HashMap::from([("prod".to_string(), profile)]), profile: Some("prod".to_string()),
+            ..Default::default()
+        };
+        let env = ShellEnv::from_config(&cfg);
+        assert_eq!(env.shell, "/bin/bash");
+        assert_eq!(env.spawn_env.get("AWS_PROFILE").map(String::as_str), Some("prod"));
+        assert!(missing_profile_vars("aws --region {{env:REGION}} {{env:ACCOUNT}}", &env) == ["ACCOUNT"]);
+        let (command, _) = expand_command("aws --region {{env:REGION}}", &env, &HashMap::new(), sh_quote, |_| None);
+        assert_eq!(command, "aws --region eu-west-1");
+        // no profile: back to the default shell and nothing extra
+        cfg.profile = None;
+        let env = ShellEnv::from_config(&cfg);
+        assert_eq!(env.shell, "/bin/sh");
+        assert!(!env.spawn_env.contains_key("AWS_PROFILE"));
+        assert_eq!(missing_profile_vars("{{env:REGION}}", &env), ["REGION"]);
+    }
+
+    #[test]
+    fn rc_files_and_history_turn_into_imports() {
+        let mine = vec![Alias { name: "gs".to_string(), command: "git status".to_string(), ..Default::default() }];
+        let rc = "# aliases\nalias gs='git status'\nalias ll=\"ls -l\"\nalias gl 'git log --oneline'\nexport EDITOR=vi\nalias bare=htop\n";
+        let found: Vec<(String, String)> = rc_file_aliases(rc, ".bashrc", &mine).into_iter().map(|t| (t.name, t.command)).collect();
+        assert_eq!(found, [("ll".to_string(), "ls -l".to_string()), ("gl".to_string(), "git log --oneline".to_string()), ("bare".to_string(), "htop".to_string())]);
+        assert_eq!(parse_history_line(": 1700000000:0;docker compose up -d").as_deref(), Some("docker compose up -d"));
+        assert_eq!(parse_history_line("#1700000000"), None);
+        assert_eq!(parse_history_line("make \\"), None);
+        assert_eq!(suggest_alias_name("git push origin main", &mine), "git push");
+        assert_eq!(suggest_alias_name("--help", &mine), "imported");
+        let taken = vec![Alias { name: "git push".to_string(), ..Default::default() }];
+        assert_eq!(suggest_alias_name("git push -f", &taken), "git push 2");
+    }
+
+    #[test]
+    fn merged_imports_go_by_source_without_repeats() {
+        let task = |source: &'static str, command: &str| TaskEntry { source, name: command.to_string(), command: command.to_string(), ..Default::default() };
+        let merged = merge_imports(vec![task("make", "make test"), task("history", "make test"), task("rc", "ls -l"), task("cargo", "cargo build"), task("history", "ls -l")]);
+        let shown: Vec<(&str, &str)> = merged.iter().map(|t| (t.source, t.command.as_str())).collect();
+        assert_eq!(shown, [("rc", "ls -l"), ("history", "make test"), ("cargo", "cargo build")]);
+    }
+
+    #[test]
+    fn just_recipe_headers_stop_at_the_rule_colon() {
+        assert_eq!(just_recipe_header("build:"), Some("build"));
+        assert_eq!(just_recipe_header("deploy env='a:b' +args: build"), Some("deploy env='a:b' +args"));
+        assert_eq!(just_recipe_header("version := \"1.0\""), None);
+        assert_eq!(just_recipe_header("no colon here"), None);
+    }
+
+    #[test]
+    fn encrypted_data_round_trips() {
+        let sealed = encrypt_with(b"{\"aliases\": {}}", "hunter2").unwrap();
+        assert_eq!(decrypt_with(&sealed, "hunter2").unwrap(), b"{\"aliases\": {}}");
+        assert!(decrypt_with(&sealed, "wrong").is_err());
+    }
+
+    #[test]
+    fn parameter_values_are_quoted_and_not_expanded_again() {
+        let values = HashMap::from([("msg".to_string(), "x; rm -rf ~ {{secret:token}} it's".to_string())]);
+        let filled = fill_placeholders("git commit -m {{msg}} {{branch}}", &values, sh_quote);
+        assert_eq!(filled, "git commit -m 'x; rm -rf ~ {''{secret:token}} it'\\''s' {{branch}}");
+        assert!(!braced_names(&filled).iter().any(|n| n.starts_with(SECRET_PREFIX)));
+        let out = Command::new("sh").arg("-c").arg(format!("printf %s {}", filled.trim_start_matches("git commit -m ").trim_end_matches(" {{branch}}"))).output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&out.stdout), "x; rm -rf ~ {{secret:token}} it's");
+        // a valid git branch name, filled in as one quoted argument like the typed value
+        let env = ShellEnv::from_config(&ConfigFile { default_shell: "/bin/sh".to_string(), login_shell: false, snapshot_env: false, ..Default::default() });
+        let branch = |name: &str| (name == "branch").then(|| "$(echo${IFS}pwned)".to_string());
+        let (command, _) = expand_command("printf %s {{branch}}", &env, &HashMap::new(), sh_quote, branch);
+        assert_eq!(command, "printf %s '$(echo${IFS}pwned)'");
+        let out = Command::new("sh").arg("-c").arg(&command).output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&out.stdout), "$(echo${IFS}pwned)");
+        assert_eq!(expand_command("printf %s {{branch}}", &env, &HashMap::new(), fish_quote, branch).0, "printf %s '$(echo${IFS}pwned)'");
+    }
+
+    #[test]
+    fn secrets_go_through_the_environment() {
+        let env = ShellEnv::from_config(&ConfigFile { default_shell: "/bin/sh".to_string(), login_shell: false, snapshot_env: false, ..Default::default() });
+        let value = "p'w $(echo pwned) \"{{branch}}";
+        let alias = Alias {
+            command: "printf %s {{password}}".to_string(),
+            secret_values: HashMap::from([("password".to_string(), value.to_string())]),
+            ..Default::default()
+        };
+        let (command, secrets) = expand_command(&alias.command, &env, &alias.secret_values, sh_quote, template_value);
+        assert_eq!(command, "printf %s \"$TUISH_SECRET_1\"");
+        assert_eq!(secrets, vec![("TUISH_SECRET_1".to_string(), value.to_string())]);
+        let out = alias_invocation(&alias, &env).output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&out.stdout), value);
+    }
+
+    #[test]
+    fn keybinds_clashing_with_aliases_or_groups_are_refused() {
+        let aliases = vec![Alias { name: "build".to_string(), keybind: KeyBind::parse("b"), ..Default::default() }];
+        let groups = HashMap::from([("git".to_string(), "g".to_string())]);
+        let kb = |s: &str| KeyBind::parse(s).unwrap();
+        assert_eq!(keybind_problem(&aliases, &groups, kb("b"), None, usize::MAX).as_deref(), Some("Clashes with 'build' [b]"));
+        // a chord starting with another alias's key clashes too
+        assert!(keybind_problem(&aliases, &groups, kb("b t"), None, usize::MAX).is_some());
+        assert_eq!(keybind_problem(&aliases, &groups, kb("g"), None, usize::MAX).as_deref(), Some("That key opens the 'git' group"));
+        // inside a group its own key is free, and the alias being edited doesn't clash with itself
+        assert!(keybind_problem(&aliases, &groups, kb("g"), Some("git"), usize::MAX).is_none());
+        assert!(keybind_problem(&aliases, &groups, kb("b"), None, 0).is_none());
+    }
+
+    #[test]
+    fn state_survives_turning_encryption_on() {
+        let dir = std::env::temp_dir().join(format!("tuish-state-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("state.json");
+        let mut state = StateFile::default();
+        state.history.push(RunRecord { name: "build".to_string(), command: "make".to_string(), exit: Some(0), secs: 1.5, at: 1 });
+        write_state(&path, &state);
+        *STATE_PASSPHRASE.lock().unwrap() = Some("hunter2".to_string());
+        // no state.age yet, the plain state is what gets loaded and then moved over
+        let loaded = load_state(&path);
+        assert_eq!(loaded.history.len(), 1);
+        write_state(&path, &loaded);
+        finish_saves();
+        assert!(!path.exists() && path.with_extension("age").exists());
+        assert_eq!(load_state(&path).history[0].command, "make");
+        *STATE_PASSPHRASE.lock().unwrap() = None;
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn explain_shows_the_profile_environment() {
+        let profile = Profile { env: HashMap::from([("STAGE".to_string(), "prod".to_string())]), ..Default::default() };
+        let cfg = ConfigFile {
+            default_shell: "/bin/sh".to_string(), login_shell: false, snapshot_env: false,
+            env: HashMap::from([("STAGE".to_string(), "dev".to_string()), ("EDITOR".to_string(), "vi".to_string())]),
+            profiles: HashMap::from([("prod".to_string(), profile)]), profile: Some("prod".to_string()),
+            ..Default::default()
+        };
+        let env = ShellEnv::from_config(&cfg);
+        let lines = explain_alias(&Alias { name: "deploy".to_string(), command: "make deploy".to_string(), ..Default::default() }, &env, &[]);
+        assert!(lines.contains(&"  EDITOR=vi".to_string()));
+        assert!(lines.contains(&"  STAGE=prod  (from the profile)".to_string()));
+        assert!(!lines.iter().any(|l| l.contains("STAGE=dev")));
+    }
+
+    #[test]
+    fn choices_commands_run_in_the_background_and_time_out() {
+        let sh = |script: &str| { let mut c = Command::new("sh"); c.arg("-c").arg(script); c };
+        let started = Instant::now();
+        assert_eq!(output_within(sh("echo partial; exec sleep 5"), Duration::from_millis(200)).as_deref(), Some(&b"partial\n"[..]));
+        assert!(started.elapsed() < Duration::from_secs(2));
+        let rx = command_choices(vec![vec!["fixed".to_string()], vec!["host".to_string()]], vec![Some(sh("printf '* main\\n  dev\\n\\n'")), None]);
+        assert_eq!(rx.recv().unwrap(), vec![vec!["fixed".to_string(), "main".to_string(), "dev".to_string()], vec!["host".to_string()]]);
+    }
+
+    #[test]
+    fn cargo_aliases_come_from_the_alias_table() {
+        let config = "[build]\njobs = 4\n\n[alias]\n# comment\nb = \"build\"\n\"x; rm -rf ~\" = \"run\"\n\n[net]\nretry = 2\n";
+        let names = cargo_aliases(config);
+        assert_eq!(names, ["b", "x; rm -rf ~"]);
+        assert_eq!(format!("cargo {}", quoted_value(&names[1], sh_quote)), "cargo 'x; rm -rf ~'");
+    }
+
+    #[test]
+    fn container_aliases_get_their_cwd_from_docker() {
+        let env = ShellEnv::from_config(&ConfigFile { default_shell: "/bin/sh".to_string(), login_shell: false, snapshot_env: false, ..Default::default() });
+        let alias = Alias { command: "make".to_string(), container: Some("dev".to_string()), cwd: Some("/srv/app".to_string()), run_in: RunIn::Pane, ..Default::default() };
+        let c = alias_invocation(&alias, &env);
+        let args: Vec<String> = c.get_args().map(|a| a.to_string_lossy().to_string()).collect();
+        assert_eq!(args, ["exec", "-i", "-w", "/srv/app", "dev", "sh", "-c", "make"]);
+        assert!(c.get_current_dir().is_none());
+        assert_eq!(resolved_command(&alias), "docker exec -it -w '/srv/app' dev sh -c 'make'");
+    }
+
+    #[test]
+    fn fuzzy_score_prefers_boundaries_and_runs() {
+        assert_eq!(fuzzy_score("", "anything", false), Some(0));
+        assert_eq!(fuzzy_score("xyz", "git push", false), None);
+        assert_eq!(fuzzy_score("pg", "git push", false), None);
+        assert!(fuzzy_score("GP", "git push", false).is_some());
+        assert_eq!(fuzzy_score("GP", "git push", true), None);
+        // "gp" on the starts of words beats it somewhere in the middle
+        assert!(fuzzy_score("gp", "git push", false) > fuzzy_score("gp", "logpath", false));
+        // consecutive beats spread out
+        assert!(fuzzy_score("push", "git push", false) > fuzzy_score("push", "p u s h", false));
+    }
+
+    #[test]
+    fn required_literal_keeps_only_what_every_match_has() {
+        assert_eq!(required_literal("git push"), "git push");
+        assert_eq!(required_literal("docker (ps|logs)"), "");
+        assert_eq!(required_literal("(?i)deploy"), "");
+        assert_eq!(required_literal("kubectl [a-z]+ pods"), "kubectl ");
+        assert_eq!(required_literal("colou?r"), "colo");
+        assert_eq!(required_literal(r"ssh\.example"), "example");
+    }
+
+    #[test]
+    fn required_literal_never_drops_a_match() {
+        // a pattern and something it matches, the literal has to be in there
+        let cases = [
+            ("a{100}", "a".repeat(100)),
+            ("ab{2}c", "abbc".to_string()),
+            ("x{2,}yz", "xxxyz".to_string()),
+            (r"\p{Greek}+ word", "αβγ word".to_string()),
+            (r"\x{41}BC", "ABC".to_string()),
+            (r"\u{1F600} smile", "😀 smile".to_string()),
+            (r"log\{1\}", "log{1}".to_string()),
+            ("id-[0-9]{4} done", "id-2024 done".to_string()),
+            ("[]abc]x", "]x".to_string()),
+            ("[^]x]yz", "ayz".to_string()),
+            ("v[[:digit:]]+ rc", "v2 rc".to_string()),
+            ("[a-z&&[^aeiou]]end", "bend".to_string()),
+            (r"[\]q]tail", "qtail".to_string()),
+        ];
+        for (pattern, text) in cases {
+            let literal = required_literal(pattern);
+            assert!(text.to_lowercase().contains(&literal.to_lowercase()), "{} gave {:?}", pattern, literal);
+        }
+    }
+
+    #[test]
+    fn parse_interval_units() {
+        assert_eq!(parse_interval("90s"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_interval(" 15m "), Some(Duration::from_secs(900)));
+        assert_eq!(parse_interval("2h"), Some(Duration::from_secs(7200)));
+        assert_eq!(parse_interval("1d"), Some(Duration::from_secs(86400)));
+        assert_eq!(parse_interval("0m"), None);
+        assert_eq!(parse_interval("5"), None);
+        assert_eq!(parse_interval("m"), None);
+        assert_eq!(parse_interval("5w"), None);
+        assert_eq!(parse_interval(""), None);
+        // parses as a number, but doesn't fit in seconds
+        assert_eq!(parse_interval(&format!("{}d", u64::MAX / 1000)), None);
+        assert_eq!(parse_interval(&format!("{}s", u64::MAX)), Some(Duration::from_secs(u64::MAX)));
+    }
+
+    #[test]
+    fn diff_lines_marks_changes() {
+        let lines = |s: &str| s.split(' ').map(String::from).collect::<Vec<_>>();
+        let diff = diff_lines(&lines("a b c d"), &lines("a x c d e"));
+        let shown: Vec<String> = diff.iter().map(|(m, l)| format!("{}{}", m, l)).collect();
+        assert_eq!(shown, [" a", "-b", "+x", " c", " d", "+e"]);
+        assert!(diff_lines(&lines("same"), &lines("same")).iter().all(|(m, _)| *m == ' '));
+        assert_eq!(diff_lines(&[], &lines("new")), [('+', "new".to_string())]);
+    }
+
+    #[test]
+    fn alias_changes_lists_each_kind() {
+        let entry = |json: &str| serde_json::from_str::<AliasEntry>(json).unwrap();
+        let old = HashMap::from([
+            ("gone".to_string(), entry(r#"{"command": "ls", "keybind": null}"#)),
+            ("edited".to_string(), entry(r#"{"command": "git pull", "keybind": null}"#)),
+            ("rebound".to_string(), entry(r#"{"command": "top", "keybind": null}"#)),
+            ("same".to_string(), entry(r#"{"command": "df -h", "keybind": "d"}"#)),
+        ]);
+        let new = HashMap::from([
+            ("edited".to_string(), entry(r#"{"command": "git pull --rebase", "keybind": null}"#)),
+            ("rebound".to_string(), entry(r#"{"command": "top", "keybind": "t"}"#)),
+            ("same".to_string(), entry(r#"{"command": "df -h", "keybind": "d"}"#)),
+            ("added".to_string(), entry(r#"{"command": "uptime", "keybind": null}"#)),
+        ]);
+        assert_eq!(alias_changes(&old, &new), [
+            "+ added: uptime",
+            "~ edited: git pull → git pull --rebase",
+            "- gone",
+            "~ rebound: settings changed",
+        ]);
+    }
+
+    #[test]
+    fn job_output_keeps_the_newest_lines_and_spills_the_rest() {
+        let alias = Alias { name: "count".to_string(), ..Default::default() };
+        let mut command = Command::new("sh");
+        command.args(["-c", "seq 1 5000"]);
+        let mut job = Job::start(&alias, command, OutputLimits { lines: 10, bytes: 1_000_000, spill: true });
+        let started = Instant::now();
+        while !job.poll() && started.elapsed() < Duration::from_secs(10) { std::thread::sleep(Duration::from_millis(10)); }
+        assert_eq!(job.lines.len(), 10);
+        assert_eq!(job.lines.back().map(String::as_str), Some("5000"));
+        assert_eq!((job.received, job.dropped), (5000, 4990));
+        let spilled = job.spill.as_ref().map(|(path, _)| path.clone()).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&spilled).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        assert_eq!(String::from_utf8(job.all_output()).unwrap().lines().count(), 5000);
+        drop(job);
+        assert!(!spilled.exists());
+    }
+
+    #[test]
+    fn pasted_commands_keep_their_lines() {
+        let script = "if [ -f x ]; then\r\n  cat x # show it\r\nfi\n\n";
+        assert_eq!(pasted_command(script), "if [ -f x ]; then\n  cat x # show it\nfi");
+        assert_eq!(pasted_line(" my\n alias \n"), "my alias");
+    }
+
+    #[test]
+    fn up_down_only_recall_into_an_empty_field() {
+        let used = vec!["prod-1".to_string(), "prod-2".to_string()];
+        assert!(param_picks(None, "", &used));
+        assert!(param_picks(None, "prod-2", &used));
+        assert!(!param_picks(None, "prod-", &used));
+        assert!(!param_picks(None, "", &[]));
+        let choice = ParamSpec { choices: used.clone(), ..Default::default() };
+        assert!(param_picks(Some(&choice), "anything", &used));
+    }
+
+    #[test]
+    fn shown_job_looks_behind_the_lock_screen() {
+        let mut mode = UiMode::Locked { previous: Box::new(UiMode::Output { job: 3 }), input: String::new(), wrong: false };
+        if let Some(job) = shown_job(&mut mode) { *job = 1; }
+        assert!(matches!(mode, UiMode::Locked { previous, .. } if matches!(*previous, UiMode::Output { job: 1 })));
+    }
+
+    #[test]
+    fn exported_bookmarks_are_quoted() {
+        let bookmark = |dir: &str| Alias { name: "proj".to_string(), command: dir.to_string(), kind: AliasKind::Cd, ..Default::default() };
+        let posix = export_aliases(&[bookmark("~/my projects/$(x)")], ShellKind::Posix, false);
+        assert!(posix.contains("alias proj='cd ~/'\\''my projects/$(x)'\\'''"), "{}", posix);
+        let fish = export_aliases(&[bookmark("/srv/it's")], ShellKind::Fish, false);
+        assert!(fish.contains("    cd '/srv/it\\'s' $argv"), "{}", fish);
+        assert_eq!(quoted_dir("~", sh_quote), "~");
+        assert_eq!(quoted_dir("~other/x", sh_quote), "'~other/x'");
+    }
+
+    #[test]
+    fn links_are_found_as_output_comes_in() {
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo 'docs at <https://example.com/a>, twice https://example.com/a'"]);
+        let mut job = Job::start(&Alias::default(), command, OutputLimits { lines: 10, bytes: 10_000, spill: false });
+        let started = Instant::now();
+        while !job.poll() && started.elapsed() < Duration::from_secs(10) { std::thread::sleep(Duration::from_millis(10)); }
+        let shown = job.visible(5);
+        let (line, _, links) = shown[0];
+        assert_eq!(links.len(), 2);
+        let (from, to, target) = &links[0];
+        assert_eq!(&line[*from..*to], "https://example.com/a");
+        assert_eq!(target, "https://example.com/a");
+    }
+
+    #[test]
+    fn logged_keys_round_trip() {
+        let keys = [
+            KeyEvent::new(KeyCode::Char('a'), KeyModifiers::NONE),
+            KeyEvent::new(KeyCode::Char('+'), KeyModifiers::NONE),
+            KeyEvent::new(KeyCode::Char(' '), KeyModifiers::NONE),
+            KeyEvent::new(KeyCode::Char('r'), KeyModifiers::CONTROL),
+            KeyEvent::new(KeyCode::Char('x'), KeyModifiers::CONTROL | KeyModifiers::ALT),
+            KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE),
+            KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE),
+            KeyEvent::new(KeyCode::BackTab, KeyModifiers::SHIFT),
+            KeyEvent::new(KeyCode::Up, KeyModifiers::NONE),
+            KeyEvent::new(KeyCode::F(5), KeyModifiers::NONE),
+        ];
+        for key in keys {
+            let logged = logged_key(&key);
+            let back = logged_key_event(&logged).unwrap_or_else(|| panic!("{} didn't parse", logged));
+            assert_eq!((back.code, back.modifiers), (key.code, key.modifiers), "{}", logged);
+        }
+        assert!(logged_key_event("hyper+a").is_none());
+    }
+
+    #[test]
+    fn popups_say_where_the_cursor_is() {
+        let cfg = ConfigFile::default();
+        let profiles = UiMode::PickProfile { items: vec!["(none)".to_string(), "staging".to_string()], cursor: 1 };
+        assert_eq!(popup_status(&profiles, &cfg).as_deref(), Some("2 of 2: staging"));
+        let mut params = HashMap::new();
+        params.insert("token".to_string(), ParamSpec { secret: true, ..Default::default() });
+        let alias = Alias { name: "deploy".to_string(), params, ..Default::default() };
+        let form = UiMode::Params { alias, names: vec!["host".to_string(), "token".to_string()], values: vec!["web1".to_string(), "hunter2".to_string()], choices: vec![Vec::new(), Vec::new()], field: 1, error: None };
+        assert_eq!(popup_status(&form, &cfg).as_deref(), Some("deploy, field 2 of 2: token, 7 characters"));
+        assert!(popup_status(&UiMode::Main, &cfg).is_none());
     }
 }